@@ -0,0 +1,675 @@
+//! REST API распознавания
+//!
+//! `POST /v1/transcriptions` принимает аудио файл (multipart/form-data с полем
+//! `file` или сырое тело запроса) и возвращает JSON с текстом, сегментами,
+//! языком и временем обработки. Распознавание то же, что и в `/ws`:
+//! декодирование, VAD, Whisper и постобработка.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::{
+    body::Bytes,
+    extract::{rejection::QueryRejection, FromRequest, Multipart, Path, Query, Request, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::audio::PcmSpec;
+use crate::auth::{AuthError, Caller};
+use crate::dictionary::{DictionaryStore, Entry, EntryInput};
+use crate::format::{self, Format};
+use crate::formatting::AppContext;
+use crate::jobs::Job;
+use crate::postprocess::{PostProcessContext, StageTiming};
+use crate::snippets::{Snippet, SnippetInput, SnippetStore};
+use crate::state::AppState;
+use crate::styles::{StyleProfile, StyleSettings, StyleStore};
+use crate::store::{Owners, Scope, StoreError};
+use crate::transcript::{Segment, Transcript};
+use crate::whisper::{self, Language, Task, TranscribeError, TranscribeOptions, WHISPER_SAMPLE_RATE};
+
+/// Ошибка API: HTTP статус и сообщение для клиента
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+}
+
+impl From<TranscribeError> for ApiError {
+    fn from(e: TranscribeError) -> Self {
+        let status = match e {
+            TranscribeError::Busy(_) => StatusCode::SERVICE_UNAVAILABLE,
+            TranscribeError::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self::new(status, e.to_string())
+    }
+}
+
+impl From<StoreError> for ApiError {
+    fn from(e: StoreError) -> Self {
+        let status = match e {
+            StoreError::InvalidOwner(_) | StoreError::Invalid(_) => StatusCode::BAD_REQUEST,
+            StoreError::NotFound(_) => StatusCode::NOT_FOUND,
+            StoreError::Full(_) => StatusCode::CONFLICT,
+            StoreError::Io { .. } | StoreError::Corrupted { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self::new(status, e.to_string())
+    }
+}
+
+impl From<AuthError> for ApiError {
+    fn from(e: AuthError) -> Self {
+        let status = match e {
+            AuthError::Missing | AuthError::UnknownKey | AuthError::InvalidToken(_) | AuthError::Expired => {
+                StatusCode::UNAUTHORIZED
+            }
+            AuthError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AuthError::Key { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self::new(status, e.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(serde_json::json!({ "error": self.message }))).into_response()
+    }
+}
+
+/// Параметры запроса (query string или текстовые поля multipart)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TranscriptionParams {
+    #[serde(default)]
+    pub language: Option<Language>,
+    #[serde(default)]
+    pub task: Option<Task>,
+    /// Частота сырого PCM без заголовка
+    #[serde(default)]
+    pub sample_rate: Option<u32>,
+    /// Каналы сырого PCM без заголовка
+    #[serde(default)]
+    pub channels: Option<u16>,
+    /// Формат экспорта вместо JSON ответа: txt, json, srt, vtt, markdown
+    #[serde(default)]
+    pub format: Option<Format>,
+    /// Пользователь: его словарь подсказывает Whisper термины
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Рабочее пространство со своим словарём
+    #[serde(default)]
+    pub workspace: Option<String>,
+    /// Стиль переписывания текста (`formal`, `email`)
+    #[serde(default)]
+    pub style: Option<String>,
+    /// Приложение, в которое вставляется текст: выбирает стиль и
+    /// форматирование по `formatting.rules` и `styles.apps`
+    #[serde(default)]
+    pub app: Option<String>,
+}
+
+/// Параметры, которые могут приходить текстовыми полями multipart формы
+pub trait FormParams {
+    /// Применяет текстовое поле формы; неизвестные поля игнорируются
+    fn set_field(&mut self, name: &str, value: &str) -> Result<(), ApiError>;
+}
+
+impl FormParams for TranscriptionParams {
+    fn set_field(&mut self, name: &str, value: &str) -> Result<(), ApiError> {
+        let invalid = |e: String| ApiError::bad_request(format!("Некорректное поле {}: {}", name, e));
+        match name {
+            "language" => self.language = Some(Language::from_str(value, true).map_err(invalid)?),
+            "task" => self.task = Some(Task::from_str(value, true).map_err(invalid)?),
+            "sample_rate" => self.sample_rate = Some(value.trim().parse().map_err(|e| invalid(format!("{}", e)))?),
+            "channels" => self.channels = Some(value.trim().parse().map_err(|e| invalid(format!("{}", e)))?),
+            "format" => {
+                self.format = Some(Format::from_name(value).ok_or_else(|| invalid(value.to_string()))?);
+            }
+            "user" => self.user = Some(value.trim().to_string()).filter(|v| !v.is_empty()),
+            "workspace" => self.workspace = Some(value.trim().to_string()).filter(|v| !v.is_empty()),
+            "style" => self.style = Some(value.trim().to_string()).filter(|v| !v.is_empty()),
+            "app" => self.app = Some(value.trim().to_string()).filter(|v| !v.is_empty()),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl TranscriptionParams {
+    pub fn owners(&self) -> Owners {
+        Owners {
+            user: self.user.clone(),
+            workspace: self.workspace.clone(),
+        }
+    }
+
+    /// Контекст постобработки: словари и сниппеты `owners`, стиль и
+    /// форматирование для приложения
+    pub fn postprocess_context(&self, state: &AppState, owners: &Owners) -> Result<PostProcessContext, ApiError> {
+        let mut context = state.postprocess_context(owners);
+        let target = AppContext {
+            app: self.app.clone(),
+            ..AppContext::default()
+        };
+        state.apply_target(&mut context, self.style.as_deref(), &target)?;
+        Ok(context)
+    }
+
+    pub fn options(&self) -> TranscribeOptions {
+        TranscribeOptions {
+            language: self.language.unwrap_or_default(),
+            task: self.task.unwrap_or_default(),
+            ..TranscribeOptions::default()
+        }
+    }
+}
+
+/// Загруженный файл с параметрами
+#[derive(Debug)]
+pub struct Upload<P> {
+    pub audio: Bytes,
+    pub params: P,
+}
+
+/// Время этапов обработки (мс)
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Timings {
+    pub decode_ms: u64,
+    pub transcribe_ms: u64,
+    pub postprocess_ms: u64,
+    pub total_ms: u64,
+    /// Время этапов постобработки
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub postprocess_stages: Vec<StageTiming>,
+}
+
+/// Результат распознавания загруженного аудио
+#[derive(Debug, Clone)]
+pub struct TranscriptionResult {
+    /// Текст после постобработки
+    pub text: String,
+    /// Исходный результат Whisper
+    pub transcript: Transcript,
+    /// Длительность аудио (сек)
+    pub duration: f64,
+    pub timings: Timings,
+}
+
+/// Ответ `POST /v1/transcriptions`
+#[derive(Debug, Serialize)]
+pub struct TranscriptionResponse {
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language_probability: Option<f32>,
+    pub duration: f64,
+    pub segments: Vec<Segment>,
+    pub timings: Timings,
+}
+
+impl From<TranscriptionResult> for TranscriptionResponse {
+    fn from(result: TranscriptionResult) -> Self {
+        Self {
+            text: result.text,
+            language: result.transcript.language,
+            language_probability: result.transcript.language_probability,
+            duration: result.duration,
+            segments: result.transcript.segments,
+            timings: result.timings,
+        }
+    }
+}
+
+/// Параметры скачивания результата
+#[derive(Debug, Default, Deserialize)]
+pub struct ExportParams {
+    #[serde(default)]
+    pub format: Option<Format>,
+}
+
+/// `POST /v1/transcriptions`
+///
+/// Без `format` отвечает JSON с таймингами, с `format` - файлом экспорта.
+pub async fn create_transcription(
+    State(state): State<Arc<AppState>>,
+    caller: Caller,
+    query: Result<Query<TranscriptionParams>, QueryRejection>,
+    request: Request,
+) -> Result<Response, ApiError> {
+    let Query(params) = query.map_err(|e| ApiError::bad_request(e.body_text()))?;
+    let upload = read_upload(params, request).await?;
+
+    let spec = PcmSpec::from_client(upload.params.sample_rate, upload.params.channels)
+        .map_err(|e| ApiError::bad_request(e.to_string()))?;
+    let context = upload.params.postprocess_context(&state, &caller.owners(upload.params.owners()))?;
+    let result = transcribe_audio(&state, upload.audio, spec, &upload.params.options(), context).await?;
+
+    Ok(match upload.params.format {
+        Some(format) => export_response(format, &result.transcript, &result.text, "transcript"),
+        None => Json(TranscriptionResponse::from(result)).into_response(),
+    })
+}
+
+/// `POST /v1/jobs`: ставит файл в очередь заданий
+///
+/// Принимает то же, что и `POST /v1/transcriptions`, и сразу отвечает
+/// `202 Accepted` с id задания.
+pub async fn create_job(
+    State(state): State<Arc<AppState>>,
+    caller: Caller,
+    query: Result<Query<TranscriptionParams>, QueryRejection>,
+    request: Request,
+) -> Result<(StatusCode, Json<Job>), ApiError> {
+    let jobs = state
+        .jobs
+        .as_ref()
+        .ok_or_else(|| ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Очередь заданий недоступна"))?;
+
+    let Query(params) = query.map_err(|e| ApiError::bad_request(e.body_text()))?;
+    let upload = read_upload(params, request).await?;
+    if upload.audio.is_empty() {
+        return Err(ApiError::bad_request("Пустой файл"));
+    }
+    let params = upload.params;
+    PcmSpec::from_client(params.sample_rate, params.channels).map_err(|e| ApiError::bad_request(e.to_string()))?;
+
+    let job = jobs
+        .submit(&upload.audio, &params.options(), params.sample_rate, params.channels, &caller.owners(params.owners()))
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// `GET /v1/jobs/{id}`: состояние задания и результат, когда он готов
+///
+/// С `?format=` отдаёт результат выполненного задания файлом экспорта.
+pub async fn get_job(
+    State(state): State<Arc<AppState>>,
+    caller: Caller,
+    Path(id): Path<String>,
+    query: Result<Query<ExportParams>, QueryRejection>,
+) -> Result<Response, ApiError> {
+    let Query(params) = query.map_err(|e| ApiError::bad_request(e.body_text()))?;
+    let job = state
+        .jobs
+        .as_ref()
+        .and_then(|jobs| jobs.get(&id))
+        .filter(|job| caller.can_access(job.user.as_deref()))
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("Задание {} не найдено", id)))?;
+
+    let Some(format) = params.format else {
+        return Ok(Json(job).into_response());
+    };
+    match job.result {
+        Some(ref result) => Ok(export_response(format, &result.transcript, &result.text, &job.id)),
+        None => Err(ApiError::new(
+            StatusCode::CONFLICT,
+            format!("Задание {} ещё не выполнено", job.id),
+        )),
+    }
+}
+
+/// `GET /v1/dictionaries/{scope}/{owner}`: записи словаря
+pub async fn list_dictionary_entries(
+    State(state): State<Arc<AppState>>,
+    caller: Caller,
+    Path((scope, owner)): Path<(Scope, String)>,
+) -> Result<Json<Vec<Entry>>, ApiError> {
+    caller.check_owner(scope, &owner)?;
+    let entries = dictionaries(&state)?.list(scope, &owner)?;
+    Ok(Json(entries.as_ref().clone()))
+}
+
+/// `POST /v1/dictionaries/{scope}/{owner}`: добавляет термин
+pub async fn add_dictionary_entry(
+    State(state): State<Arc<AppState>>,
+    caller: Caller,
+    Path((scope, owner)): Path<(Scope, String)>,
+    Json(input): Json<EntryInput>,
+) -> Result<(StatusCode, Json<Entry>), ApiError> {
+    caller.check_owner(scope, &owner)?;
+    let entry = dictionaries(&state)?.add(scope, &owner, input)?;
+    Ok((StatusCode::CREATED, Json(entry)))
+}
+
+/// `PUT /v1/dictionaries/{scope}/{owner}/{id}`: заменяет термин и варианты
+pub async fn update_dictionary_entry(
+    State(state): State<Arc<AppState>>,
+    caller: Caller,
+    Path((scope, owner, id)): Path<(Scope, String, String)>,
+    Json(input): Json<EntryInput>,
+) -> Result<Json<Entry>, ApiError> {
+    caller.check_owner(scope, &owner)?;
+    Ok(Json(dictionaries(&state)?.update(scope, &owner, &id, input)?))
+}
+
+/// `DELETE /v1/dictionaries/{scope}/{owner}/{id}`
+pub async fn delete_dictionary_entry(
+    State(state): State<Arc<AppState>>,
+    caller: Caller,
+    Path((scope, owner, id)): Path<(Scope, String, String)>,
+) -> Result<StatusCode, ApiError> {
+    caller.check_owner(scope, &owner)?;
+    dictionaries(&state)?.remove(scope, &owner, &id)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn dictionaries(state: &AppState) -> Result<&DictionaryStore, ApiError> {
+    state
+        .dictionaries
+        .as_deref()
+        .ok_or_else(|| ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Словари недоступны"))
+}
+
+/// `GET /v1/snippets/{scope}/{owner}`: сниппеты
+pub async fn list_snippets(
+    State(state): State<Arc<AppState>>,
+    caller: Caller,
+    Path((scope, owner)): Path<(Scope, String)>,
+) -> Result<Json<Vec<Snippet>>, ApiError> {
+    caller.check_owner(scope, &owner)?;
+    let snippets = snippets(&state)?.list(scope, &owner)?;
+    Ok(Json(snippets.as_ref().clone()))
+}
+
+/// `POST /v1/snippets/{scope}/{owner}`: добавляет сниппет
+pub async fn add_snippet(
+    State(state): State<Arc<AppState>>,
+    caller: Caller,
+    Path((scope, owner)): Path<(Scope, String)>,
+    Json(input): Json<SnippetInput>,
+) -> Result<(StatusCode, Json<Snippet>), ApiError> {
+    caller.check_owner(scope, &owner)?;
+    let snippet = snippets(&state)?.add(scope, &owner, input)?;
+    Ok((StatusCode::CREATED, Json(snippet)))
+}
+
+/// `PUT /v1/snippets/{scope}/{owner}/{id}`: заменяет триггер и тело
+pub async fn update_snippet(
+    State(state): State<Arc<AppState>>,
+    caller: Caller,
+    Path((scope, owner, id)): Path<(Scope, String, String)>,
+    Json(input): Json<SnippetInput>,
+) -> Result<Json<Snippet>, ApiError> {
+    caller.check_owner(scope, &owner)?;
+    Ok(Json(snippets(&state)?.update(scope, &owner, &id, input)?))
+}
+
+/// `DELETE /v1/snippets/{scope}/{owner}/{id}`
+pub async fn delete_snippet(
+    State(state): State<Arc<AppState>>,
+    caller: Caller,
+    Path((scope, owner, id)): Path<(Scope, String, String)>,
+) -> Result<StatusCode, ApiError> {
+    caller.check_owner(scope, &owner)?;
+    snippets(&state)?.remove(scope, &owner, &id)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn snippets(state: &AppState) -> Result<&SnippetStore, ApiError> {
+    state
+        .snippets
+        .as_deref()
+        .ok_or_else(|| ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Сниппеты недоступны"))
+}
+
+/// `GET /v1/styles`: профили стилей
+pub async fn list_styles(State(state): State<Arc<AppState>>) -> Result<Json<Vec<StyleProfile>>, ApiError> {
+    Ok(Json(styles(&state)?.list()))
+}
+
+/// `GET /v1/styles/{id}`
+pub async fn get_style(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<StyleProfile>, ApiError> {
+    let style = styles(&state)?.get(&id).ok_or(StoreError::NotFound(id))?;
+    Ok(Json(style.as_ref().clone()))
+}
+
+/// `PUT /v1/styles/{id}`: создаёт или заменяет профиль
+pub async fn put_style(
+    State(state): State<Arc<AppState>>,
+    caller: Caller,
+    Path(id): Path<String>,
+    Json(settings): Json<StyleSettings>,
+) -> Result<(StatusCode, Json<StyleProfile>), ApiError> {
+    caller.check_admin()?;
+    let (style, created) = styles(&state)?.put(&id, settings)?;
+    let status = if created { StatusCode::CREATED } else { StatusCode::OK };
+    Ok((status, Json(style)))
+}
+
+/// `DELETE /v1/styles/{id}`
+pub async fn delete_style(
+    State(state): State<Arc<AppState>>,
+    caller: Caller,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    caller.check_admin()?;
+    styles(&state)?.remove(&id)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn styles(state: &AppState) -> Result<&StyleStore, ApiError> {
+    state
+        .styles
+        .as_deref()
+        .ok_or_else(|| ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Стили недоступны"))
+}
+
+/// Ответ файлом экспорта `<name>.<ext>`
+fn export_response(format: Format, transcript: &Transcript, text: &str, name: &str) -> Response {
+    let disposition = format!("inline; filename=\"{}.{}\"", name, format.extension());
+    let headers = [
+        (header::CONTENT_TYPE, format.content_type().to_string()),
+        (header::CONTENT_DISPOSITION, disposition),
+    ];
+    (headers, format::render(format, transcript, text)).into_response()
+}
+
+/// Читает аудио из multipart формы (поле `file` или `audio`) или из тела запроса
+///
+/// Текстовые поля формы переопределяют параметры из query string.
+pub async fn read_upload<P: FormParams>(mut params: P, request: Request) -> Result<Upload<P>, ApiError> {
+    let is_multipart = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("multipart/form-data"));
+
+    if !is_multipart {
+        let audio = Bytes::from_request(request, &())
+            .await
+            .map_err(|e| ApiError::new(e.status(), e.body_text()))?;
+        return Ok(Upload { audio, params });
+    }
+
+    let mut multipart = Multipart::from_request(request, &())
+        .await
+        .map_err(|e| ApiError::new(e.status(), e.body_text()))?;
+
+    let mut audio = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| ApiError::new(e.status(), e.body_text()))?
+    {
+        let name = field.name().unwrap_or_default().to_string();
+        if name == "file" || name == "audio" {
+            audio = Some(field.bytes().await.map_err(|e| ApiError::new(e.status(), e.body_text()))?);
+        } else {
+            let value = field.text().await.map_err(|e| ApiError::new(e.status(), e.body_text()))?;
+            params.set_field(&name, &value)?;
+        }
+    }
+
+    let audio = audio.ok_or_else(|| ApiError::bad_request("В форме нет поля file с аудио"))?;
+    Ok(Upload { audio, params })
+}
+
+/// Декодирует аудио, распознаёт речь и выполняет постобработку
+///
+/// Словарь из `context` добавляет термины в подсказку Whisper и заменяет
+/// варианты терминов в тексте, сниппеты раскрываются, стиль переписывает текст.
+pub async fn transcribe_audio(
+    state: &AppState,
+    audio: Bytes,
+    spec: PcmSpec,
+    options: &TranscribeOptions,
+    mut context: PostProcessContext,
+) -> Result<TranscriptionResult, ApiError> {
+    let started = Instant::now();
+    let options = &TranscribeOptions {
+        prompt: context.prompt(options.prompt.as_deref()),
+        ..options.clone()
+    };
+
+    let model = state
+        .whisper_model
+        .as_ref()
+        .ok_or_else(|| ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Whisper модель не загружена"))?;
+
+    if audio.is_empty() {
+        return Err(ApiError::bad_request("Пустой файл"));
+    }
+
+    // Декодирование и ресемплинг - CPU работа, выносим из async потока
+    let pcm = tokio::task::spawn_blocking(move || whisper::convert_audio_to_pcm_with_spec(&audio, spec))
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| ApiError::new(StatusCode::UNSUPPORTED_MEDIA_TYPE, e.to_string()))?;
+    let decode_ms = started.elapsed().as_millis() as u64;
+
+    let duration = pcm.len() as f64 / WHISPER_SAMPLE_RATE as f64;
+    if duration > state.limits.max_audio_secs as f64 {
+        return Err(ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "Аудио слишком длинное: {:.0} сек, максимум {} сек",
+                duration, state.limits.max_audio_secs
+            ),
+        ));
+    }
+
+    let transcribe_started = Instant::now();
+    let transcript = model
+        .transcribe_speech(&pcm, &state.vad, options)
+        .await
+        .inspect_err(|e| error!("Ошибка транскрипции: {}", e))?;
+    let transcribe_ms = transcribe_started.elapsed().as_millis() as u64;
+
+    let postprocess_started = Instant::now();
+    context.language = transcript.language.clone();
+    let processed = state.post_process_detailed(transcript.text(), &context).await;
+    let postprocess_ms = postprocess_started.elapsed().as_millis() as u64;
+
+    let timings = Timings {
+        decode_ms,
+        transcribe_ms,
+        postprocess_ms,
+        total_ms: started.elapsed().as_millis() as u64,
+        postprocess_stages: processed.stages,
+    };
+    info!(
+        "HTTP транскрипция: {:.1} сек аудио за {} мс (декодирование {} мс, Whisper {} мс)",
+        duration, timings.total_ms, timings.decode_ms, timings.transcribe_ms
+    );
+
+    Ok(TranscriptionResult {
+        text: processed.text,
+        transcript,
+        duration,
+        timings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    fn multipart_request(parts: &[(&str, &[u8])]) -> Request {
+        let boundary = "alfavoice-boundary";
+        let mut body = Vec::new();
+        for (name, value) in parts {
+            body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+            body.extend_from_slice(
+                format!("Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\r\n", name, name)
+                    .as_bytes(),
+            );
+            body.extend_from_slice(value);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+        Request::builder()
+            .method("POST")
+            .header(header::CONTENT_TYPE, format!("multipart/form-data; boundary={}", boundary))
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_read_multipart_upload() {
+        let request = multipart_request(&[("language", b"auto"), ("file", &[1, 2, 3, 4]), ("task", b"translate")]);
+        let params = TranscriptionParams {
+            language: Some(Language::En),
+            ..TranscriptionParams::default()
+        };
+
+        let upload = read_upload(params, request).await.unwrap();
+        assert_eq!(upload.audio.as_ref(), &[1, 2, 3, 4]);
+        // Поле формы переопределяет query string
+        assert_eq!(upload.params.language, Some(Language::Auto));
+        assert_eq!(upload.params.options().task, Task::Translate);
+
+        let request = multipart_request(&[("format", b"srt"), ("file", b"x")]);
+        let upload = read_upload(TranscriptionParams::default(), request).await.unwrap();
+        assert_eq!(upload.params.format, Some(Format::Srt));
+    }
+
+    #[tokio::test]
+    async fn test_read_upload_errors() {
+        let request = multipart_request(&[("language", b"auto")]);
+        let error = read_upload(TranscriptionParams::default(), request).await.unwrap_err();
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+
+        let request = multipart_request(&[("language", b"de"), ("file", b"x")]);
+        let error = read_upload(TranscriptionParams::default(), request).await.unwrap_err();
+        assert!(error.message.contains("language"), "{}", error.message);
+    }
+
+    #[tokio::test]
+    async fn test_raw_body_without_model() {
+        let request = Request::builder().method("POST").body(Body::from(vec![0u8; 3200])).unwrap();
+        let upload = read_upload(TranscriptionParams::default(), request).await.unwrap();
+        assert_eq!(upload.audio.len(), 3200);
+
+        let state = AppState::new();
+        let options = TranscribeOptions::default();
+        let error = transcribe_audio(&state, upload.audio, PcmSpec::default(), &options, PostProcessContext::default())
+            .await
+            .unwrap_err();
+        assert_eq!(error.status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -0,0 +1,906 @@
+//! Декодирование аудио контейнеров в PCM
+//!
+//! Определяет формат входных данных по сигнатуре (RIFF, EBML, OggS, fLaC,
+//! ID3/MPEG sync) и декодирует их через symphonia в mono f32.
+//! Данные без известной сигнатуры считаются сырым PCM 16-bit little-endian
+//! с параметрами, объявленными клиентом (`PcmSpec`).
+
+use std::collections::VecDeque;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{CodecParameters, Decoder, DecoderOptions, CODEC_TYPE_NULL, CODEC_TYPE_OPUS};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader, Packet};
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+/// Частота дискретизации сырого PCM без заголовка (если клиент не указал иную)
+pub const RAW_PCM_SAMPLE_RATE: u32 = 16000;
+
+/// Допустимый диапазон частот дискретизации, объявляемых клиентом
+const SAMPLE_RATE_RANGE: std::ops::RangeInclusive<u32> = 4000..=192000;
+
+/// Максимальное количество каналов сырого PCM
+const MAX_CHANNELS: u16 = 8;
+
+/// Ошибки декодирования аудио
+#[derive(Error, Debug)]
+pub enum AudioError {
+    #[error("Пустые аудио данные")]
+    Empty,
+
+    #[error("Неподдерживаемый формат аудио: {0}")]
+    UnsupportedFormat(String),
+
+    #[error("Неподдерживаемый кодек: {0}")]
+    UnsupportedCodec(String),
+
+    #[error("В контейнере нет аудио дорожки")]
+    NoAudioTrack,
+
+    #[error("Ошибка декодирования аудио: {0}")]
+    DecodeError(String),
+
+    #[error("Некорректные параметры аудио: {0}")]
+    InvalidSpec(String),
+
+    #[error("Аудио слишком длинное: больше {max_secs} сек")]
+    TooLong { max_secs: u64 },
+
+    #[error("Не удалось прочитать аудио: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Параметры сырого PCM 16-bit, объявленные клиентом
+///
+/// Используются только для данных без заголовка: у контейнеров частота
+/// и количество каналов берутся из самого контейнера.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcmSpec {
+    /// Частота дискретизации в Гц
+    pub sample_rate: u32,
+    /// Количество interleaved каналов
+    pub channels: u16,
+}
+
+impl Default for PcmSpec {
+    fn default() -> Self {
+        Self {
+            sample_rate: RAW_PCM_SAMPLE_RATE,
+            channels: 1,
+        }
+    }
+}
+
+impl PcmSpec {
+    /// Создаёт параметры из необязательных полей запроса клиента
+    pub fn from_client(sample_rate: Option<u32>, channels: Option<u16>) -> Result<Self, AudioError> {
+        let spec = Self {
+            sample_rate: sample_rate.unwrap_or(RAW_PCM_SAMPLE_RATE),
+            channels: channels.unwrap_or(1),
+        };
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    /// Проверяет, что параметры находятся в допустимых пределах
+    pub fn validate(&self) -> Result<(), AudioError> {
+        if !SAMPLE_RATE_RANGE.contains(&self.sample_rate) {
+            return Err(AudioError::InvalidSpec(format!(
+                "частота дискретизации {} Гц вне диапазона {}..={} Гц",
+                self.sample_rate,
+                SAMPLE_RATE_RANGE.start(),
+                SAMPLE_RATE_RANGE.end()
+            )));
+        }
+        if self.channels == 0 || self.channels > MAX_CHANNELS {
+            return Err(AudioError::InvalidSpec(format!(
+                "количество каналов {} вне диапазона 1..={}",
+                self.channels, MAX_CHANNELS
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Формат контейнера, определённый по сигнатуре
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    Wav,
+    WebM,
+    Ogg,
+    Flac,
+    Mp3,
+    /// Сырой PCM 16-bit little-endian без заголовка
+    RawPcm,
+}
+
+impl AudioFormat {
+    /// Расширение файла, используемое как подсказка для symphonia
+    fn extension(self) -> Option<&'static str> {
+        match self {
+            AudioFormat::Wav => Some("wav"),
+            AudioFormat::WebM => Some("webm"),
+            AudioFormat::Ogg => Some("ogg"),
+            AudioFormat::Flac => Some("flac"),
+            AudioFormat::Mp3 => Some("mp3"),
+            AudioFormat::RawPcm => None,
+        }
+    }
+}
+
+/// Декодированное аудио (mono)
+#[derive(Debug, Clone)]
+pub struct DecodedAudio {
+    /// Сэмплы в диапазоне [-1.0, 1.0]
+    pub samples: Vec<f32>,
+    /// Частота дискретизации источника
+    pub sample_rate: u32,
+    /// Количество каналов в источнике (до сведения в mono)
+    pub source_channels: usize,
+    /// Формат контейнера
+    pub format: AudioFormat,
+}
+
+/// Определяет формат аудио по первым байтам
+pub fn detect_format(data: &[u8]) -> AudioFormat {
+    if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WAVE" {
+        AudioFormat::Wav
+    } else if data.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        AudioFormat::WebM
+    } else if data.starts_with(b"OggS") {
+        AudioFormat::Ogg
+    } else if data.starts_with(b"fLaC") {
+        AudioFormat::Flac
+    } else if data.starts_with(b"ID3") || is_mpeg_frame_sync(data) {
+        AudioFormat::Mp3
+    } else {
+        AudioFormat::RawPcm
+    }
+}
+
+/// Проверяет заголовок кадра MPEG Layer III
+///
+/// Одного синхрослова 0xFFE недостаточно: в сыром PCM байты 0xFF встречаются
+/// постоянно, поэтому дополнительно проверяем слой, битрейт и частоту.
+fn is_mpeg_frame_sync(data: &[u8]) -> bool {
+    if data.len() < 4 || data[0] != 0xFF || data[1] & 0xE0 != 0xE0 {
+        return false;
+    }
+
+    let version = (data[1] >> 3) & 0x03;
+    let layer = (data[1] >> 1) & 0x03;
+    let bitrate_index = data[2] >> 4;
+    let sample_rate_index = (data[2] >> 2) & 0x03;
+
+    version != 0x01
+        && layer == 0x01
+        && bitrate_index != 0x00
+        && bitrate_index != 0x0F
+        && sample_rate_index != 0x03
+}
+
+/// Декодирует аудио данные любого поддерживаемого формата в mono f32
+///
+/// `raw_spec` описывает данные без заголовка и игнорируется для контейнеров.
+pub fn decode(data: &[u8], raw_spec: PcmSpec) -> Result<DecodedAudio, AudioError> {
+    if data.is_empty() {
+        return Err(AudioError::Empty);
+    }
+
+    let format = detect_format(data);
+    debug!("Определён формат аудио: {:?} ({} байт)", format, data.len());
+
+    match format {
+        AudioFormat::RawPcm => decode_raw_pcm(data, raw_spec),
+        _ => decode_container(data, format),
+    }
+}
+
+/// Декодирует аудио файл, не читая контейнер в память целиком
+///
+/// Длительность больше `max_secs` - ошибка: у сырого PCM она считается по
+/// размеру файла, у контейнера - по метаданным дорожки до декодирования,
+/// а без метаданных (WebM от MediaRecorder) - по мере декодирования.
+pub fn decode_file(path: &Path, raw_spec: PcmSpec, max_secs: u64) -> Result<DecodedAudio, AudioError> {
+    let mut file = std::fs::File::open(path)?;
+    let mut signature = Vec::with_capacity(16);
+    file.by_ref().take(16).read_to_end(&mut signature)?;
+    if signature.is_empty() {
+        return Err(AudioError::Empty);
+    }
+
+    let format = detect_format(&signature);
+    debug!("Определён формат аудио файла {}: {:?}", path.display(), format);
+
+    if format == AudioFormat::RawPcm {
+        raw_spec.validate()?;
+        let frame_bytes = 2 * raw_spec.channels as u64 * raw_spec.sample_rate as u64;
+        if file.metadata()?.len() > max_secs * frame_bytes {
+            return Err(AudioError::TooLong { max_secs });
+        }
+        return decode_raw_pcm(&std::fs::read(path)?, raw_spec);
+    }
+
+    let source = MediaSourceStream::new(Box::new(std::fs::File::open(path)?), Default::default());
+    decode_track(source, format, Some(max_secs))
+}
+
+/// Интерпретирует данные как interleaved PCM 16-bit little-endian
+fn decode_raw_pcm(data: &[u8], spec: PcmSpec) -> Result<DecodedAudio, AudioError> {
+    spec.validate()?;
+
+    let frame_bytes = 2 * spec.channels as usize;
+    if !data.len().is_multiple_of(frame_bytes) {
+        return Err(AudioError::UnsupportedFormat(format!(
+            "неизвестная сигнатура и длина данных {} не кратна кадру PCM 16-bit ({} байт)",
+            data.len(),
+            frame_bytes
+        )));
+    }
+
+    let interleaved: Vec<f32> = data
+        .chunks_exact(2)
+        .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]) as f32 / 32768.0)
+        .collect();
+
+    let mut samples = Vec::with_capacity(interleaved.len() / spec.channels as usize);
+    downmix_into(&interleaved, spec.channels as usize, &mut samples);
+
+    Ok(DecodedAudio {
+        samples,
+        sample_rate: spec.sample_rate,
+        source_channels: spec.channels as usize,
+        format: AudioFormat::RawPcm,
+    })
+}
+
+/// Демультиплексирует контейнер и декодирует первую аудио дорожку
+fn decode_container(data: &[u8], format: AudioFormat) -> Result<DecodedAudio, AudioError> {
+    let source = MediaSourceStream::new(Box::new(Cursor::new(data.to_vec())), Default::default());
+    decode_track(source, format, None)
+}
+
+/// Декодирует первую аудио дорожку контейнера целиком
+///
+/// Если задан `max_secs`, длительность проверяется по метаданным дорожки
+/// до декодирования, а без метаданных (WebM от MediaRecorder) - по мере
+/// декодирования.
+fn decode_track(
+    source: MediaSourceStream,
+    format: AudioFormat,
+    max_secs: Option<u64>,
+) -> Result<DecodedAudio, AudioError> {
+    let (mut reader, track_id, codec_params) = probe(source, format)?;
+
+    // Потоковые WAV пишут в длину data заглушку 0xFFFFFFFF: их проверяем по декодированному
+    let declared = codec_params.n_frames.zip(codec_params.sample_rate).filter(|_| format != AudioFormat::Wav);
+    if let (Some(max_secs), Some((frames, rate))) = (max_secs, declared) {
+        if frames > max_secs * rate as u64 {
+            return Err(AudioError::TooLong { max_secs });
+        }
+    }
+
+    let mut samples = Vec::new();
+    let mut sample_rate = None;
+    decode_packets(reader.as_mut(), track_id, &codec_params, |chunk, rate| {
+        samples.extend_from_slice(chunk);
+        sample_rate = Some(rate);
+        match max_secs {
+            Some(max_secs) if samples.len() as u64 > max_secs * rate as u64 => {
+                Err(AudioError::TooLong { max_secs })
+            }
+            _ => Ok(()),
+        }
+    })?;
+
+    let sample_rate = sample_rate.or(codec_params.sample_rate).ok_or_else(|| {
+        AudioError::DecodeError("не удалось определить частоту дискретизации".to_string())
+    })?;
+    Ok(DecodedAudio {
+        samples,
+        sample_rate,
+        source_channels: codec_params.channels.map(|c| c.count()).unwrap_or(1),
+        format,
+    })
+}
+
+/// Максимум байт потока, ожидающих декодера
+///
+/// Фрагмент декодируется до прихода следующего, поэтому очередь растёт,
+/// только если декодер не успевает за клиентом.
+const MAX_PENDING_BYTES: usize = 8 * 1024 * 1024;
+
+/// Потоковое декодирование контейнера, приходящего частями
+///
+/// MediaRecorder присылает заголовок WebM (EBML + Tracks) или Ogg (OpusHead)
+/// только в первом фрагменте, а продолжения - это кластеры и страницы без
+/// заголовка. Поэтому демультиплексор и декодер живут весь поток в отдельном
+/// потоке ОС и читают байты из очереди: чтение блокируется, пока не придёт
+/// следующий фрагмент, а прочитанные байты из очереди удаляются.
+pub struct ContainerStream {
+    input: Arc<StreamInput>,
+    events: mpsc::UnboundedReceiver<StreamEvent>,
+    /// Сколько байт передано декодеру за весь поток
+    pushed: u64,
+    sample_rate: Option<u32>,
+}
+
+/// Очередь байт между сессией и потоком декодера
+#[derive(Default)]
+struct StreamInput {
+    state: Mutex<InputState>,
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct InputState {
+    bytes: VecDeque<u8>,
+    /// Поток завершён: после очереди читается конец данных
+    finished: bool,
+}
+
+/// Сообщения потока декодера
+enum StreamEvent {
+    /// Mono сэмплы очередного пакета и их частота
+    Samples(Vec<f32>, u32),
+    /// Декодер прочитал столько байт и ждёт следующий фрагмент
+    Waiting(u64),
+    Failed(AudioError),
+}
+
+/// Источник для symphonia, читающий байты из очереди потока
+struct StreamReader {
+    input: Arc<StreamInput>,
+    events: mpsc::UnboundedSender<StreamEvent>,
+    consumed: u64,
+}
+
+impl StreamInput {
+    fn lock(&self) -> MutexGuard<'_, InputState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn finish(&self) {
+        self.lock().finished = true;
+        self.ready.notify_one();
+    }
+}
+
+impl Read for StreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut state = self.input.lock();
+        while state.bytes.is_empty() && !state.finished {
+            let _ = self.events.send(StreamEvent::Waiting(self.consumed));
+            state = self.input.ready.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+
+        let n = buf.len().min(state.bytes.len());
+        for (dst, src) in buf.iter_mut().zip(state.bytes.drain(..n)) {
+            *dst = src;
+        }
+        self.consumed += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for StreamReader {
+    fn seek(&mut self, _pos: SeekFrom) -> std::io::Result<u64> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "поток аудио не поддерживает перемотку",
+        ))
+    }
+}
+
+impl MediaSource for StreamReader {
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl ContainerStream {
+    /// Запускает поток декодера для контейнера указанного формата
+    pub fn new(format: AudioFormat) -> Self {
+        let input = Arc::new(StreamInput::default());
+        let (sender, events) = mpsc::unbounded_channel();
+        let reader = StreamReader {
+            input: input.clone(),
+            events: sender.clone(),
+            consumed: 0,
+        };
+        std::thread::spawn(move || run_decoder(reader, format, sender));
+
+        Self {
+            input,
+            events,
+            pushed: 0,
+            sample_rate: None,
+        }
+    }
+
+    /// Передаёт декодеру очередной фрагмент потока
+    pub fn push(&mut self, chunk: &[u8]) -> Result<(), AudioError> {
+        let mut state = self.input.lock();
+        if state.bytes.len() + chunk.len() > MAX_PENDING_BYTES {
+            return Err(AudioError::DecodeError(format!(
+                "декодер не успевает за потоком: в очереди больше {} байт",
+                MAX_PENDING_BYTES
+            )));
+        }
+        state.bytes.extend(chunk);
+        drop(state);
+
+        self.pushed += chunk.len() as u64;
+        self.input.ready.notify_one();
+        Ok(())
+    }
+
+    /// Частота дискретизации декодированных сэмплов (известна после первого пакета)
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.sample_rate
+    }
+
+    /// Ждёт, пока декодер разберёт переданные фрагменты, и забирает сэмплы
+    ///
+    /// Пакет, обрезанный границей фрагмента, декодируется со следующим.
+    pub async fn decode_new(&mut self) -> Result<Vec<f32>, AudioError> {
+        self.collect(false).await
+    }
+
+    /// Завершает поток и забирает оставшиеся сэмплы, включая последний пакет
+    pub async fn finish(&mut self) -> Result<Vec<f32>, AudioError> {
+        self.input.finish();
+        self.collect(true).await
+    }
+
+    async fn collect(&mut self, until_closed: bool) -> Result<Vec<f32>, AudioError> {
+        let mut samples = Vec::new();
+        while let Some(event) = self.events.recv().await {
+            match event {
+                StreamEvent::Samples(chunk, rate) => {
+                    self.sample_rate = Some(rate);
+                    samples.extend(chunk);
+                }
+                StreamEvent::Waiting(consumed) if !until_closed && consumed == self.pushed => break,
+                StreamEvent::Waiting(_) => {}
+                StreamEvent::Failed(e) => return Err(e),
+            }
+        }
+        Ok(samples)
+    }
+}
+
+impl Drop for ContainerStream {
+    fn drop(&mut self) {
+        // Декодер дочитает очередь до конца и завершится
+        self.input.finish();
+    }
+}
+
+/// Тело потока декодера: демультиплексирует и декодирует поток до конца
+fn run_decoder(reader: StreamReader, format: AudioFormat, events: mpsc::UnboundedSender<StreamEvent>) {
+    let source = MediaSourceStream::new(Box::new(reader), Default::default());
+    let result = probe(source, format).and_then(|(mut reader, track_id, codec_params)| {
+        decode_packets(reader.as_mut(), track_id, &codec_params, |samples, rate| {
+            events
+                .send(StreamEvent::Samples(samples.to_vec(), rate))
+                .map_err(|_| AudioError::DecodeError("поток аудио закрыт".to_string()))
+        })
+    });
+
+    if let Err(e) = result {
+        debug!("Потоковый декодер остановлен: {}", e);
+        let _ = events.send(StreamEvent::Failed(e));
+    }
+}
+
+/// Открывает контейнер и находит первую аудио дорожку
+fn probe(
+    source: MediaSourceStream,
+    format: AudioFormat,
+) -> Result<(Box<dyn FormatReader>, u32, CodecParameters), AudioError> {
+    let mut hint = Hint::new();
+    if let Some(ext) = format.extension() {
+        hint.with_extension(ext);
+    }
+
+    let probed = symphonia::default::get_probe()
+        .format(&hint, source, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|e| match e {
+            SymphoniaError::Unsupported(msg) => AudioError::UnsupportedFormat(msg.to_string()),
+            other => AudioError::DecodeError(other.to_string()),
+        })?;
+
+    let track = probed
+        .format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or(AudioError::NoAudioTrack)?;
+    let (track_id, codec_params) = (track.id, track.codec_params.clone());
+    Ok((probed.format, track_id, codec_params))
+}
+
+/// Декодирует пакеты дорожки до конца потока
+///
+/// Mono сэмплы каждого пакета передаются в `sink` вместе с частотой,
+/// ошибка `sink` прекращает декодирование.
+fn decode_packets(
+    reader: &mut dyn FormatReader,
+    track_id: u32,
+    codec_params: &CodecParameters,
+    mut sink: impl FnMut(&[f32], u32) -> Result<(), AudioError>,
+) -> Result<(), AudioError> {
+    let mut decoder = PacketDecoder::new(codec_params)?;
+    let mut samples = Vec::new();
+    while let Some(packet) = next_packet(reader)? {
+        if packet.track_id() != track_id {
+            continue;
+        }
+        samples.clear();
+        decoder.decode(&packet, &mut samples)?;
+        if let Some(rate) = decoder.sample_rate().filter(|_| !samples.is_empty()) {
+            sink(&samples, rate)?;
+        }
+    }
+    Ok(())
+}
+
+/// Декодер пакетов одной дорожки, сохраняющий состояние между фрагментами
+enum PacketDecoder {
+    Symphonia {
+        decoder: Box<dyn Decoder>,
+        buffer: Option<SampleBuffer<f32>>,
+        sample_rate: Option<u32>,
+    },
+    #[cfg(feature = "opus")]
+    Opus {
+        decoder: audiopus::coder::Decoder,
+        frame: Vec<f32>,
+        /// Сколько сэмплов начала потока ещё нужно отбросить (pre-skip)
+        skip: usize,
+    },
+}
+
+impl PacketDecoder {
+    fn new(codec_params: &CodecParameters) -> Result<Self, AudioError> {
+        if codec_params.codec == CODEC_TYPE_OPUS {
+            return Self::opus(codec_params);
+        }
+
+        let decoder = symphonia::default::get_codecs()
+            .make(codec_params, &DecoderOptions::default())
+            .map_err(|e| match e {
+                SymphoniaError::Unsupported(_) => {
+                    let name = symphonia::default::get_codecs()
+                        .get_codec(codec_params.codec)
+                        .map(|d| d.short_name.to_string())
+                        .unwrap_or_else(|| codec_params.codec.to_string());
+                    AudioError::UnsupportedCodec(name)
+                }
+                other => AudioError::DecodeError(other.to_string()),
+            })?;
+
+        Ok(PacketDecoder::Symphonia {
+            decoder,
+            buffer: None,
+            sample_rate: codec_params.sample_rate,
+        })
+    }
+
+    /// Декодер Opus через libopus (feature "opus")
+    ///
+    /// symphonia не содержит декодера Opus, поэтому пакеты, извлечённые
+    /// демультиплексором WebM/Ogg, передаются в libopus. Декодер создаётся
+    /// в mono: libopus сам сводит стерео поток.
+    #[cfg(feature = "opus")]
+    fn opus(codec_params: &CodecParameters) -> Result<Self, AudioError> {
+        use audiopus::{Channels, SampleRate};
+
+        // Максимальная длительность кадра Opus - 120 мс
+        const MAX_FRAME_SAMPLES: usize = OPUS_SAMPLE_RATE as usize * 120 / 1000;
+
+        let decoder = audiopus::coder::Decoder::new(SampleRate::Hz48000, Channels::Mono)
+            .map_err(|e| AudioError::DecodeError(format!("не удалось создать декодер Opus: {}", e)))?;
+
+        Ok(PacketDecoder::Opus {
+            decoder,
+            frame: vec![0.0; MAX_FRAME_SAMPLES],
+            skip: opus_pre_skip(codec_params),
+        })
+    }
+
+    #[cfg(not(feature = "opus"))]
+    fn opus(_codec_params: &CodecParameters) -> Result<Self, AudioError> {
+        Err(AudioError::UnsupportedCodec(
+            "opus (сервер собран без feature `opus`)".to_string(),
+        ))
+    }
+
+    fn sample_rate(&self) -> Option<u32> {
+        match self {
+            PacketDecoder::Symphonia { sample_rate, .. } => *sample_rate,
+            #[cfg(feature = "opus")]
+            PacketDecoder::Opus { .. } => Some(OPUS_SAMPLE_RATE),
+        }
+    }
+
+    /// Декодирует пакет и добавляет mono сэмплы в `out`
+    ///
+    /// Повреждённый кадр пропускается, остальные декодируются.
+    fn decode(&mut self, packet: &Packet, out: &mut Vec<f32>) -> Result<(), AudioError> {
+        match self {
+            PacketDecoder::Symphonia {
+                decoder,
+                buffer,
+                sample_rate,
+            } => {
+                let decoded = match decoder.decode(packet) {
+                    Ok(decoded) => decoded,
+                    Err(SymphoniaError::DecodeError(e)) => {
+                        warn!("Пропущен повреждённый аудио кадр: {}", e);
+                        return Ok(());
+                    }
+                    Err(e) => return Err(AudioError::DecodeError(e.to_string())),
+                };
+
+                let spec = *decoded.spec();
+                *sample_rate = Some(spec.rate);
+
+                let channels = spec.channels.count();
+                let too_small = buffer
+                    .as_ref()
+                    .is_none_or(|buf| buf.capacity() < decoded.capacity() * channels);
+                if too_small {
+                    *buffer = Some(SampleBuffer::new(decoded.capacity() as u64, spec));
+                }
+
+                if let Some(buf) = buffer.as_mut() {
+                    buf.copy_interleaved_ref(decoded);
+                    downmix_into(buf.samples(), channels, out);
+                }
+            }
+            #[cfg(feature = "opus")]
+            PacketDecoder::Opus { decoder, frame, skip } => {
+                use audiopus::packet::Packet as OpusPacket;
+                use audiopus::MutSignals;
+
+                if packet.data.is_empty() {
+                    return Ok(());
+                }
+
+                let input = OpusPacket::try_from(&packet.data[..])
+                    .map_err(|e| AudioError::DecodeError(e.to_string()))?;
+                let output = MutSignals::try_from(&mut frame[..])
+                    .map_err(|e| AudioError::DecodeError(e.to_string()))?;
+
+                match decoder.decode_float(Some(input), output, false) {
+                    Ok(decoded) => {
+                        // Первые pre-skip сэмплов - разогрев декодера, а не звук
+                        let skipped = (*skip).min(decoded);
+                        *skip -= skipped;
+                        out.extend_from_slice(&frame[skipped..decoded]);
+                    }
+                    Err(e) => warn!("Пропущен повреждённый Opus кадр: {}", e),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Читает следующий пакет, считая конец потока штатным завершением
+fn next_packet(reader: &mut dyn FormatReader) -> Result<Option<Packet>, AudioError> {
+    match reader.next_packet() {
+        Ok(packet) => Ok(Some(packet)),
+        Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(SymphoniaError::ResetRequired) => Ok(None),
+        Err(e) => Err(AudioError::DecodeError(e.to_string())),
+    }
+}
+
+/// Сводит interleaved сэмплы в mono усреднением каналов
+fn downmix_into(interleaved: &[f32], channels: usize, out: &mut Vec<f32>) {
+    if channels <= 1 {
+        out.extend_from_slice(interleaved);
+        return;
+    }
+
+    let scale = 1.0 / channels as f32;
+    out.extend(
+        interleaved
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().sum::<f32>() * scale),
+    );
+}
+
+/// Opus всегда декодируется в 48kHz
+#[cfg_attr(not(feature = "opus"), allow(dead_code))]
+const OPUS_SAMPLE_RATE: u32 = 48000;
+
+/// Проверяет, что сервер умеет декодировать все основные форматы клиентов
+///
+/// Браузерный MediaRecorder отдаёт WebM/Opus, поэтому сборка без Opus
+/// не может обслуживать клиентов: об этом сообщаем при запуске,
+/// а не ошибкой на каждый запрос.
+pub fn check_codecs() -> Result<(), String> {
+    if cfg!(feature = "opus") {
+        Ok(())
+    } else {
+        Err("сервер собран без feature `opus`: WebM/Opus и Ogg/Opus не декодируются".to_string())
+    }
+}
+
+/// Количество сэмплов 48kHz, которые нужно отбросить в начале потока Opus
+///
+/// Ogg демультиплексор symphonia кладёт pre-skip в `delay`, WebM передаёт
+/// заголовок OpusHead как есть в `extra_data` (pre-skip - u16 LE по смещению 10).
+#[cfg_attr(not(feature = "opus"), allow(dead_code))]
+fn opus_pre_skip(params: &CodecParameters) -> usize {
+    if let Some(delay) = params.delay {
+        return delay as usize;
+    }
+    match params.extra_data.as_deref() {
+        Some(head) if head.len() >= 12 && head.starts_with(b"OpusHead") => {
+            u16::from_le_bytes([head[10], head[11]]) as usize
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Собирает WAV файл PCM 16-bit из interleaved сэмплов
+    fn make_wav(samples: &[i16], sample_rate: u32, channels: u16) -> Vec<u8> {
+        let data_len = (samples.len() * 2) as u32;
+        let block_align = channels * 2;
+        let mut wav = Vec::with_capacity(44 + data_len as usize);
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVE");
+        wav.extend_from_slice(b"fmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&channels.to_le_bytes());
+        wav.extend_from_slice(&sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        wav.extend_from_slice(&block_align.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        for sample in samples {
+            wav.extend_from_slice(&sample.to_le_bytes());
+        }
+        wav
+    }
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(detect_format(&make_wav(&[0; 4], 16000, 1)), AudioFormat::Wav);
+        assert_eq!(detect_format(&[0x1A, 0x45, 0xDF, 0xA3, 0x00]), AudioFormat::WebM);
+        assert_eq!(detect_format(b"OggS\x00\x02"), AudioFormat::Ogg);
+        assert_eq!(detect_format(b"fLaC\x00\x00"), AudioFormat::Flac);
+        assert_eq!(detect_format(b"ID3\x04\x00"), AudioFormat::Mp3);
+        assert_eq!(detect_format(&[0xFF, 0xFB, 0x90, 0x64]), AudioFormat::Mp3);
+        // Тишина с шумом -1 в сыром PCM не должна распознаваться как MP3
+        assert_eq!(detect_format(&[0xFF, 0xFF, 0xFF, 0xFF]), AudioFormat::RawPcm);
+    }
+
+    #[test]
+    fn test_decode_wav_mono() {
+        let wav = make_wav(&[0, 16384, -16384, 32767], 16000, 1);
+        let decoded = decode(&wav, PcmSpec::default()).unwrap();
+
+        assert_eq!(decoded.format, AudioFormat::Wav);
+        assert_eq!(decoded.sample_rate, 16000);
+        assert_eq!(decoded.samples.len(), 4);
+        assert!((decoded.samples[1] - 0.5).abs() < 0.01);
+        assert!((decoded.samples[2] + 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_decode_wav_stereo_downmix() {
+        // Левый канал 0.5, правый -0.5 (и наоборот) - в mono должна получиться тишина
+        let wav = make_wav(&[16384, -16384, -16384, 16384], 44100, 2);
+        let decoded = decode(&wav, PcmSpec::default()).unwrap();
+
+        assert_eq!(decoded.sample_rate, 44100);
+        assert_eq!(decoded.source_channels, 2);
+        assert_eq!(decoded.samples.len(), 2);
+        assert!(decoded.samples.iter().all(|s| s.abs() < 0.01));
+    }
+
+    #[test]
+    fn test_decode_raw_pcm_odd_length() {
+        let result = decode(&[0x01, 0x02, 0x03], PcmSpec::default());
+        assert!(matches!(result, Err(AudioError::UnsupportedFormat(_))));
+    }
+
+    #[test]
+    fn test_decode_raw_pcm_declared_spec() {
+        // Стерео 48kHz: два кадра (0.5, -0.5) и (0.5, 0.5)
+        let data: Vec<u8> = [16384i16, -16384, 16384, 16384]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let spec = PcmSpec::from_client(Some(48000), Some(2)).unwrap();
+        let decoded = decode(&data, spec).unwrap();
+
+        assert_eq!(decoded.sample_rate, 48000);
+        assert_eq!(decoded.samples.len(), 2);
+        assert!(decoded.samples[0].abs() < 0.01);
+        assert!((decoded.samples[1] - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_pcm_spec_validation() {
+        assert!(PcmSpec::from_client(None, None).is_ok());
+        assert!(matches!(
+            PcmSpec::from_client(Some(100), None),
+            Err(AudioError::InvalidSpec(_))
+        ));
+        assert!(matches!(
+            PcmSpec::from_client(Some(16000), Some(0)),
+            Err(AudioError::InvalidSpec(_))
+        ));
+    }
+
+    #[test]
+    fn test_opus_pre_skip() {
+        let mut params = CodecParameters::new();
+        assert_eq!(opus_pre_skip(&params), 0);
+
+        // OpusHead из WebM: version 1, 1 канал, pre-skip 312
+        let mut head = b"OpusHead".to_vec();
+        head.extend_from_slice(&[1, 1]);
+        head.extend_from_slice(&312u16.to_le_bytes());
+        head.extend_from_slice(&48000u32.to_le_bytes());
+        params.with_extra_data(head.into_boxed_slice());
+        assert_eq!(opus_pre_skip(&params), 312);
+
+        // Значение от Ogg демультиплексора имеет приоритет
+        params.with_delay(3840);
+        assert_eq!(opus_pre_skip(&params), 3840);
+    }
+
+    #[test]
+    fn test_decode_file_checks_duration() {
+        let dir = std::env::temp_dir().join(format!("alfavoice-audio-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // 3 секунды WAV и сырого PCM при 8kHz
+        let wav = dir.join("long.wav");
+        std::fs::write(&wav, make_wav(&vec![1000; 24000], 8000, 1)).unwrap();
+        let raw = dir.join("long.pcm");
+        std::fs::write(&raw, vec![1u8; 48000]).unwrap();
+        let spec = PcmSpec::from_client(Some(8000), None).unwrap();
+
+        for path in [&wav, &raw] {
+            assert!(matches!(decode_file(path, spec, 2), Err(AudioError::TooLong { max_secs: 2 })));
+            let decoded = decode_file(path, spec, 3).unwrap();
+            assert_eq!((decoded.samples.len(), decoded.sample_rate), (24000, 8000));
+        }
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_decode_truncated_container() {
+        let result = decode(b"fLaC", PcmSpec::default());
+        assert!(result.is_err());
+    }
+}
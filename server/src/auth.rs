@@ -0,0 +1,475 @@
+//! Аутентификация клиентов: API ключи и JWT
+//!
+//! API ключи задаются в настройках SHA-256 хешами и сопоставлены
+//! пользователю. JWT проверяется локально открытым ключом издателя из PEM
+//! файла: алгоритм определяется ключом (RS256, ES256 или EdDSA), `alg` токена
+//! должен с ним совпадать. Middleware `authenticate` кладёт в запрос `Caller`:
+//! пользователь и рабочее пространство заменяют присланные клиентом `user`
+//! и `workspace`, ограничивают доступ к словарям, сниппетам и заданиям.
+//! Общие для всех профили стилей изменяют только пользователи из `admins`.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::async_trait;
+use axum::extract::{FromRequestParts, Query, Request, State};
+use axum::http::header;
+use axum::http::request::Parts;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use ring::signature::{self, UnparsedPublicKey, VerificationAlgorithm};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::api::ApiError;
+use crate::config::{AuthConfig, JwtConfig};
+use crate::state::AppState;
+use crate::store::{Owners, Scope};
+
+/// Ошибки аутентификации
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("Требуется аутентификация: Authorization: Bearer <токен>")]
+    Missing,
+
+    #[error("Неизвестный API ключ")]
+    UnknownKey,
+
+    #[error("Некорректный токен: {0}")]
+    InvalidToken(String),
+
+    #[error("Срок действия токена истёк")]
+    Expired,
+
+    #[error("Нет доступа: {0}")]
+    Forbidden(String),
+
+    #[error("Не удалось прочитать ключ {path}: {message}")]
+    Key { path: PathBuf, message: String },
+}
+
+/// Аутентифицированный пользователь
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub user: String,
+    pub workspace: Option<String>,
+    /// Пользователь из `auth.admins`
+    pub admin: bool,
+}
+
+/// Пользователь запроса; `None`, если аутентификация отключена
+#[derive(Debug, Clone, Default)]
+pub struct Caller(pub Option<Identity>);
+
+impl Caller {
+    /// Владельцы словарей и сниппетов запроса
+    ///
+    /// С аутентификацией присланные клиентом `user` и `workspace`
+    /// игнорируются: используются пользователь и пространство токена.
+    pub fn owners(&self, requested: Owners) -> Owners {
+        match &self.0 {
+            Some(identity) => Owners {
+                user: Some(identity.user.clone()),
+                workspace: identity.workspace.clone(),
+            },
+            None => requested,
+        }
+    }
+
+    /// Пользователь может изменять только свои записи и записи своего пространства
+    pub fn check_owner(&self, scope: Scope, owner: &str) -> Result<(), AuthError> {
+        let Some(identity) = &self.0 else {
+            return Ok(());
+        };
+        let allowed = match scope {
+            Scope::User => identity.user == owner,
+            Scope::Workspace => identity.workspace.as_deref() == Some(owner),
+        };
+        if allowed {
+            Ok(())
+        } else {
+            Err(AuthError::Forbidden(format!("{} {}", scope.as_str(), owner)))
+        }
+    }
+
+    /// Общие настройки сервера (профили стилей) изменяют только администраторы
+    pub fn check_admin(&self) -> Result<(), AuthError> {
+        match &self.0 {
+            Some(identity) if !identity.admin => Err(AuthError::Forbidden(format!(
+                "пользователь {} не администратор",
+                identity.user
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Задание доступно только создавшему его пользователю
+    pub fn can_access(&self, owner: Option<&str>) -> bool {
+        self.0.as_ref().is_none_or(|identity| owner == Some(identity.user.as_str()))
+    }
+
+    pub fn user(&self) -> Option<&str> {
+        self.0.as_ref().map(|identity| identity.user.as_str())
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Caller {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<Caller>().cloned().unwrap_or_default())
+    }
+}
+
+/// Проверка API ключей и JWT
+pub struct Authenticator {
+    /// Пользователи по SHA-256 ключа
+    api_keys: HashMap<Vec<u8>, Identity>,
+    jwt: Option<JwtVerifier>,
+    admins: Vec<String>,
+}
+
+impl Authenticator {
+    /// Читает ключи из настроек; ошибка, если открытый ключ JWT недоступен
+    pub fn new(config: &AuthConfig) -> Result<Arc<Self>, AuthError> {
+        let api_keys = config
+            .api_keys
+            .iter()
+            .filter_map(|key| {
+                let identity = Identity {
+                    user: key.user.clone(),
+                    workspace: key.workspace.clone(),
+                    admin: config.admins.contains(&key.user),
+                };
+                Some((decode_hex(&key.key_sha256)?, identity))
+            })
+            .collect();
+
+        let jwt = match &config.jwt {
+            Some(jwt) => {
+                let pem = std::fs::read_to_string(&jwt.public_key).map_err(|e| AuthError::Key {
+                    path: jwt.public_key.clone(),
+                    message: e.to_string(),
+                })?;
+                let verifier = JwtVerifier::new(&pem, jwt).map_err(|message| AuthError::Key {
+                    path: jwt.public_key.clone(),
+                    message,
+                })?;
+                Some(verifier)
+            }
+            None => None,
+        };
+
+        Ok(Arc::new(Self {
+            api_keys,
+            jwt,
+            admins: config.admins.clone(),
+        }))
+    }
+
+    /// Пользователь по API ключу или JWT (токен из трёх частей через точку)
+    pub fn verify(&self, token: &str) -> Result<Identity, AuthError> {
+        if token.split('.').count() == 3 {
+            if let Some(jwt) = &self.jwt {
+                let mut identity = jwt.verify(token, chrono::Utc::now().timestamp())?;
+                identity.admin = self.admins.contains(&identity.user);
+                return Ok(identity);
+            }
+        }
+
+        let hash = ring::digest::digest(&ring::digest::SHA256, token.as_bytes());
+        self.api_keys.get(hash.as_ref()).cloned().ok_or(AuthError::UnknownKey)
+    }
+}
+
+/// Middleware: проверяет учётные данные и кладёт `Caller` в запрос
+pub async fn authenticate(State(state): State<Arc<AppState>>, mut request: Request, next: Next) -> Response {
+    let caller = match &state.auth {
+        None => Caller::default(),
+        Some(auth) => {
+            let identity = credentials(&request).ok_or(AuthError::Missing).and_then(|token| auth.verify(&token));
+            match identity {
+                Ok(identity) => {
+                    debug!("{} {}: пользователь {}", request.method(), request.uri().path(), identity.user);
+                    Caller(Some(identity))
+                }
+                Err(e) => {
+                    warn!("{} {}: {}", request.method(), request.uri().path(), e);
+                    return ApiError::from(e).into_response();
+                }
+            }
+        }
+    };
+
+    request.extensions_mut().insert(caller);
+    next.run(request).await
+}
+
+/// Токен из `Authorization: Bearer`, `X-API-Key` или `?access_token=`
+///
+/// Браузерный WebSocket не может задать заголовки, поэтому для `/ws`
+/// токен передаётся в query string.
+fn credentials(request: &Request) -> Option<String> {
+    let headers = request.headers();
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let api_key = headers.get("x-api-key").and_then(|value| value.to_str().ok());
+    if let Some(token) = bearer.or(api_key) {
+        return Some(token.trim().to_string());
+    }
+
+    Query::<HashMap<String, String>>::try_from_uri(request.uri())
+        .ok()
+        .and_then(|Query(mut query)| query.remove("access_token"))
+}
+
+/// Проверка подписи и claims JWT
+struct JwtVerifier {
+    /// `alg` заголовка токена
+    alg: &'static str,
+    algorithm: &'static dyn VerificationAlgorithm,
+    key: Vec<u8>,
+    config: JwtConfig,
+}
+
+impl JwtVerifier {
+    fn new(pem: &str, config: &JwtConfig) -> Result<Self, String> {
+        let der = pem_public_key(pem)?;
+        let (oid, key) = subject_public_key(&der).ok_or("Некорректный SubjectPublicKeyInfo")?;
+
+        /// OID rsaEncryption, id-ecPublicKey, Ed25519 в DER
+        const RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+        const EC: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+        const ED25519: &[u8] = &[0x2b, 0x65, 0x70];
+        let (alg, algorithm): (_, &'static dyn VerificationAlgorithm) = match oid {
+            RSA => ("RS256", &signature::RSA_PKCS1_2048_8192_SHA256),
+            // Ключ P-256: 65 байт несжатой точки
+            EC if key.len() == 65 => ("ES256", &signature::ECDSA_P256_SHA256_FIXED),
+            ED25519 => ("EdDSA", &signature::ED25519),
+            _ => return Err("Поддерживаются ключи RSA, EC P-256 и Ed25519".to_string()),
+        };
+
+        Ok(Self {
+            alg,
+            algorithm,
+            key: key.to_vec(),
+            config: config.clone(),
+        })
+    }
+
+    fn verify(&self, token: &str, now: i64) -> Result<Identity, AuthError> {
+        let invalid = |message: &str| AuthError::InvalidToken(message.to_string());
+        let (signed, signature) = token.rsplit_once('.').ok_or_else(|| invalid("нет подписи"))?;
+        let (header, payload) = signed.split_once('.').ok_or_else(|| invalid("нет claims"))?;
+        let decode = |part: &str| URL_SAFE_NO_PAD.decode(part).map_err(|_| invalid("некорректный base64url"));
+
+        let header: serde_json::Value =
+            serde_json::from_slice(&decode(header)?).map_err(|_| invalid("некорректный заголовок"))?;
+        if header["alg"].as_str() != Some(self.alg) {
+            return Err(AuthError::InvalidToken(format!("ожидается alg {}", self.alg)));
+        }
+        UnparsedPublicKey::new(self.algorithm, &self.key)
+            .verify(signed.as_bytes(), &decode(signature)?)
+            .map_err(|_| invalid("неверная подпись"))?;
+
+        let claims: serde_json::Value =
+            serde_json::from_slice(&decode(payload)?).map_err(|_| invalid("некорректные claims"))?;
+        let leeway = self.config.leeway_secs as i64;
+        let exp = claims["exp"].as_i64().ok_or_else(|| invalid("нет exp"))?;
+        if now > exp + leeway {
+            return Err(AuthError::Expired);
+        }
+        if claims["nbf"].as_i64().is_some_and(|nbf| now + leeway < nbf) {
+            return Err(invalid("токен ещё не действует"));
+        }
+        if let Some(issuer) = &self.config.issuer {
+            if claims["iss"].as_str() != Some(issuer) {
+                return Err(invalid("чужой iss"));
+            }
+        }
+        if let Some(audience) = &self.config.audience {
+            let matches = match &claims["aud"] {
+                serde_json::Value::String(aud) => aud == audience,
+                serde_json::Value::Array(auds) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+                _ => false,
+            };
+            if !matches {
+                return Err(invalid("чужой aud"));
+            }
+        }
+
+        let claim = |name: &str| claims[name].as_str().filter(|value| !value.is_empty()).map(str::to_string);
+        let user = claim(&self.config.user_claim)
+            .ok_or_else(|| AuthError::InvalidToken(format!("нет claim {}", self.config.user_claim)))?;
+        let workspace = self.config.workspace_claim.as_deref().and_then(claim);
+        Ok(Identity {
+            user,
+            workspace,
+            admin: false,
+        })
+    }
+}
+
+/// DER из PEM `PUBLIC KEY`
+fn pem_public_key(pem: &str) -> Result<Vec<u8>, String> {
+    let body = pem
+        .split("-----BEGIN PUBLIC KEY-----")
+        .nth(1)
+        .and_then(|rest| rest.split("-----END PUBLIC KEY-----").next())
+        .ok_or("Нужен PEM с -----BEGIN PUBLIC KEY-----")?;
+    let base64: String = body.chars().filter(|c| !c.is_whitespace()).collect();
+    base64::engine::general_purpose::STANDARD
+        .decode(base64)
+        .map_err(|e| format!("Некорректный PEM: {}", e))
+}
+
+/// OID алгоритма и ключ из SubjectPublicKeyInfo
+///
+/// ```text
+/// SEQUENCE { SEQUENCE { OID, параметры }, BIT STRING { 0, ключ } }
+/// ```
+fn subject_public_key(der: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (info, _) = der_element(der, 0x30)?;
+    let (algorithm, rest) = der_element(info, 0x30)?;
+    let (oid, _) = der_element(algorithm, 0x06)?;
+    let (bits, _) = der_element(rest, 0x03)?;
+    let (&unused_bits, key) = bits.split_first()?;
+    (unused_bits == 0).then_some((oid, key))
+}
+
+/// Содержимое DER элемента с тегом `tag` и остаток
+fn der_element(input: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    let (&actual, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    if actual != tag {
+        return None;
+    }
+    let (length, rest) = match first {
+        0..=0x7f => (first as usize, rest),
+        0x81..=0x82 => {
+            let count = (first & 0x7f) as usize;
+            let bytes = rest.get(..count)?;
+            (bytes.iter().fold(0, |length, &b| length << 8 | b as usize), &rest[count..])
+        }
+        _ => return None,
+    };
+    (rest.len() >= length).then(|| rest.split_at(length))
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ApiKeyConfig;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    /// Пара ключей Ed25519 и PEM открытого ключа
+    fn ed25519() -> (Ed25519KeyPair, String) {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new()).unwrap();
+        let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let mut spki = vec![0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+        spki.extend_from_slice(pair.public_key().as_ref());
+        let pem = format!(
+            "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+            base64::engine::general_purpose::STANDARD.encode(spki)
+        );
+        (pair, pem)
+    }
+
+    fn token(pair: &Ed25519KeyPair, alg: &str, claims: serde_json::Value) -> String {
+        let header = URL_SAFE_NO_PAD.encode(serde_json::json!({ "alg": alg, "typ": "JWT" }).to_string());
+        let signed = format!("{}.{}", header, URL_SAFE_NO_PAD.encode(claims.to_string()));
+        let signature = URL_SAFE_NO_PAD.encode(pair.sign(signed.as_bytes()));
+        format!("{}.{}", signed, signature)
+    }
+
+    #[test]
+    fn test_jwt_verification() {
+        let (pair, pem) = ed25519();
+        let config = JwtConfig {
+            audience: Some("alfavoice".to_string()),
+            workspace_claim: Some("org".to_string()),
+            ..JwtConfig::default()
+        };
+        let verifier = JwtVerifier::new(&pem, &config).unwrap();
+        let now = 1_800_000_000;
+        let claims = |exp: i64, aud: &str| serde_json::json!({ "sub": "alice", "org": "acme", "exp": exp, "aud": [aud] });
+
+        let identity = verifier.verify(&token(&pair, "EdDSA", claims(now + 60, "alfavoice")), now).unwrap();
+        assert_eq!(
+            identity,
+            Identity {
+                user: "alice".to_string(),
+                workspace: Some("acme".to_string()),
+                admin: false,
+            }
+        );
+
+        assert!(matches!(verifier.verify(&token(&pair, "EdDSA", claims(now - 120, "alfavoice")), now), Err(AuthError::Expired)));
+        assert!(verifier.verify(&token(&pair, "EdDSA", claims(now + 60, "other")), now).is_err());
+        assert!(verifier.verify(&token(&pair, "none", claims(now + 60, "alfavoice")), now).is_err());
+
+        // Подпись другим ключом
+        let (other, _) = ed25519();
+        assert!(verifier.verify(&token(&other, "EdDSA", claims(now + 60, "alfavoice")), now).is_err());
+    }
+
+    #[test]
+    fn test_api_keys_and_access() {
+        let hash = ring::digest::digest(&ring::digest::SHA256, b"secret-key");
+        let hex: String = hash.as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+        let config = AuthConfig {
+            enabled: true,
+            api_keys: vec![ApiKeyConfig {
+                key_sha256: hex,
+                user: "bob".to_string(),
+                workspace: None,
+            }],
+            jwt: None,
+            admins: vec!["root".to_string()],
+        };
+        let auth = Authenticator::new(&config).unwrap();
+        assert!(matches!(auth.verify("wrong-key"), Err(AuthError::UnknownKey)));
+
+        let caller = Caller(Some(auth.verify("secret-key").unwrap()));
+        let requested = Owners {
+            user: Some("alice".to_string()),
+            workspace: Some("acme".to_string()),
+        };
+        assert_eq!(
+            caller.owners(requested),
+            Owners {
+                user: Some("bob".to_string()),
+                workspace: None
+            }
+        );
+        assert!(caller.check_owner(Scope::User, "bob").is_ok());
+        assert!(caller.check_owner(Scope::User, "alice").is_err());
+        assert!(caller.check_owner(Scope::Workspace, "acme").is_err());
+        assert!(caller.can_access(Some("bob")) && !caller.can_access(None));
+        assert!(Caller::default().can_access(None));
+
+        // Стили изменяют только администраторы; без аутентификации - все
+        assert!(matches!(caller.check_admin(), Err(AuthError::Forbidden(_))));
+        let admin = Caller(Some(Identity {
+            user: "root".to_string(),
+            workspace: None,
+            admin: true,
+        }));
+        assert!(admin.check_admin().is_ok());
+        assert!(Caller::default().check_admin().is_ok());
+    }
+}
@@ -0,0 +1,143 @@
+//! Аргументы командной строки
+//!
+//! Без подкоманды бинарник запускает сервер (`serve`). Глобальные флаги
+//! переопределяют значения из `alfavoice.toml` и переменных окружения
+//! для любой подкоманды.
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::config::{ConfigOverrides, WhisperPreset};
+use crate::whisper::{Language, Task};
+
+/// AlfaVoice Server - распознавание речи через WebSocket
+#[derive(Debug, Parser)]
+#[command(name = "alfavoice-server", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Файл конфигурации (по умолчанию alfavoice.toml, если существует)
+    #[arg(short, long, global = true, env = "ALFAVOICE_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Адрес сервера, например 0.0.0.0:8081
+    #[arg(long, global = true)]
+    pub bind: Option<SocketAddr>,
+
+    /// Путь к модели Whisper
+    #[arg(long, global = true)]
+    pub whisper_model: Option<String>,
+
+    /// Путь к модели LLM
+    #[arg(long, global = true)]
+    pub llm_model: Option<String>,
+
+    /// Пресет инференса Whisper
+    #[arg(long, global = true, value_enum)]
+    pub preset: Option<WhisperPreset>,
+
+    /// Фильтр логов (формат RUST_LOG)
+    #[arg(long, global = true)]
+    pub log: Option<String>,
+}
+
+/// Подкоманды
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Запустить HTTP/WebSocket сервер (по умолчанию)
+    Serve,
+
+    /// Проверить наличие и загрузку моделей
+    CheckModels,
+
+    /// Распознать аудио файл и вывести текст
+    Transcribe {
+        /// Аудио файл (WAV, WebM, Ogg, FLAC, MP3 или сырой PCM 16kHz)
+        file: PathBuf,
+
+        /// Язык речи
+        #[arg(long, value_enum, default_value_t = Language::Ru)]
+        language: Language,
+
+        /// Задача: распознавание или перевод на английский
+        #[arg(long, value_enum, default_value_t = Task::Transcribe)]
+        task: Task,
+
+        /// Вывести JSON с сегментами, таймкодами слов и языком
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Замерить скорость распознавания на файле
+    Bench {
+        /// Аудио файл для замера
+        file: PathBuf,
+
+        /// Количество прогонов
+        #[arg(long, default_value_t = 3)]
+        iterations: usize,
+
+        /// Одновременных распознаваний в каждом прогоне (нагрузка на пул);
+        /// пул Whisper увеличивается до этого числа
+        #[arg(long, default_value_t = 1)]
+        concurrency: usize,
+    },
+
+    /// Вывести итоговую конфигурацию в формате TOML
+    PrintConfig,
+}
+
+impl Cli {
+    /// Переопределения конфигурации из флагов
+    pub fn overrides(&self) -> ConfigOverrides {
+        ConfigOverrides {
+            bind: self.bind,
+            whisper_model: self.whisper_model.clone(),
+            llm_model: self.llm_model.clone(),
+            preset: self.preset,
+            log: self.log.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_flags() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from([
+            "alfavoice-server",
+            "transcribe",
+            "speech.wav",
+            "--language",
+            "auto",
+            "--config",
+            "prod.toml",
+            "--bind",
+            "0.0.0.0:9000",
+            "--preset",
+            "high-quality",
+        ])
+        .unwrap();
+        let overrides = cli.overrides();
+
+        assert_eq!(cli.config, Some(PathBuf::from("prod.toml")));
+        assert_eq!(overrides.bind.map(|a| a.port()), Some(9000));
+        assert_eq!(overrides.preset, Some(WhisperPreset::HighQuality));
+        assert!(matches!(
+            cli.command,
+            Some(Command::Transcribe { language: Language::Auto, json: false, .. })
+        ));
+
+        // Без подкоманды - запуск сервера
+        let cli = Cli::try_parse_from(["alfavoice-server", "--bind", "127.0.0.1:9001"]).unwrap();
+        assert!(cli.command.is_none());
+    }
+}
@@ -0,0 +1,185 @@
+//! Подкоманды для эксплуатации: проверка моделей, распознавание файла,
+//! замер скорости и вывод конфигурации
+//!
+//! Результат печатается в stdout, логи идут в stderr, поэтому вывод
+//! можно перенаправлять в файл или передавать другой программе.
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+use tracing::info;
+
+use crate::audio;
+use crate::config::AppConfig;
+use crate::llm::LlmModel;
+use crate::vad::Vad;
+use crate::whisper::{self, TranscribeOptions, WhisperModel, WHISPER_SAMPLE_RATE};
+
+/// `print-config`: итоговая конфигурация после всех переопределений
+pub fn print_config(config: &AppConfig) -> Result<(), String> {
+    let text = toml::to_string_pretty(config)
+        .map_err(|e| format!("Не удалось сериализовать конфигурацию: {}", e))?;
+    print!("{}", text);
+    Ok(())
+}
+
+/// `check-models`: наличие файлов и пробная загрузка моделей
+pub fn check_models(config: &AppConfig) -> Result<(), String> {
+    let models = &config.models;
+
+    println!("Whisper: {}", models.whisper_model);
+    models.check_models_exist()?;
+    let started = Instant::now();
+    load_whisper(config, 1)?;
+    println!("  OK, загружена за {} мс", started.elapsed().as_millis());
+
+    println!("Opus: {}", if cfg!(feature = "opus") { "OK (libopus)" } else { "не поддерживается" });
+    audio::check_codecs()?;
+
+    match models.llm_model.as_deref() {
+        None => println!("LLM: не указана (models.llm_model)"),
+        Some(path) => {
+            println!("LLM: {}", path);
+            if !Path::new(path).exists() {
+                println!("  файл не найден, постобработка LLM будет отключена");
+            } else if !cfg!(feature = "llm") {
+                println!("  файл найден, но сервер собран без feature `llm`");
+            } else {
+                let model = LlmModel::new(Some(path), true, config.llm.clone()).map_err(|e| e.to_string())?;
+                if model.is_enabled() {
+                    println!("  OK");
+                } else {
+                    println!("  не удалось загрузить, постобработка LLM будет отключена");
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// `transcribe <file>`: распознавание файла с выводом текста или JSON
+pub async fn transcribe_file(
+    config: &AppConfig,
+    file: &Path,
+    options: &TranscribeOptions,
+    json: bool,
+) -> Result<(), String> {
+    let pcm = read_audio(file)?;
+    let model = load_whisper(config, 1)?;
+    let vad = Vad::new(config.vad.clone());
+
+    let started = Instant::now();
+    let transcript = model
+        .transcribe_speech(&pcm, &vad, options)
+        .await
+        .map_err(|e| e.to_string())?;
+    info!(
+        "Файл {} ({:.1} сек) распознан за {} мс",
+        file.display(),
+        audio_secs(&pcm),
+        started.elapsed().as_millis()
+    );
+
+    if json {
+        let text = serde_json::to_string_pretty(&transcript)
+            .map_err(|e| format!("Не удалось сериализовать результат: {}", e))?;
+        println!("{}", text);
+    } else {
+        println!("{}", transcript.text());
+    }
+
+    Ok(())
+}
+
+/// `bench <file>`: время распознавания и real-time factor
+///
+/// В каждом прогоне запускается `concurrency` одновременных распознаваний,
+/// что позволяет оценить пропускную способность пула состояний Whisper.
+/// Пул увеличивается до `concurrency`, чтобы распознавания не получали
+/// отказ из-за занятого пула.
+pub async fn bench(config: &AppConfig, file: &Path, iterations: usize, concurrency: usize) -> Result<(), String> {
+    let iterations = iterations.max(1);
+    let concurrency = concurrency.max(1);
+
+    let pcm = Arc::new(read_audio(file)?);
+    if pcm.is_empty() {
+        return Err(format!("В файле {} нет аудио", file.display()));
+    }
+    let seconds = audio_secs(&pcm);
+
+    let started = Instant::now();
+    let pool_size = config.whisper.to_config().pool_size.max(concurrency);
+    let model = Arc::new(load_whisper(config, pool_size)?);
+    let vad = Arc::new(Vad::new(config.vad.clone()));
+    println!("Аудио: {} ({:.1} сек)", file.display(), seconds);
+    println!("Загрузка модели: {} мс", started.elapsed().as_millis());
+    println!("Прогонов: {}, одновременно: {}", iterations, concurrency);
+
+    let mut timings = Vec::with_capacity(iterations);
+    for iteration in 1..=iterations {
+        let started = Instant::now();
+
+        let tasks: Vec<_> = (0..concurrency)
+            .map(|_| {
+                let (model, vad, pcm) = (model.clone(), vad.clone(), pcm.clone());
+                tokio::spawn(async move {
+                    model
+                        .transcribe_speech(&pcm, &vad, &TranscribeOptions::default())
+                        .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await
+                .map_err(|e| format!("Ошибка задачи распознавания: {}", e))?
+                .map_err(|e| e.to_string())?;
+        }
+
+        let elapsed = started.elapsed().as_secs_f64();
+        println!(
+            "  #{}: {:.0} мс, RTF {:.3}",
+            iteration,
+            elapsed * 1000.0,
+            elapsed / (seconds * concurrency as f64)
+        );
+        timings.push(elapsed);
+    }
+
+    let avg = timings.iter().sum::<f64>() / timings.len() as f64;
+    let min = timings.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = timings.iter().copied().fold(0.0, f64::max);
+    println!(
+        "Итого: среднее {:.0} мс, мин {:.0} мс, макс {:.0} мс, RTF {:.3}",
+        avg * 1000.0,
+        min * 1000.0,
+        max * 1000.0,
+        avg / (seconds * concurrency as f64)
+    );
+
+    let stats = model.pool_stats();
+    println!(
+        "Пул Whisper: размер {}, среднее ожидание {:.1} мс, отказов {}",
+        stats.size, stats.avg_wait_ms, stats.rejected_total
+    );
+
+    Ok(())
+}
+
+/// Загружает Whisper модель с указанным размером пула
+fn load_whisper(config: &AppConfig, pool_size: usize) -> Result<WhisperModel, String> {
+    let mut whisper_config = config.whisper.to_config();
+    whisper_config.pool_size = pool_size;
+    WhisperModel::load(&config.models.whisper_model, Some(whisper_config))
+}
+
+/// Читает и декодирует аудио файл в PCM 16kHz mono
+fn read_audio(file: &Path) -> Result<Vec<f32>, String> {
+    let bytes = std::fs::read(file).map_err(|e| format!("Не удалось прочитать {}: {}", file.display(), e))?;
+    whisper::convert_audio_to_pcm(&bytes).map_err(|e| format!("Не удалось декодировать {}: {}", file.display(), e))
+}
+
+fn audio_secs(pcm: &[f32]) -> f64 {
+    pcm.len() as f64 / WHISPER_SAMPLE_RATE as f64
+}
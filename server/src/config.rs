@@ -1,3 +1,10 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
 /// Конфигурация параметров инференса для Whisper
 #[derive(Debug, Clone)]
 pub struct WhisperConfig {
@@ -13,8 +20,25 @@ pub struct WhisperConfig {
     
     /// Стратегия сэмплинга
     pub use_beam_search: bool,
+
+    /// Количество состояний Whisper (одновременных распознаваний)
+    /// Каждое состояние занимает память под KV-кэш и использует `n_threads` потоков
+    pub pool_size: usize,
+
+    /// Максимальное ожидание свободного состояния (мс),
+    /// после которого клиент получает ошибку «сервер занят»
+    pub max_queue_wait_ms: u64,
+
+    /// Инференс на GPU (CUDA)
+    pub use_gpu: bool,
 }
 
+/// Размер пула состояний Whisper по умолчанию
+const DEFAULT_POOL_SIZE: usize = 2;
+
+/// Максимальное ожидание в очереди Whisper по умолчанию (мс)
+const DEFAULT_MAX_QUEUE_WAIT_MS: u64 = 30_000;
+
 impl Default for WhisperConfig {
     fn default() -> Self {
         // Определяем оптимальное количество потоков
@@ -29,6 +53,9 @@ impl Default for WhisperConfig {
             n_threads,
             beam_size: 5, // Стандартное значение для баланса точность/скорость
             use_beam_search: false, // По умолчанию используем greedy (быстрее)
+            pool_size: DEFAULT_POOL_SIZE,
+            max_queue_wait_ms: DEFAULT_MAX_QUEUE_WAIT_MS,
+            use_gpu: false,
         }
     }
 }
@@ -40,6 +67,9 @@ impl WhisperConfig {
             n_threads: n_threads.clamp(1, 16),
             beam_size: beam_size.clamp(1, 10),
             use_beam_search,
+            pool_size: DEFAULT_POOL_SIZE,
+            max_queue_wait_ms: DEFAULT_MAX_QUEUE_WAIT_MS,
+            use_gpu: false,
         }
     }
     
@@ -53,6 +83,9 @@ impl WhisperConfig {
             n_threads: (available_threads / 2).clamp(2, 8),
             beam_size: 1,
             use_beam_search: false,
+            pool_size: DEFAULT_POOL_SIZE,
+            max_queue_wait_ms: DEFAULT_MAX_QUEUE_WAIT_MS,
+            use_gpu: false,
         }
     }
     
@@ -66,18 +99,125 @@ impl WhisperConfig {
             n_threads: available_threads.clamp(4, 12),
             beam_size: 10,
             use_beam_search: true,
+            pool_size: DEFAULT_POOL_SIZE,
+            max_queue_wait_ms: DEFAULT_MAX_QUEUE_WAIT_MS,
+            use_gpu: false,
+        }
+    }
+}
+
+/// Конфигурация потоковых сессий распознавания (`start` / аудио / `stop`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamingConfig {
+    /// Сколько нового аудио (мс) должно накопиться между промежуточными результатами
+    pub partial_interval_ms: u64,
+
+    /// Минимальная длительность аудио (мс) для первого промежуточного результата
+    pub min_partial_audio_ms: u64,
+
+    /// Длительность конца высказывания (сек), распознаваемого для промежуточного
+    /// результата: длинное высказывание целиком распознаётся только в финале
+    pub partial_window_secs: u64,
+
+    /// Максимальная длительность буфера сессии (сек)
+    /// При переполнении накопленное аудио фиксируется как финальный результат
+    pub max_buffer_secs: u64,
+
+    /// Длительность паузы после речи (мс), после которой высказывание
+    /// считается завершённым и отправляется финальный результат
+    pub end_of_utterance_ms: u64,
+
+    /// Максимальная длительность аудио одной сессии (сек)
+    /// Дальше фрагменты отклоняются: клиент должен открыть новую сессию
+    pub max_session_secs: u64,
+}
+
+impl Default for StreamingConfig {
+    fn default() -> Self {
+        Self {
+            partial_interval_ms: 1000,
+            min_partial_audio_ms: 500,
+            partial_window_secs: 10,
+            // Whisper обрабатывает окно в 30 секунд
+            max_buffer_secs: 30,
+            end_of_utterance_ms: 800,
+            max_session_secs: 2 * 60 * 60,
         }
     }
 }
 
+/// Конфигурация детектора голосовой активности (VAD)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VadConfig {
+    /// Длительность кадра анализа (мс)
+    pub frame_ms: u64,
+
+    /// Насколько энергия речи должна превышать шумовой фон (дБ)
+    pub energy_margin_db: f32,
+
+    /// Нижняя граница порога энергии (dBFS): тише - всегда тишина
+    pub min_energy_db: f32,
+
+    /// Верхняя граница порога энергии (dBFS): защищает от завышенного
+    /// шумового фона, когда весь буфер состоит из речи
+    pub max_threshold_db: f32,
+
+    /// Максимальная спектральная плоскостность речевого кадра
+    /// 0 - чистый тон, ~0.56 - белый шум
+    pub max_flatness: f32,
+
+    /// Минимальная длительность участка речи (мс), короче - щелчки и шум
+    pub min_speech_ms: u64,
+
+    /// Минимальная пауза (мс), по которой аудио делится на сегменты
+    pub min_silence_ms: u64,
+
+    /// Запас тишины вокруг каждого сегмента (мс)
+    pub padding_ms: u64,
+
+    /// Максимальная длительность сегмента (сек), не больше окна Whisper
+    pub max_segment_secs: u64,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            frame_ms: 20,
+            energy_margin_db: 10.0,
+            min_energy_db: -50.0,
+            max_threshold_db: -30.0,
+            max_flatness: 0.35,
+            min_speech_ms: 200,
+            min_silence_ms: 500,
+            padding_ms: 200,
+            max_segment_secs: 28,
+        }
+    }
+}
+
+impl VadConfig {
+    /// Длина кадра в сэмплах при 16kHz
+    pub fn frame_samples(&self) -> usize {
+        (self.frame_ms * crate::whisper::WHISPER_SAMPLE_RATE as u64 / 1000) as usize
+    }
+}
+
 /// Конфигурация путей к моделям
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct ModelPaths {
     /// Путь к модели Whisper
     pub whisper_model: String,
     
     /// Путь к модели LLM
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub llm_model: Option<String>,
+
+    /// Каталог модели восстановления пунктуации (feature `nlp`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub punctuation_model: Option<String>,
 }
 
 impl Default for ModelPaths {
@@ -85,6 +225,7 @@ impl Default for ModelPaths {
         Self {
             whisper_model: "models/ggml-large-v3-q5_0.bin".to_string(),
             llm_model: Some("models/qwen1_5-0_5b-chat-q4_k_m.gguf".to_string()),
+            punctuation_model: Some("models/punctuation".to_string()),
         }
     }
 }
@@ -95,13 +236,12 @@ impl ModelPaths {
         Self {
             whisper_model,
             llm_model,
+            punctuation_model: None,
         }
     }
     
     /// Проверяет существование файлов моделей
     pub fn check_models_exist(&self) -> Result<(), String> {
-        use std::path::Path;
-        
         // Проверяем Whisper модель
         if !Path::new(&self.whisper_model).exists() {
             return Err(format!(
@@ -132,3 +272,1148 @@ impl ModelPaths {
         Ok(())
     }
 }
+
+/// Ошибки загрузки конфигурации
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Файл конфигурации не найден: {0}")]
+    NotFound(PathBuf),
+
+    #[error("Не удалось прочитать {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Ошибка в {path}: {message}")]
+    Parse { path: PathBuf, message: String },
+
+    #[error("Некорректное значение переменной окружения {var}={value:?}: {message}")]
+    Env {
+        var: &'static str,
+        value: String,
+        message: String,
+    },
+
+    #[error("Некорректная конфигурация:\n  - {}", .0.join("\n  - "))]
+    Invalid(Vec<String>),
+}
+
+/// Конфигурация сервера (`alfavoice.toml`)
+///
+/// Источники применяются по порядку: значения по умолчанию, файл,
+/// переменные окружения, флаги командной строки. Каждый следующий
+/// переопределяет только заданные в нём параметры.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    pub server: ServerConfig,
+    pub models: ModelPaths,
+    pub whisper: WhisperSettings,
+    pub streaming: StreamingConfig,
+    pub vad: VadConfig,
+    pub postprocessing: PostProcessingConfig,
+    pub llm: LlmConfig,
+    pub limits: LimitsConfig,
+    pub jobs: JobsConfig,
+    pub dictionaries: DictionaryConfig,
+    pub snippets: SnippetsConfig,
+    pub styles: StylesConfig,
+    pub voice_commands: VoiceCommandsConfig,
+    pub formatting: FormattingConfig,
+    pub auth: AuthConfig,
+    pub logging: LoggingConfig,
+}
+
+/// Сетевые настройки
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Адрес и порт HTTP/WebSocket сервера
+    pub bind: SocketAddr,
+    /// HTTPS и WSS вместо HTTP и WS
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([127, 0, 0, 1], 8081)),
+            tls: None,
+        }
+    }
+}
+
+/// TLS сервера
+///
+/// Файлы перечитываются при изменении без перезапуска сервера: новые
+/// соединения получают новый сертификат, открытые работают со старым.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM файл сертификата с цепочкой промежуточных
+    pub cert: PathBuf,
+    /// PEM файл закрытого ключа (PKCS#8, PKCS#1 или SEC1)
+    pub key: PathBuf,
+    /// PEM файл корневых сертификатов клиентов: включает mTLS
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ca: Option<PathBuf>,
+    /// С `client_ca`: `false` - пускать и клиентов без сертификата
+    pub require_client_cert: bool,
+    /// Интервал проверки изменения файлов (сек)
+    pub reload_secs: u64,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            cert: PathBuf::new(),
+            key: PathBuf::new(),
+            client_ca: None,
+            require_client_cert: true,
+            reload_secs: 30,
+        }
+    }
+}
+
+/// Пресет параметров инференса Whisper
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum WhisperPreset {
+    /// Баланс скорость/качество (`WhisperConfig::default`)
+    #[default]
+    Default,
+    /// Максимальная скорость (`WhisperConfig::fast`)
+    Fast,
+    /// Максимальное качество (`WhisperConfig::high_quality`)
+    HighQuality,
+}
+
+/// Устройство инференса Whisper
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Device {
+    #[default]
+    Cpu,
+    #[serde(alias = "cuda")]
+    Gpu,
+}
+
+impl std::str::FromStr for Device {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "cpu" => Ok(Device::Cpu),
+            "gpu" | "cuda" => Ok(Device::Gpu),
+            _ => Err("ожидается \"cpu\" или \"gpu\"".to_string()),
+        }
+    }
+}
+
+/// Секция `[whisper]`: пресет и точечные переопределения его параметров
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WhisperSettings {
+    pub preset: WhisperPreset,
+    pub device: Device,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n_threads: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub beam_size: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub use_beam_search: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_queue_wait_ms: Option<u64>,
+}
+
+impl WhisperSettings {
+    /// Итоговые параметры инференса: пресет с переопределениями
+    pub fn to_config(&self) -> WhisperConfig {
+        let mut config = match self.preset {
+            WhisperPreset::Default => WhisperConfig::default(),
+            WhisperPreset::Fast => WhisperConfig::fast(),
+            WhisperPreset::HighQuality => WhisperConfig::high_quality(),
+        };
+
+        if let Some(n_threads) = self.n_threads {
+            config.n_threads = n_threads;
+        }
+        if let Some(beam_size) = self.beam_size {
+            config.beam_size = beam_size;
+        }
+        if let Some(use_beam_search) = self.use_beam_search {
+            config.use_beam_search = use_beam_search;
+        }
+        if let Some(pool_size) = self.pool_size {
+            config.pool_size = pool_size;
+        }
+        if let Some(max_queue_wait_ms) = self.max_queue_wait_ms {
+            config.max_queue_wait_ms = max_queue_wait_ms;
+        }
+        config.use_gpu = self.device == Device::Gpu;
+
+        config
+    }
+}
+
+/// Настройки постобработки текста
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PostProcessingConfig {
+    /// Постобработка LLM (Qwen)
+    pub llm_enabled: bool,
+    /// Постобработка BERT (feature `nlp`)
+    pub bert_enabled: bool,
+    /// Этапы постобработки в порядке применения
+    pub stages: Vec<StageConfig>,
+}
+
+impl Default for PostProcessingConfig {
+    fn default() -> Self {
+        Self {
+            llm_enabled: true,
+            bert_enabled: true,
+            stages: vec![
+                StageConfig::new(StageKind::Normalize),
+                // Словарь и сниппеты ищут фразы до того, как модели расставят
+                // в них знаки и изменят регистр
+                StageConfig::new(StageKind::Dictionary),
+                StageConfig::new(StageKind::Snippets),
+                StageConfig::new(StageKind::Bert),
+                // LLM исправляет текст, только если BERT недоступен или упал
+                StageConfig {
+                    fallback: true,
+                    ..StageConfig::new(StageKind::Llm)
+                },
+                StageConfig::new(StageKind::Style),
+                StageConfig::new(StageKind::Format),
+            ],
+        }
+    }
+}
+
+/// Этап конвейера постобработки
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StageKind {
+    /// Нормализация пробелов
+    Normalize,
+    /// Восстановление пунктуации и регистра моделью BERT (feature `nlp`)
+    #[serde(alias = "punctuation")]
+    Bert,
+    /// Исправление текста LLM (feature `llm`)
+    Llm,
+    /// Замена вариантов из словарей пользователя и рабочего пространства
+    Dictionary,
+    /// Переписывание в стиле профиля (LLM или без неё)
+    Style,
+    /// Пунктуация и регистр по правилам `formatting` для приложения запроса
+    Format,
+    /// Раскрытие голосовых сниппетов по фразам-триггерам
+    Snippets,
+}
+
+/// Что делать, если этап завершился ошибкой или превысил время
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailurePolicy {
+    /// Пропустить этап: следующий получает текст до этого этапа
+    #[default]
+    Skip,
+    /// Остановить конвейер и вернуть текст до этого этапа
+    Stop,
+}
+
+/// Настройки одного этапа постобработки
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StageConfig {
+    pub name: StageKind,
+    #[serde(default)]
+    pub on_error: FailurePolicy,
+    /// Ограничение времени этапа (мс); без него этап не ограничен
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Выполнять этап, только если предыдущий этап не включён в конвейер
+    /// или завершился ошибкой
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fallback: bool,
+}
+
+impl StageConfig {
+    pub fn new(name: StageKind) -> Self {
+        Self {
+            name,
+            on_error: FailurePolicy::Skip,
+            timeout_ms: None,
+            fallback: false,
+        }
+    }
+}
+
+/// Параметры генерации LLM постобработки
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LlmConfig {
+    /// Максимум токенов ответа; более длинный текст не отправляется в LLM
+    pub max_tokens: usize,
+    /// Температура сэмплинга; 0 - жадный выбор (детерминированный результат)
+    pub temperature: f64,
+    /// Порог top-p (nucleus sampling) при температуре больше 0
+    pub top_p: f64,
+    /// Seed генератора случайных чисел сэмплинга
+    pub seed: u64,
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        Self {
+            max_tokens: 512,
+            temperature: 0.0,
+            top_p: 0.9,
+            seed: 42,
+        }
+    }
+}
+
+/// Ограничения на клиентов и размер аудио
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Максимальное количество одновременных WebSocket соединений
+    pub max_connections: usize,
+    /// Максимальный размер одного WebSocket сообщения (байт)
+    pub max_message_bytes: usize,
+    /// Максимальный размер загружаемого через HTTP файла (байт)
+    pub max_upload_bytes: usize,
+    /// Максимальная длительность аудио одиночной транскрипции (сек)
+    pub max_audio_secs: u64,
+    /// Максимум WebSocket соединений одного пользователя (с `auth`)
+    pub max_connections_per_user: Option<usize>,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 100,
+            max_message_bytes: 16 * 1024 * 1024,
+            max_upload_bytes: 100 * 1024 * 1024,
+            max_audio_secs: 600,
+            max_connections_per_user: None,
+        }
+    }
+}
+
+/// Очередь заданий на распознавание длинных записей
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobsConfig {
+    /// Каталог для аудио и результатов заданий
+    pub dir: PathBuf,
+    /// Количество заданий, обрабатываемых одновременно
+    pub workers: usize,
+    /// Максимальная длительность аудио задания (сек)
+    pub max_audio_secs: u64,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("data/jobs"),
+            workers: 1,
+            max_audio_secs: 4 * 60 * 60,
+        }
+    }
+}
+
+/// Персональные словари терминов
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DictionaryConfig {
+    /// Каталог словарей (`user/<id>.json`, `workspace/<id>.json`)
+    pub dir: PathBuf,
+    /// Максимум записей в одном словаре
+    pub max_entries: usize,
+    /// Максимальная длина подсказки Whisper с терминами (символов)
+    pub max_prompt_chars: usize,
+}
+
+impl Default for DictionaryConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("data/dictionaries"),
+            max_entries: 1000,
+            max_prompt_chars: 600,
+        }
+    }
+}
+
+/// Голосовые сниппеты
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnippetsConfig {
+    /// Каталог сниппетов (`user/<id>.json`, `workspace/<id>.json`)
+    pub dir: PathBuf,
+    /// Максимум сниппетов у одного владельца
+    pub max_snippets: usize,
+}
+
+impl Default for SnippetsConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("data/snippets"),
+            max_snippets: 200,
+        }
+    }
+}
+
+/// Профили стилей переписывания
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StylesConfig {
+    /// JSON файл профилей; создаётся со встроенными профилями
+    pub path: PathBuf,
+    /// Стиль, если клиент и приложение его не задали
+    pub default: Option<String>,
+    /// Стиль по id приложения из запроса: `"com.microsoft.Outlook" = "email"`
+    pub apps: BTreeMap<String, String>,
+}
+
+impl Default for StylesConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("data/styles.json"),
+            default: None,
+            apps: BTreeMap::new(),
+        }
+    }
+}
+
+/// Голосовые команды редактирования в потоковых сессиях
+///
+/// Список `commands` задаёт грамматику целиком: если он указан в файле,
+/// встроенные команды не используются. Встроенные фразы состоят из двух и
+/// более слов, а знаки вставляются только с «поставь»/«insert»: отдельные
+/// слова вроде «запятая» или «period» встречаются в обычной диктовке.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VoiceCommandsConfig {
+    pub enabled: bool,
+    pub commands: Vec<VoiceCommandConfig>,
+}
+
+impl Default for VoiceCommandsConfig {
+    fn default() -> Self {
+        let insert = |text: &str| EditOp::Insert { text: text.to_string() };
+        let command = |phrases: &[&str], op: EditOp| VoiceCommandConfig {
+            phrases: phrases.iter().map(|phrase| phrase.to_string()).collect(),
+            op,
+        };
+
+        Self {
+            enabled: true,
+            commands: vec![
+                command(&["новая строка", "с новой строки", "new line"], insert("\n")),
+                command(&["новый абзац", "new paragraph"], insert("\n\n")),
+                command(&["поставь запятую", "insert comma"], insert(",")),
+                command(&["поставь точку", "insert period"], insert(".")),
+                command(&["поставь вопросительный знак", "insert question mark"], insert("?")),
+                command(&["поставь восклицательный знак", "insert exclamation mark"], insert("!")),
+                command(&["поставь двоеточие", "insert colon"], insert(":")),
+                command(&["поставь точку с запятой", "insert semicolon"], insert(";")),
+                command(
+                    &["удали последнее слово", "delete last word"],
+                    EditOp::Delete { unit: TextUnit::Word },
+                ),
+                command(
+                    &["удали последнее предложение", "delete last sentence"],
+                    EditOp::Delete { unit: TextUnit::Sentence },
+                ),
+                command(
+                    &["выдели последнее предложение", "select last sentence"],
+                    EditOp::Select { unit: TextUnit::Sentence },
+                ),
+                command(&["выдели всё", "выдели все", "select all"], EditOp::Select { unit: TextUnit::All }),
+                command(
+                    &["с большой буквы", "capitalize that"],
+                    EditOp::Capitalize { unit: TextUnit::Word },
+                ),
+                command(&["отмени это", "undo that", "scratch that"], EditOp::Undo),
+            ],
+        }
+    }
+}
+
+/// Фразы команды и операция, которую получает клиент
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoiceCommandConfig {
+    pub phrases: Vec<String>,
+    #[serde(flatten)]
+    pub op: EditOp,
+}
+
+/// Операция редактирования текста на клиенте
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum EditOp {
+    /// Вставить текст в позицию курсора
+    Insert { text: String },
+    /// Удалить последнюю единицу текста
+    Delete { unit: TextUnit },
+    /// Выделить последнюю единицу текста
+    Select { unit: TextUnit },
+    /// Сделать первую букву последней единицы текста заглавной
+    Capitalize { unit: TextUnit },
+    /// Отменить последнее изменение
+    Undo,
+}
+
+/// Единица текста, к которой применяется операция
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextUnit {
+    Word,
+    Sentence,
+    Paragraph,
+    All,
+}
+
+/// Форматирование текста по приложению, в которое он вставляется
+///
+/// Правила `rules` проверяются по порядку; каждое действие (стиль, точка,
+/// регистр) берётся из первого подходящего правила, где оно задано. Список
+/// из файла заменяет встроенные правила целиком.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FormattingConfig {
+    pub enabled: bool,
+    pub rules: Vec<FormatRule>,
+}
+
+impl Default for FormattingConfig {
+    fn default() -> Self {
+        let field = |field: FieldType| FormatRule {
+            field: Some(field),
+            ..FormatRule::default()
+        };
+
+        Self {
+            enabled: true,
+            rules: vec![
+                FormatRule {
+                    trailing_period: Some(false),
+                    ..field(FieldType::Chat)
+                },
+                FormatRule {
+                    trailing_period: Some(false),
+                    ..field(FieldType::Search)
+                },
+                FormatRule {
+                    style: Some("email".to_string()),
+                    ..field(FieldType::Email)
+                },
+                FormatRule {
+                    case: Some(Casing::SnakeCase),
+                    ..field(FieldType::Code)
+                },
+            ],
+        }
+    }
+}
+
+/// Правило форматирования: условия и действия
+///
+/// Правило без условий подходит для любого приложения.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FormatRule {
+    /// id приложения без учёта регистра; `*` в конце - префикс: `com.jetbrains.*`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    /// Подстрока заголовка окна без учёта регистра
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<FieldType>,
+
+    /// Профиль стиля, если клиент не выбрал стиль сам
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+    /// `false` - убрать точку в конце текста, `true` - поставить
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trailing_period: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub case: Option<Casing>,
+}
+
+/// Тип поля ввода, в которое вставляется текст
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    Text,
+    Code,
+    Chat,
+    Email,
+    Search,
+}
+
+/// Регистр текста
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Casing {
+    /// Все буквы строчные
+    Lower,
+    /// Первая буква заглавная
+    Sentence,
+    /// Идентификатор `user_name`
+    SnakeCase,
+    /// Идентификатор `userName`
+    CamelCase,
+}
+
+/// Аутентификация клиентов
+///
+/// Клиент передаёт API ключ или JWT в `Authorization: Bearer`, API ключ
+/// также в `X-API-Key`, а браузерный WebSocket - в `?access_token=`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Без аутентификации сервер доступен всем, кто может подключиться
+    pub enabled: bool,
+    pub api_keys: Vec<ApiKeyConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jwt: Option<JwtConfig>,
+    /// Пользователи, которым разрешено изменять общие профили стилей
+    pub admins: Vec<String>,
+}
+
+/// API ключ пользователя
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeyConfig {
+    /// SHA-256 ключа в hex: `printf %s "$KEY" | sha256sum`
+    pub key_sha256: String,
+    pub user: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+}
+
+/// Проверка JWT открытым ключом издателя (RS256, ES256 или EdDSA)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JwtConfig {
+    /// PEM файл открытого ключа (`-----BEGIN PUBLIC KEY-----`)
+    pub public_key: PathBuf,
+    /// Ожидаемый `iss`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    /// Ожидаемый `aud`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audience: Option<String>,
+    /// Claim с id пользователя
+    pub user_claim: String,
+    /// Claim с id рабочего пространства
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workspace_claim: Option<String>,
+    /// Допустимое расхождение часов при проверке `exp` и `nbf` (сек)
+    pub leeway_secs: u64,
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        Self {
+            public_key: PathBuf::new(),
+            issuer: None,
+            audience: None,
+            user_claim: "sub".to_string(),
+            workspace_claim: None,
+            leeway_secs: 60,
+        }
+    }
+}
+
+/// Настройки логирования
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// Фильтр в формате `tracing_subscriber::EnvFilter` (RUST_LOG имеет приоритет)
+    pub filter: String,
+    /// Цветной вывод
+    pub ansi: bool,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            filter: "alfavoice_server=debug,tower_http=debug,axum=debug".to_string(),
+            ansi: true,
+        }
+    }
+}
+
+/// Переопределения из командной строки
+#[derive(Debug, Clone, Default)]
+pub struct ConfigOverrides {
+    pub bind: Option<SocketAddr>,
+    pub whisper_model: Option<String>,
+    pub llm_model: Option<String>,
+    pub preset: Option<WhisperPreset>,
+    pub log: Option<String>,
+}
+
+impl AppConfig {
+    /// Файл конфигурации по умолчанию (в рабочем каталоге)
+    pub const DEFAULT_PATH: &'static str = "alfavoice.toml";
+
+    /// Загружает конфигурацию из всех источников и проверяет её
+    ///
+    /// Явно указанный файл обязан существовать; файл по умолчанию
+    /// необязателен. Возвращает конфигурацию и путь к прочитанному файлу.
+    pub fn load(path: Option<&Path>, overrides: &ConfigOverrides) -> Result<(Self, Option<PathBuf>), ConfigError> {
+        let (mut config, source) = match path {
+            Some(path) => (Self::from_file(path)?, Some(path.to_path_buf())),
+            None => {
+                let default = Path::new(Self::DEFAULT_PATH);
+                if default.exists() {
+                    (Self::from_file(default)?, Some(default.to_path_buf()))
+                } else {
+                    (Self::default(), None)
+                }
+            }
+        };
+
+        config.apply_env(|var| std::env::var(var).ok())?;
+        config.apply_overrides(overrides);
+        config.validate()?;
+
+        Ok((config, source))
+    }
+
+    /// Читает конфигурацию из TOML файла
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|source| {
+            if source.kind() == std::io::ErrorKind::NotFound {
+                ConfigError::NotFound(path.to_path_buf())
+            } else {
+                ConfigError::Io {
+                    path: path.to_path_buf(),
+                    source,
+                }
+            }
+        })?;
+
+        toml::from_str(&text).map_err(|e| ConfigError::Parse {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })
+    }
+
+    /// Применяет переменные окружения
+    ///
+    /// `var` возвращает значение переменной (в тестах - из таблицы).
+    /// Поддерживаются и прежние переменные `WHISPER_DEVICE`, `LLM_ENABLED`,
+    /// `LLM_MODEL_PATH`; новые `ALFAVOICE_*` имеют приоритет.
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        fn parse<T: std::str::FromStr>(var: &'static str, value: String) -> Result<T, ConfigError>
+        where
+            T::Err: std::fmt::Display,
+        {
+            value.trim().parse().map_err(|e: T::Err| ConfigError::Env {
+                var,
+                value: value.clone(),
+                message: e.to_string(),
+            })
+        }
+
+        fn parse_bool(var: &'static str, value: String) -> Result<bool, ConfigError> {
+            match value.trim().to_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => Ok(true),
+                "0" | "false" | "no" | "off" => Ok(false),
+                _ => Err(ConfigError::Env {
+                    var,
+                    value,
+                    message: "ожидается true или false".to_string(),
+                }),
+            }
+        }
+
+        let first = |names: [&'static str; 2]| names.into_iter().find_map(|name| var(name).map(|v| (name, v)));
+
+        if let Some(value) = var("ALFAVOICE_BIND") {
+            self.server.bind = parse("ALFAVOICE_BIND", value)?;
+        }
+        if let Some(value) = var("ALFAVOICE_WHISPER_MODEL") {
+            self.models.whisper_model = value;
+        }
+        if let Some((_, value)) = first(["ALFAVOICE_LLM_MODEL", "LLM_MODEL_PATH"]) {
+            self.models.llm_model = Some(value);
+        }
+        if let Some(value) = var("ALFAVOICE_PUNCTUATION_MODEL") {
+            self.models.punctuation_model = Some(value);
+        }
+        if let Some((name, value)) = first(["ALFAVOICE_WHISPER_DEVICE", "WHISPER_DEVICE"]) {
+            self.whisper.device = parse(name, value)?;
+        }
+        if let Some(value) = var("ALFAVOICE_WHISPER_THREADS") {
+            self.whisper.n_threads = Some(parse("ALFAVOICE_WHISPER_THREADS", value)?);
+        }
+        if let Some(value) = var("ALFAVOICE_WHISPER_POOL_SIZE") {
+            self.whisper.pool_size = Some(parse("ALFAVOICE_WHISPER_POOL_SIZE", value)?);
+        }
+        if let Some((name, value)) = first(["ALFAVOICE_LLM_ENABLED", "LLM_ENABLED"]) {
+            self.postprocessing.llm_enabled = parse_bool(name, value)?;
+        }
+        if let Some(value) = var("ALFAVOICE_LOG") {
+            self.logging.filter = value;
+        }
+
+        Ok(())
+    }
+
+    /// Применяет флаги командной строки
+    pub fn apply_overrides(&mut self, overrides: &ConfigOverrides) {
+        if let Some(bind) = overrides.bind {
+            self.server.bind = bind;
+        }
+        if let Some(ref path) = overrides.whisper_model {
+            self.models.whisper_model = path.clone();
+        }
+        if let Some(ref path) = overrides.llm_model {
+            self.models.llm_model = Some(path.clone());
+        }
+        if let Some(preset) = overrides.preset {
+            self.whisper.preset = preset;
+        }
+        if let Some(ref filter) = overrides.log {
+            self.logging.filter = filter.clone();
+        }
+    }
+
+    /// Проверяет значения; возвращает все найденные ошибки сразу
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+        let mut check = |ok: bool, message: String| {
+            if !ok {
+                errors.push(message);
+            }
+        };
+
+        if let Some(tls) = &self.server.tls {
+            check(!tls.cert.as_os_str().is_empty(), "server.tls.cert не задан".to_string());
+            check(!tls.key.as_os_str().is_empty(), "server.tls.key не задан".to_string());
+            check(tls.reload_secs > 0, "server.tls.reload_secs должно быть больше 0".to_string());
+        }
+
+        let whisper = &self.whisper;
+        if let Some(n) = whisper.n_threads {
+            check((1..=64).contains(&n), format!("whisper.n_threads = {}: допустимо 1..=64", n));
+        }
+        if let Some(n) = whisper.beam_size {
+            check((1..=16).contains(&n), format!("whisper.beam_size = {}: допустимо 1..=16", n));
+        }
+        if let Some(n) = whisper.pool_size {
+            check((1..=32).contains(&n), format!("whisper.pool_size = {}: допустимо 1..=32", n));
+        }
+        if let Some(ms) = whisper.max_queue_wait_ms {
+            check(ms > 0, "whisper.max_queue_wait_ms должно быть больше 0".to_string());
+        }
+
+        check(
+            !self.models.whisper_model.trim().is_empty(),
+            "models.whisper_model не может быть пустым".to_string(),
+        );
+
+        let streaming = &self.streaming;
+        check(
+            streaming.partial_interval_ms > 0,
+            "streaming.partial_interval_ms должно быть больше 0".to_string(),
+        );
+        check(
+            streaming.partial_window_secs > 0,
+            "streaming.partial_window_secs должно быть больше 0".to_string(),
+        );
+        check(
+            (1..=30).contains(&streaming.max_buffer_secs),
+            format!("streaming.max_buffer_secs = {}: допустимо 1..=30 (окно Whisper)", streaming.max_buffer_secs),
+        );
+        check(
+            streaming.end_of_utterance_ms > 0,
+            "streaming.end_of_utterance_ms должно быть больше 0".to_string(),
+        );
+        check(
+            streaming.max_session_secs > 0,
+            "streaming.max_session_secs должно быть больше 0".to_string(),
+        );
+
+        let vad = &self.vad;
+        check(
+            [10, 20, 30].contains(&vad.frame_ms),
+            format!("vad.frame_ms = {}: допустимо 10, 20 или 30", vad.frame_ms),
+        );
+        check(
+            (0.0..=1.0).contains(&vad.max_flatness),
+            format!("vad.max_flatness = {}: допустимо 0.0..=1.0", vad.max_flatness),
+        );
+        check(
+            vad.min_energy_db <= vad.max_threshold_db,
+            format!(
+                "vad.min_energy_db ({}) должно быть не больше vad.max_threshold_db ({})",
+                vad.min_energy_db, vad.max_threshold_db
+            ),
+        );
+        check(
+            (1..=30).contains(&vad.max_segment_secs),
+            format!("vad.max_segment_secs = {}: допустимо 1..=30 (окно Whisper)", vad.max_segment_secs),
+        );
+
+        let llm = &self.llm;
+        check(
+            (1..=4096).contains(&llm.max_tokens),
+            format!("llm.max_tokens = {}: допустимо 1..=4096", llm.max_tokens),
+        );
+        check(
+            (0.0..=2.0).contains(&llm.temperature),
+            format!("llm.temperature = {}: допустимо 0.0..=2.0", llm.temperature),
+        );
+        check(
+            llm.top_p > 0.0 && llm.top_p <= 1.0,
+            format!("llm.top_p = {}: допустимо (0.0, 1.0]", llm.top_p),
+        );
+
+        let stages = &self.postprocessing.stages;
+        for (index, stage) in stages.iter().enumerate() {
+            check(
+                !stages[..index].iter().any(|other| other.name == stage.name),
+                format!("postprocessing.stages: этап {:?} указан несколько раз", stage.name),
+            );
+            if let Some(ms) = stage.timeout_ms {
+                check(ms > 0, format!("postprocessing.stages: timeout_ms этапа {:?} должно быть больше 0", stage.name));
+            }
+        }
+
+        let limits = &self.limits;
+        check(limits.max_connections > 0, "limits.max_connections должно быть больше 0".to_string());
+        check(
+            limits.max_message_bytes >= 1024,
+            format!("limits.max_message_bytes = {}: минимум 1024", limits.max_message_bytes),
+        );
+        check(
+            limits.max_upload_bytes >= 1024,
+            format!("limits.max_upload_bytes = {}: минимум 1024", limits.max_upload_bytes),
+        );
+        check(limits.max_audio_secs > 0, "limits.max_audio_secs должно быть больше 0".to_string());
+        if let Some(n) = limits.max_connections_per_user {
+            check(n > 0, "limits.max_connections_per_user должно быть больше 0".to_string());
+        }
+
+        let jobs = &self.jobs;
+        check((1..=16).contains(&jobs.workers), format!("jobs.workers = {}: допустимо 1..=16", jobs.workers));
+        check(jobs.max_audio_secs > 0, "jobs.max_audio_secs должно быть больше 0".to_string());
+
+        let dictionaries = &self.dictionaries;
+        check(dictionaries.max_entries > 0, "dictionaries.max_entries должно быть больше 0".to_string());
+        check(
+            (1..=2000).contains(&dictionaries.max_prompt_chars),
+            format!("dictionaries.max_prompt_chars = {}: допустимо 1..=2000", dictionaries.max_prompt_chars),
+        );
+        check(self.snippets.max_snippets > 0, "snippets.max_snippets должно быть больше 0".to_string());
+
+        for command in &self.voice_commands.commands {
+            check(
+                !command.phrases.is_empty() && command.phrases.iter().all(|p| p.chars().any(char::is_alphanumeric)),
+                format!("voice_commands.commands: у команды {:?} пустая фраза или нет фраз", command.op),
+            );
+            if let EditOp::Insert { text } = &command.op {
+                check(!text.is_empty(), "voice_commands.commands: пустой text у insert".to_string());
+            }
+        }
+
+        for (index, rule) in self.formatting.rules.iter().enumerate() {
+            check(
+                rule.style.is_some() || rule.trailing_period.is_some() || rule.case.is_some(),
+                format!("formatting.rules[{}]: нужно хотя бы одно из style, trailing_period, case", index),
+            );
+            check(
+                rule.app.as_ref().is_none_or(|app| !app.trim_end_matches('*').trim().is_empty()),
+                format!("formatting.rules[{}]: пустой app", index),
+            );
+        }
+
+        let auth = &self.auth;
+        check(
+            !auth.enabled || !auth.api_keys.is_empty() || auth.jwt.is_some(),
+            "auth.enabled: нужны auth.api_keys или auth.jwt".to_string(),
+        );
+        for (index, key) in auth.api_keys.iter().enumerate() {
+            check(
+                key.key_sha256.len() == 64 && key.key_sha256.chars().all(|c| c.is_ascii_hexdigit()),
+                format!("auth.api_keys[{}].key_sha256: нужен SHA-256 в hex (64 символа)", index),
+            );
+            check(!key.user.trim().is_empty(), format!("auth.api_keys[{}].user не может быть пустым", index));
+        }
+        if let Some(jwt) = &auth.jwt {
+            check(!jwt.public_key.as_os_str().is_empty(), "auth.jwt.public_key не задан".to_string());
+            check(!jwt.user_claim.is_empty(), "auth.jwt.user_claim не может быть пустым".to_string());
+        }
+
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.logging.filter) {
+            errors.push(format!("logging.filter = {:?}: {}", self.logging.filter, e));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(errors))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_parse_partial_file() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            [server]
+            bind = "0.0.0.0:9000"
+
+            [server.tls]
+            cert = "certs/server.pem"
+            key = "certs/server.key"
+
+            [whisper]
+            preset = "fast"
+            pool_size = 4
+
+            [limits]
+            max_audio_secs = 120
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.server.bind.port(), 9000);
+        let tls = config.server.tls.as_ref().unwrap();
+        assert!(tls.client_ca.is_none() && tls.require_client_cert);
+        assert_eq!(config.limits.max_audio_secs, 120);
+        // Незаданные поля берутся по умолчанию
+        assert_eq!(config.limits.max_connections, LimitsConfig::default().max_connections);
+
+        let whisper = config.whisper.to_config();
+        assert_eq!(whisper.beam_size, WhisperConfig::fast().beam_size);
+        assert_eq!(whisper.pool_size, 4);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_example_file_is_valid() {
+        let config: AppConfig = toml::from_str(include_str!("../alfavoice.toml.example")).unwrap();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_unknown_field_is_rejected() {
+        let result: Result<AppConfig, _> = toml::from_str("[whisper]\npool = 4\n");
+        let message = result.unwrap_err().to_string();
+        assert!(message.contains("pool"), "{}", message);
+    }
+
+    #[test]
+    fn test_env_and_overrides_layering() {
+        let env: HashMap<&str, &str> = HashMap::from([
+            ("ALFAVOICE_BIND", "127.0.0.1:7000"),
+            ("WHISPER_DEVICE", "cuda"),
+            ("LLM_ENABLED", "false"),
+        ]);
+        let mut config = AppConfig::default();
+        config.apply_env(|var| env.get(var).map(|v| v.to_string())).unwrap();
+
+        assert_eq!(config.server.bind.port(), 7000);
+        assert!(config.whisper.to_config().use_gpu);
+        assert!(!config.postprocessing.llm_enabled);
+
+        config.apply_overrides(&ConfigOverrides {
+            bind: Some("127.0.0.1:7001".parse().unwrap()),
+            ..ConfigOverrides::default()
+        });
+        assert_eq!(config.server.bind.port(), 7001);
+
+        let result = config.apply_env(|var| (var == "ALFAVOICE_WHISPER_THREADS").then(|| "many".to_string()));
+        assert!(matches!(result, Err(ConfigError::Env { var: "ALFAVOICE_WHISPER_THREADS", .. })));
+    }
+
+    #[test]
+    fn test_postprocessing_stages() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            [[postprocessing.stages]]
+            name = "llm"
+            on_error = "stop"
+            timeout_ms = 5000
+
+            [[postprocessing.stages]]
+            name = "llm"
+            "#,
+        )
+        .unwrap();
+
+        let stage = &config.postprocessing.stages[0];
+        assert_eq!(stage.name, StageKind::Llm);
+        assert_eq!(stage.on_error, FailurePolicy::Stop);
+        assert_eq!(stage.timeout_ms, Some(5000));
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(errors)) if errors[0].contains("несколько раз")));
+
+        let result: Result<AppConfig, _> = toml::from_str("[[postprocessing.stages]]\nname = \"spellcheck\"\n");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_voice_commands_grammar() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            [[voice_commands.commands]]
+            phrases = ["абзац"]
+            op = "insert"
+            text = "\n\n"
+
+            [[voice_commands.commands]]
+            phrases = ["стереть слово"]
+            op = "delete"
+            unit = "word"
+            "#,
+        )
+        .unwrap();
+
+        // Список из файла заменяет встроенную грамматику
+        let commands = &config.voice_commands.commands;
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[1].op, EditOp::Delete { unit: TextUnit::Word });
+
+        // print-config выводит грамматику в том же формате
+        let printed: AppConfig = toml::from_str(&toml::to_string_pretty(&AppConfig::default()).unwrap()).unwrap();
+        assert_eq!(printed.voice_commands.commands, VoiceCommandsConfig::default().commands);
+    }
+
+    #[test]
+    fn test_validation_reports_all_errors() {
+        let mut config = AppConfig::default();
+        config.whisper.pool_size = Some(0);
+        config.vad.frame_ms = 25;
+
+        match config.validate() {
+            Err(ConfigError::Invalid(errors)) => {
+                assert_eq!(errors.len(), 2);
+                assert!(errors[0].contains("whisper.pool_size"));
+                assert!(errors[1].contains("vad.frame_ms"));
+            }
+            other => panic!("ожидалась ошибка валидации, получено {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,284 @@
+//! Персональные словари: термины, их написание и варианты распознавания
+//!
+//! Словарь принадлежит пользователю или рабочему пространству и хранится
+//! в `<dir>/<scope>/<id>.json`. Для запроса объединяются словари
+//! пользователя и его рабочего пространства (`Vocabulary`):
+//! - термины попадают в `initial_prompt` Whisper, что смещает распознавание
+//!   к нужному написанию названий продуктов и фамилий;
+//! - после распознавания детерминированно заменяются варианты (`aliases`)
+//!   и термины в другом регистре на написание из словаря.
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::DictionaryConfig;
+use crate::phrases::{self, Phrases};
+use crate::store::{OwnedStore, Owners, Record, Scope, StoreError};
+
+/// Максимальная длина термина и варианта (символов)
+const MAX_TERM_CHARS: usize = 100;
+
+/// Максимум вариантов у одного термина
+const MAX_ALIASES: usize = 20;
+
+/// Запись словаря
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: String,
+    /// Правильное написание: `AlfaVoice`, `Кубернетес`, `Иванов`
+    pub term: String,
+    /// Как Whisper распознаёт термин: `альфа войс`, `alpha voice`
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Record for Entry {
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+/// Тело запроса на создание и изменение записи
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EntryInput {
+    pub term: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+impl EntryInput {
+    /// Обрезает пробелы, убирает пустые и повторяющиеся варианты
+    fn normalized(self) -> Result<Self, StoreError> {
+        let term = self.term.trim().to_string();
+        if phrases::words(&term).is_empty() {
+            return Err(StoreError::Invalid("term должен содержать буквы или цифры".to_string()));
+        }
+        if term.chars().count() > MAX_TERM_CHARS {
+            return Err(StoreError::Invalid(format!("term длиннее {} символов", MAX_TERM_CHARS)));
+        }
+
+        let mut aliases: Vec<String> = Vec::new();
+        for alias in self.aliases {
+            let alias = alias.trim().to_string();
+            if phrases::words(&alias).is_empty() || aliases.iter().any(|a| a.to_lowercase() == alias.to_lowercase()) {
+                continue;
+            }
+            if alias.chars().count() > MAX_TERM_CHARS {
+                return Err(StoreError::Invalid(format!("alias длиннее {} символов", MAX_TERM_CHARS)));
+            }
+            aliases.push(alias);
+        }
+        if aliases.len() > MAX_ALIASES {
+            return Err(StoreError::Invalid(format!("Не больше {} вариантов у термина", MAX_ALIASES)));
+        }
+
+        Ok(Self { term, aliases })
+    }
+}
+
+/// Хранилище словарей
+pub struct DictionaryStore {
+    entries: OwnedStore<Entry>,
+    max_prompt_chars: usize,
+}
+
+impl DictionaryStore {
+    /// Открывает каталог словарей
+    pub fn open(config: &DictionaryConfig) -> Result<Arc<Self>, StoreError> {
+        Ok(Arc::new(Self {
+            entries: OwnedStore::open("Словарь", &config.dir, config.max_entries)?,
+            max_prompt_chars: config.max_prompt_chars,
+        }))
+    }
+
+    /// Записи словаря в порядке добавления
+    pub fn list(&self, scope: Scope, owner: &str) -> Result<Arc<Vec<Entry>>, StoreError> {
+        self.entries.list(scope, owner)
+    }
+
+    /// Добавляет запись
+    pub fn add(&self, scope: Scope, owner: &str, input: EntryInput) -> Result<Entry, StoreError> {
+        let input = input.normalized()?;
+        let now = Utc::now();
+        let entry = Entry {
+            id: uuid::Uuid::new_v4().to_string(),
+            term: input.term,
+            aliases: input.aliases,
+            created_at: now,
+            updated_at: now,
+        };
+        self.entries.insert(scope, owner, entry)
+    }
+
+    /// Заменяет термин и варианты записи
+    pub fn update(&self, scope: Scope, owner: &str, id: &str, input: EntryInput) -> Result<Entry, StoreError> {
+        let input = input.normalized()?;
+        self.entries.update(scope, owner, id, |entry| {
+            entry.term = input.term;
+            entry.aliases = input.aliases;
+            entry.updated_at = Utc::now();
+        })
+    }
+
+    /// Удаляет запись
+    pub fn remove(&self, scope: Scope, owner: &str, id: &str) -> Result<(), StoreError> {
+        self.entries.remove(scope, owner, id)
+    }
+
+    /// Объединённый словарь пользователя и рабочего пространства
+    ///
+    /// Записи пользователя идут первыми и имеют приоритет.
+    pub fn vocabulary(&self, owners: &Owners) -> Option<Arc<Vocabulary>> {
+        let entries = self.entries.merged(owners);
+        (!entries.is_empty()).then(|| Arc::new(Vocabulary::new(entries, self.max_prompt_chars)))
+    }
+}
+
+/// Словарь, применяемый к одному запросу
+#[derive(Debug)]
+pub struct Vocabulary {
+    terms: Vec<String>,
+    /// Варианты и сами термины -> термин
+    patterns: Phrases<String>,
+    max_prompt_chars: usize,
+}
+
+impl Vocabulary {
+    pub fn new(entries: Vec<Entry>, max_prompt_chars: usize) -> Self {
+        let mut terms: Vec<String> = Vec::new();
+        let mut patterns = Phrases::default();
+
+        for entry in entries {
+            // Сам термин тоже вариант: исправляет регистр (`alfavoice` -> `AlfaVoice`).
+            // При совпадении вариантов выигрывает первая запись (словарь пользователя)
+            for variant in std::iter::once(&entry.term).chain(&entry.aliases) {
+                patterns.insert(variant, entry.term.clone());
+            }
+            if !terms.contains(&entry.term) {
+                terms.push(entry.term);
+            }
+        }
+
+        Self {
+            terms,
+            patterns,
+            max_prompt_chars,
+        }
+    }
+
+    /// `initial_prompt` Whisper: подсказка клиента и термины словаря
+    ///
+    /// Термины добавляются, пока подсказка укладывается в `max_prompt_chars`.
+    pub fn prompt(&self, base: Option<&str>) -> Option<String> {
+        let base = base.map(str::trim).filter(|base| !base.is_empty());
+        let mut prompt = base.map(str::to_string).unwrap_or_default();
+        let mut added = 0;
+
+        for term in &self.terms {
+            let separator = match (added, prompt.is_empty()) {
+                (0, true) => "",
+                (0, false) => " ",
+                _ => ", ",
+            };
+            if prompt.chars().count() + separator.len() + term.chars().count() + 1 > self.max_prompt_chars {
+                break;
+            }
+            prompt.push_str(separator);
+            prompt.push_str(term);
+            added += 1;
+        }
+        if added > 0 {
+            prompt.push('.');
+        }
+
+        (!prompt.is_empty()).then_some(prompt)
+    }
+
+    /// Заменяет варианты на термины целыми словами без учёта регистра
+    pub fn apply(&self, text: &str) -> String {
+        self.patterns.replace(text, String::clone)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(term: &str, aliases: &[&str]) -> Entry {
+        Entry {
+            id: term.to_string(),
+            term: term.to_string(),
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_apply_replacements() {
+        let vocabulary = Vocabulary::new(
+            vec![
+                entry("AlfaVoice", &["альфа войс", "alpha voice"]),
+                entry("Иванов", &["иваноф"]),
+            ],
+            200,
+        );
+
+        assert_eq!(
+            vocabulary.apply("Альфа-войс работает, спросите Иваноф. alfavoice и alpha  voice!"),
+            "AlfaVoice работает, спросите Иванов. AlfaVoice и AlfaVoice!"
+        );
+        // Только целые слова и без знаков между словами варианта
+        assert_eq!(vocabulary.apply("альфа, войс и иванова"), "альфа, войс и иванова");
+    }
+
+    #[test]
+    fn test_prompt_budget() {
+        let vocabulary = Vocabulary::new(vec![entry("AlfaVoice", &[]), entry("Kubernetes", &[])], 30);
+        assert_eq!(vocabulary.prompt(None).as_deref(), Some("AlfaVoice, Kubernetes."));
+        assert_eq!(vocabulary.prompt(Some("Совещание.")).as_deref(), Some("Совещание. AlfaVoice."));
+    }
+
+    #[test]
+    fn test_store_crud_and_merge() {
+        let dir = std::env::temp_dir().join(format!("alfavoice-dictionary-{}", uuid::Uuid::new_v4()));
+        let config = DictionaryConfig {
+            dir: dir.clone(),
+            max_entries: 2,
+            ..DictionaryConfig::default()
+        };
+        let store = DictionaryStore::open(&config).unwrap();
+        let input = |term: &str, aliases: &[&str]| EntryInput {
+            term: term.to_string(),
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+        };
+
+        let added = store.add(Scope::User, "u1", input(" AlfaVoice ", &["альфа войс", "Альфа Войс", " "])).unwrap();
+        assert_eq!(added.term, "AlfaVoice");
+        assert_eq!(added.aliases, ["альфа войс"]);
+        store.add(Scope::Workspace, "acme", input("Иванов", &["иваноф"])).unwrap();
+        store.update(Scope::User, "u1", &added.id, input("AlfaVoice", &["альфа войс"])).unwrap();
+
+        // Словарь читается с диска заново
+        let reopened = DictionaryStore::open(&config).unwrap();
+        assert_eq!(reopened.list(Scope::User, "u1").unwrap()[0].aliases, ["альфа войс"]);
+        let owners = Owners {
+            user: Some("u1".to_string()),
+            workspace: Some("acme".to_string()),
+        };
+        let vocabulary = reopened.vocabulary(&owners).unwrap();
+        assert_eq!(vocabulary.apply("альфа войс и иваноф"), "AlfaVoice и Иванов");
+
+        assert!(matches!(store.add(Scope::User, "../etc", input("x", &[])), Err(StoreError::InvalidOwner(_))));
+        assert!(matches!(store.remove(Scope::User, "u1", "missing"), Err(StoreError::NotFound(_))));
+        store.add(Scope::User, "u1", input("Kubernetes", &[])).unwrap();
+        assert!(matches!(store.add(Scope::User, "u1", input("Helm", &[])), Err(StoreError::Full(2))));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,195 @@
+//! Экспорт результата распознавания
+//!
+//! Субтитры (SRT, WebVTT) и Markdown строятся по сегментам `Transcript`:
+//! один сегмент - одна реплика с таймкодом от начала аудио. TXT содержит
+//! текст после постобработки, JSON - текст вместе с сегментами.
+
+use serde::{Deserialize, Serialize};
+
+use crate::transcript::{Segment, Transcript};
+
+/// Формат экспорта (`format` в HTTP API и в сообщении `stop`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Txt,
+    Json,
+    Srt,
+    Vtt,
+    #[serde(alias = "md")]
+    Markdown,
+}
+
+impl Format {
+    /// Разбирает название формата из поля формы
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "txt" | "text" => Some(Self::Txt),
+            "json" => Some(Self::Json),
+            "srt" => Some(Self::Srt),
+            "vtt" => Some(Self::Vtt),
+            "markdown" | "md" => Some(Self::Markdown),
+            _ => None,
+        }
+    }
+
+    /// MIME тип для HTTP ответа
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Txt => "text/plain; charset=utf-8",
+            Self::Json => "application/json",
+            Self::Srt => "application/x-subrip; charset=utf-8",
+            Self::Vtt => "text/vtt; charset=utf-8",
+            Self::Markdown => "text/markdown; charset=utf-8",
+        }
+    }
+
+    /// Расширение файла для скачивания
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Txt => "txt",
+            Self::Json => "json",
+            Self::Srt => "srt",
+            Self::Vtt => "vtt",
+            Self::Markdown => "md",
+        }
+    }
+}
+
+/// Структура JSON экспорта
+#[derive(Serialize)]
+struct Document<'a> {
+    text: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<&'a str>,
+    segments: &'a [Segment],
+}
+
+/// Отрисовывает результат в выбранном формате
+///
+/// `text` - текст после постобработки (для TXT и JSON), субтитры и
+/// Markdown используют исходные сегменты с таймкодами.
+pub fn render(format: Format, transcript: &Transcript, text: &str) -> String {
+    match format {
+        Format::Txt => format!("{}\n", text),
+        Format::Json => {
+            let document = Document {
+                text,
+                language: transcript.language.as_deref(),
+                segments: &transcript.segments,
+            };
+            serde_json::to_string_pretty(&document).unwrap_or_default()
+        }
+        Format::Srt => srt(transcript),
+        Format::Vtt => vtt(transcript),
+        Format::Markdown => markdown(transcript),
+    }
+}
+
+/// Субтитры SubRip (SRT)
+pub fn srt(transcript: &Transcript) -> String {
+    let mut out = String::new();
+    for (index, segment) in transcript.segments.iter().filter(|s| !s.text.is_empty()).enumerate() {
+        out.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            index + 1,
+            timestamp(segment.start, ','),
+            timestamp(segment.end, ','),
+            segment.text
+        ));
+    }
+    out
+}
+
+/// Субтитры WebVTT
+pub fn vtt(transcript: &Transcript) -> String {
+    let mut out = String::from("WEBVTT\n\n");
+    for segment in transcript.segments.iter().filter(|s| !s.text.is_empty()) {
+        out.push_str(&format!(
+            "{} --> {}\n{}\n\n",
+            timestamp(segment.start, '.'),
+            timestamp(segment.end, '.'),
+            segment.text
+        ));
+    }
+    out
+}
+
+/// Markdown для заметок: реплика с таймкодом начала
+pub fn markdown(transcript: &Transcript) -> String {
+    let mut out = String::new();
+    for segment in transcript.segments.iter().filter(|s| !s.text.is_empty()) {
+        let millis = (segment.start.max(0.0) * 1000.0) as u64;
+        out.push_str(&format!(
+            "**[{:02}:{:02}:{:02}]** {}\n\n",
+            millis / 3_600_000,
+            millis / 60_000 % 60,
+            millis / 1000 % 60,
+            segment.text
+        ));
+    }
+    out
+}
+
+/// Таймкод `ЧЧ:ММ:СС<sep>ммм`
+fn timestamp(secs: f64, separator: char) -> String {
+    let millis = (secs.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transcript() -> Transcript {
+        let segment = |start, end, text: &str| Segment {
+            start,
+            end,
+            text: text.to_string(),
+            avg_logprob: -0.1,
+            no_speech_prob: None,
+            words: Vec::new(),
+        };
+        Transcript {
+            segments: vec![segment(0.0, 1.5, "Привет."), segment(1.5, 2.0, ""), segment(3661.25, 3662.0, "Как дела?")],
+            ..Transcript::default()
+        }
+    }
+
+    #[test]
+    fn test_srt() {
+        assert_eq!(
+            srt(&transcript()),
+            "1\n00:00:00,000 --> 00:00:01,500\nПривет.\n\n2\n01:01:01,250 --> 01:01:02,000\nКак дела?\n\n"
+        );
+    }
+
+    #[test]
+    fn test_markdown_and_txt() {
+        assert_eq!(markdown(&transcript()), "**[00:00:00]** Привет.\n\n**[01:01:01]** Как дела?\n\n");
+        assert_eq!(render(Format::Txt, &transcript(), "Привет. Как дела?"), "Привет. Как дела?\n");
+    }
+
+    #[test]
+    fn test_format_names() {
+        assert_eq!(Format::from_name("MD"), Some(Format::Markdown));
+        assert_eq!(Format::from_name("docx"), None);
+        let format: Format = serde_json::from_str("\"md\"").unwrap();
+        assert_eq!(format, Format::Markdown);
+    }
+
+    #[test]
+    fn test_vtt() {
+        assert_eq!(
+            vtt(&transcript()),
+            "WEBVTT\n\n00:00:00.000 --> 00:00:01.500\nПривет.\n\n01:01:01.250 --> 01:01:02.000\nКак дела?\n\n"
+        );
+    }
+}
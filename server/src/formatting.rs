@@ -0,0 +1,188 @@
+//! Форматирование по приложению, в которое вставляется текст
+//!
+//! Клиент сообщает контекст: id приложения, заголовок окна и тип поля ввода.
+//! Правила `formatting.rules` выбирают по нему профиль стиля, точку в конце
+//! и регистр: в чате без точки, в редакторе кода короткая фраза становится
+//! идентификатором `snake_case`. Этап постобработки `format` применяет точку
+//! и регистр, стиль применяет этап `style`.
+
+use serde::Deserialize;
+
+use crate::config::{Casing, FieldType, FormatRule, FormattingConfig};
+use crate::phrases;
+
+/// Максимум слов во фразе, которая превращается в идентификатор
+const MAX_IDENTIFIER_WORDS: usize = 4;
+
+/// Куда вставляется текст
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AppContext {
+    /// id приложения: `com.tinyspeck.slackmacgap`, `code.exe`
+    #[serde(default)]
+    pub app: Option<String>,
+    /// Заголовок активного окна
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub field: Option<FieldType>,
+}
+
+/// Правила форматирования
+#[derive(Debug)]
+pub struct Rules {
+    rules: Vec<FormatRule>,
+}
+
+impl Rules {
+    pub fn new(config: &FormattingConfig) -> Self {
+        Self {
+            rules: config.rules.clone(),
+        }
+    }
+
+    /// Действия для контекста: каждое из первого подходящего правила, где оно задано
+    pub fn resolve(&self, context: &AppContext) -> Formatting {
+        let mut formatting = Formatting::default();
+        for rule in self.rules.iter().filter(|rule| matches(rule, context)) {
+            formatting.style = formatting.style.or_else(|| rule.style.clone());
+            formatting.trailing_period = formatting.trailing_period.or(rule.trailing_period);
+            formatting.case = formatting.case.or(rule.case);
+        }
+        formatting
+    }
+}
+
+fn matches(rule: &FormatRule, context: &AppContext) -> bool {
+    let app = match (&rule.app, &context.app) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(pattern), Some(app)) => match pattern.strip_suffix('*') {
+            Some(prefix) => app.to_lowercase().starts_with(&prefix.to_lowercase()),
+            None => app.eq_ignore_ascii_case(pattern),
+        },
+    };
+    let title = match (&rule.title, &context.title) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(part), Some(title)) => title.to_lowercase().contains(&part.to_lowercase()),
+    };
+    let field = rule.field.is_none() || rule.field == context.field;
+    app && title && field
+}
+
+/// Форматирование одного запроса
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Formatting {
+    /// Профиль стиля, если клиент не выбрал стиль сам
+    pub style: Option<String>,
+    pub trailing_period: Option<bool>,
+    pub case: Option<Casing>,
+}
+
+impl Formatting {
+    /// Применяет регистр и точку в конце
+    pub fn apply(&self, text: &str) -> String {
+        let words = || phrases::words(text).into_iter().map(|(word, _)| word);
+        let mut text = match self.case {
+            None => text.to_string(),
+            Some(Casing::Lower) => text.to_lowercase(),
+            Some(Casing::Sentence) => capitalize(text),
+            // Идентификатор: только буквы и цифры, точка к нему не относится.
+            // Обычная диктовка (комментарий, строка) остаётся текстом
+            Some(Casing::SnakeCase | Casing::CamelCase) if !is_identifier_like(text) => text.to_string(),
+            Some(Casing::SnakeCase) => return words().collect::<Vec<_>>().join("_"),
+            Some(Casing::CamelCase) => {
+                return words()
+                    .enumerate()
+                    .map(|(index, word)| if index == 0 { word } else { capitalize(&word) })
+                    .collect()
+            }
+        };
+
+        match self.trailing_period {
+            Some(false) if text.ends_with('.') && !text.ends_with("..") => {
+                text.pop();
+            }
+            Some(true) if text.ends_with(char::is_alphanumeric) => text.push('.'),
+            _ => {}
+        }
+        text
+    }
+}
+
+/// Похож ли текст на продиктованный идентификатор: не больше
+/// `MAX_IDENTIFIER_WORDS` слов и без знаков конца предложения внутри
+fn is_identifier_like(text: &str) -> bool {
+    let body = text.trim_end().trim_end_matches('.');
+    phrases::words(body).len() <= MAX_IDENTIFIER_WORDS
+        && !body.contains(['.', '?', '!', ';', ':', '\n'])
+}
+
+/// Первая буква текста заглавная
+fn capitalize(text: &str) -> String {
+    match text.char_indices().find(|(_, c)| c.is_alphabetic()) {
+        Some((index, c)) => format!("{}{}{}", &text[..index], c.to_uppercase(), &text[index + c.len_utf8()..]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(app: &str, title: &str, field: Option<FieldType>) -> AppContext {
+        AppContext {
+            app: Some(app.to_string()),
+            title: Some(title.to_string()),
+            field,
+        }
+    }
+
+    #[test]
+    fn test_resolve_rules() {
+        let mut config = FormattingConfig::default();
+        config.rules.insert(
+            0,
+            FormatRule {
+                app: Some("com.jetbrains.*".to_string()),
+                title: Some("commit".to_string()),
+                style: Some("formal".to_string()),
+                ..FormatRule::default()
+            },
+        );
+        let rules = Rules::new(&config);
+
+        let chat = rules.resolve(&context("com.tinyspeck.slackmacgap", "general", Some(FieldType::Chat)));
+        assert_eq!(chat.trailing_period, Some(false));
+        assert_eq!(chat.style, None);
+
+        // Действия разных правил объединяются
+        let commit = rules.resolve(&context("com.jetbrains.IntelliJ-IDEA", "Commit Changes", Some(FieldType::Code)));
+        assert_eq!(commit.style.as_deref(), Some("formal"));
+        assert_eq!(commit.case, Some(Casing::SnakeCase));
+
+        assert_eq!(rules.resolve(&context("com.jetbrains.idea", "Main.kt", None)), Formatting::default());
+        assert_eq!(rules.resolve(&AppContext::default()), Formatting::default());
+    }
+
+    #[test]
+    fn test_apply_formatting() {
+        let formatting = |trailing_period, case| Formatting {
+            style: None,
+            trailing_period,
+            case,
+        };
+
+        assert_eq!(formatting(Some(false), None).apply("Буду через 5 минут."), "Буду через 5 минут");
+        assert_eq!(formatting(Some(false), None).apply("Подожди..."), "Подожди...");
+        assert_eq!(formatting(Some(true), Some(Casing::Sentence)).apply("привет"), "Привет.");
+        assert_eq!(formatting(None, Some(Casing::SnakeCase)).apply("User name, ID."), "user_name_id");
+        assert_eq!(formatting(Some(true), Some(Casing::CamelCase)).apply("max retry count"), "maxRetryCount");
+
+        // Предложения в поле кода не склеиваются в идентификатор
+        let sentence = "Проверяем, что пользователь авторизован перед запросом.";
+        assert_eq!(formatting(None, Some(Casing::SnakeCase)).apply(sentence), sentence);
+        assert_eq!(formatting(None, Some(Casing::SnakeCase)).apply("Готово. Тесты"), "Готово. Тесты");
+    }
+}
@@ -0,0 +1,577 @@
+//! Асинхронные задания на распознавание длинных записей
+//!
+//! Запись часового совещания не помещается в одно WebSocket сообщение и не
+//! укладывается в таймауты HTTP. Клиент загружает файл, получает id задания
+//! и опрашивает `GET /v1/jobs/{id}` или подписывается на прогресс по WebSocket.
+//!
+//! Каждое задание хранится на диске: метаданные и результат в `<dir>/<id>.json`,
+//! аудио в `<dir>/<id>.audio` до конца обработки. После перезапуска сервера
+//! незавершённые задания снова ставятся в очередь.
+//!
+//! Файл декодируется потоково с проверкой длительности, ресемплинг и VAD
+//! считаются частями в пуле rayon (`setup_rayon_thread_pool`), а участки речи,
+//! найденные VAD, распознаются конкурентно на runtime: одновременно в Whisper
+//! уходит не больше запросов, чем состояний в пуле `WhisperModel`, остальные
+//! участки ждут своей очереди.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{error, info, warn};
+
+use crate::audio::{self, PcmSpec};
+use crate::config::JobsConfig;
+use crate::store::Owners;
+use crate::postprocess::PostProcessContext;
+use crate::state::AppState;
+use crate::transcript::Transcript;
+use crate::resample;
+use crate::whisper::{Language, Task, TranscribeError, TranscribeOptions, WhisperModel, WHISPER_SAMPLE_RATE};
+
+/// Пауза перед повторной попыткой, если все состояния Whisper заняты
+const BUSY_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Ёмкость канала событий для WebSocket подписчиков
+const EVENTS_CAPACITY: usize = 256;
+
+/// Ошибки хранилища заданий
+#[derive(Debug, Error)]
+pub enum JobError {
+    #[error("Ошибка ввода-вывода {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Некорректный файл задания {path}: {source}")]
+    Corrupted {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+/// Состояние задания
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+}
+
+impl JobStatus {
+    /// Задание завершено (успешно или с ошибкой)
+    pub fn is_finished(self) -> bool {
+        matches!(self, JobStatus::Completed | JobStatus::Failed)
+    }
+}
+
+/// Задание на распознавание
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    pub status: JobStatus,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub language: Language,
+    pub task: Task,
+    /// Частота сырого PCM без заголовка
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<u32>,
+    /// Каналы сырого PCM без заголовка
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channels: Option<u16>,
+    /// Пользователь и рабочее пространство, чьи словари применяются
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    /// Доля обработанных участков речи, 0.0..=1.0
+    pub progress: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<JobResult>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Результат задания
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobResult {
+    /// Текст после постобработки
+    pub text: String,
+    /// Длительность аудио (сек)
+    pub duration: f64,
+    /// Сегменты и язык (исходный результат Whisper)
+    #[serde(flatten)]
+    pub transcript: Transcript,
+}
+
+/// Изменение задания для подписчиков WebSocket
+#[derive(Debug, Clone)]
+pub struct JobEvent {
+    pub id: String,
+    pub status: JobStatus,
+    pub progress: f32,
+    pub text: Option<String>,
+    pub error: Option<String>,
+}
+
+impl From<&Job> for JobEvent {
+    fn from(job: &Job) -> Self {
+        Self {
+            id: job.id.clone(),
+            status: job.status,
+            progress: job.progress,
+            text: job.result.as_ref().map(|r| r.text.clone()),
+            error: job.error.clone(),
+        }
+    }
+}
+
+/// Очередь id заданий для обработчиков
+pub type JobQueue = mpsc::UnboundedReceiver<String>;
+
+/// Хранилище и очередь заданий
+pub struct JobManager {
+    dir: PathBuf,
+    max_audio_secs: u64,
+    jobs: Mutex<HashMap<String, Job>>,
+    queue: mpsc::UnboundedSender<String>,
+    events: broadcast::Sender<JobEvent>,
+}
+
+impl JobManager {
+    /// Открывает каталог заданий и восстанавливает очередь
+    ///
+    /// Задания, прерванные перезапуском (`queued` и `running`), снова ставятся
+    /// в очередь в порядке создания. Если их аудио пропало, задание
+    /// помечается как неудачное.
+    pub fn open(config: &JobsConfig) -> Result<(Arc<Self>, JobQueue), JobError> {
+        let dir = config.dir.clone();
+        std::fs::create_dir_all(&dir).map_err(|source| JobError::Io {
+            path: dir.clone(),
+            source,
+        })?;
+
+        let (queue, receiver) = mpsc::unbounded_channel();
+        let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+        let manager = Arc::new(Self {
+            dir,
+            max_audio_secs: config.max_audio_secs,
+            jobs: Mutex::new(HashMap::new()),
+            queue,
+            events,
+        });
+
+        let mut pending = Vec::new();
+        for job in manager.load_all()? {
+            let id = job.id.clone();
+            let interrupted = !job.status.is_finished();
+            manager.lock_jobs().insert(id.clone(), job);
+
+            if !interrupted {
+                continue;
+            }
+            if manager.audio_path(&id).exists() {
+                manager.update(&id, |job| {
+                    job.status = JobStatus::Queued;
+                    job.progress = 0.0;
+                });
+                pending.push(id);
+            } else {
+                manager.update(&id, |job| {
+                    job.status = JobStatus::Failed;
+                    job.error = Some("Аудио задания не найдено после перезапуска".to_string());
+                });
+            }
+        }
+
+        pending.sort_by_key(|id| manager.get(id).map(|job| job.created_at));
+        if !pending.is_empty() {
+            info!("Восстановлено незавершённых заданий: {}", pending.len());
+        }
+        for id in pending {
+            let _ = manager.queue.send(id);
+        }
+
+        Ok((manager, receiver))
+    }
+
+    /// Сохраняет аудио и ставит задание в очередь
+    pub async fn submit(
+        &self,
+        audio: &[u8],
+        options: &TranscribeOptions,
+        sample_rate: Option<u32>,
+        channels: Option<u16>,
+        owners: &Owners,
+    ) -> Result<Job, JobError> {
+        let now = Utc::now();
+        let job = Job {
+            id: uuid::Uuid::new_v4().to_string(),
+            status: JobStatus::Queued,
+            created_at: now,
+            updated_at: now,
+            language: options.language,
+            task: options.task,
+            sample_rate,
+            channels,
+            user: owners.user.clone(),
+            workspace: owners.workspace.clone(),
+            progress: 0.0,
+            result: None,
+            error: None,
+        };
+
+        let audio_path = self.audio_path(&job.id);
+        tokio::fs::write(&audio_path, audio).await.map_err(|source| JobError::Io {
+            path: audio_path,
+            source,
+        })?;
+        self.save(&job)?;
+
+        self.lock_jobs().insert(job.id.clone(), job.clone());
+        let _ = self.queue.send(job.id.clone());
+        info!("Задание {} поставлено в очередь ({} байт)", job.id, audio.len());
+
+        Ok(job)
+    }
+
+    /// Текущее состояние задания
+    pub fn get(&self, id: &str) -> Option<Job> {
+        self.lock_jobs().get(id).cloned()
+    }
+
+    /// Подписка на изменения всех заданий
+    pub fn subscribe(&self) -> broadcast::Receiver<JobEvent> {
+        self.events.subscribe()
+    }
+
+    /// Изменяет задание, сохраняет его на диск и оповещает подписчиков
+    fn update(&self, id: &str, change: impl FnOnce(&mut Job)) {
+        // Запись на диск под блокировкой, чтобы старое состояние не перезаписало новое
+        let mut jobs = self.lock_jobs();
+        let Some(job) = jobs.get_mut(id) else {
+            return;
+        };
+        change(job);
+        job.updated_at = Utc::now();
+
+        if let Err(e) = self.save(job) {
+            error!("Не удалось сохранить задание {}: {}", id, e);
+        }
+        let _ = self.events.send(JobEvent::from(&*job));
+    }
+
+    fn save(&self, job: &Job) -> Result<(), JobError> {
+        let path = self.job_path(&job.id);
+        let tmp = path.with_extension("json.tmp");
+        let json = serde_json::to_vec_pretty(job).map_err(|source| JobError::Corrupted {
+            path: path.clone(),
+            source,
+        })?;
+
+        std::fs::write(&tmp, json)
+            .and_then(|_| std::fs::rename(&tmp, &path))
+            .map_err(|source| JobError::Io { path, source })
+    }
+
+    fn load_all(&self) -> Result<Vec<Job>, JobError> {
+        let io_error = |source| JobError::Io {
+            path: self.dir.clone(),
+            source,
+        };
+
+        let mut jobs = Vec::new();
+        for entry in std::fs::read_dir(&self.dir).map_err(io_error)? {
+            let path = entry.map_err(io_error)?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            match read_job(&path) {
+                Ok(job) => jobs.push(job),
+                // Один испорченный файл не должен мешать запуску сервера
+                Err(e) => warn!("{}", e),
+            }
+        }
+        Ok(jobs)
+    }
+
+    fn job_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    fn audio_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.audio", id))
+    }
+
+    fn lock_jobs(&self) -> std::sync::MutexGuard<'_, HashMap<String, Job>> {
+        self.jobs.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn read_job(path: &Path) -> Result<Job, JobError> {
+    let text = std::fs::read_to_string(path).map_err(|source| JobError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    serde_json::from_str(&text).map_err(|source| JobError::Corrupted {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Запускает обработчики очереди заданий
+pub fn spawn_workers(state: Arc<AppState>, jobs: Arc<JobManager>, queue: JobQueue, workers: usize) {
+    let queue = Arc::new(tokio::sync::Mutex::new(queue));
+
+    for _ in 0..workers.max(1) {
+        let (state, jobs, queue) = (state.clone(), jobs.clone(), queue.clone());
+        tokio::spawn(async move {
+            loop {
+                let Some(id) = queue.lock().await.recv().await else {
+                    break;
+                };
+                run_job(&state, &jobs, &id).await;
+            }
+        });
+    }
+
+    info!("Запущено обработчиков заданий: {}", workers.max(1));
+}
+
+/// Выполняет задание и сохраняет результат или ошибку
+async fn run_job(state: &Arc<AppState>, jobs: &Arc<JobManager>, id: &str) {
+    let Some(job) = jobs.get(id) else {
+        return;
+    };
+    info!("Задание {}: начата обработка", id);
+    jobs.update(id, |job| {
+        job.status = JobStatus::Running;
+        job.progress = 0.0;
+    });
+
+    match process(state, jobs, &job).await {
+        Ok(result) => {
+            info!("Задание {}: готово, {:.0} сек аудио", id, result.duration);
+            jobs.update(id, |job| {
+                job.status = JobStatus::Completed;
+                job.progress = 1.0;
+                job.result = Some(result);
+            });
+        }
+        Err(e) => {
+            error!("Задание {}: ошибка: {}", id, e);
+            jobs.update(id, |job| {
+                job.status = JobStatus::Failed;
+                job.error = Some(e);
+            });
+        }
+    }
+
+    if let Err(e) = tokio::fs::remove_file(jobs.audio_path(id)).await {
+        warn!("Не удалось удалить аудио задания {}: {}", id, e);
+    }
+}
+
+/// Распознаёт аудио задания по участкам речи
+async fn process(state: &Arc<AppState>, jobs: &Arc<JobManager>, job: &Job) -> Result<JobResult, String> {
+    let model = state
+        .whisper_model
+        .clone()
+        .ok_or_else(|| "Whisper модель не загружена".to_string())?;
+
+    let audio_path = jobs.audio_path(&job.id);
+    let spec = PcmSpec::from_client(job.sample_rate, job.channels).map_err(|e| e.to_string())?;
+
+    // Декодирование - CPU работа, выносим из async потока; ресемплинг и
+    // признаки VAD считаются частями параллельно в пуле rayon. Длительность
+    // проверяется до того, как всё аудио окажется в памяти
+    let vad = state.vad.clone();
+    let max_audio_secs = jobs.max_audio_secs;
+    let (pcm, segments) = tokio::task::spawn_blocking(move || {
+        let decoded = audio::decode_file(&audio_path, spec, max_audio_secs)?;
+        let pcm = resample::resample(&decoded.samples, decoded.sample_rate, WHISPER_SAMPLE_RATE);
+        let segments = vad.segments(&pcm);
+        Ok::<_, audio::AudioError>((pcm, segments))
+    })
+    .await
+    .map_err(|e| format!("Ошибка задачи декодирования: {}", e))?
+    .map_err(|e| e.to_string())?;
+
+    let duration = pcm.len() as f64 / WHISPER_SAMPLE_RATE as f64;
+    if segments.is_empty() {
+        return Ok(JobResult {
+            text: String::new(),
+            duration,
+            transcript: Transcript::default(),
+        });
+    }
+
+    let total = segments.len();
+    let done = Arc::new(AtomicUsize::new(0));
+    let report = {
+        let (jobs, id, done) = (jobs.clone(), job.id.clone(), done.clone());
+        move || {
+            let finished = done.fetch_add(1, Ordering::Relaxed) + 1;
+            jobs.update(&id, |job| job.progress = finished as f32 / total as f32);
+        }
+    };
+
+    // Словарь и сниппеты читаются при обработке: задание видит последние правки
+    let owners = Owners {
+        user: job.user.clone(),
+        workspace: job.workspace.clone(),
+    };
+    let mut context = state.postprocess_context(&owners);
+    let mut options = TranscribeOptions {
+        language: job.language,
+        task: job.task,
+        prompt: context.prompt(None),
+        ..TranscribeOptions::default()
+    };
+
+    // Язык определяется по первому участку и фиксируется для остальных
+    let first = transcribe_segment(state, &model, &pcm[segments[0].clone()], &options, &context).await?;
+    report();
+    if options.language == Language::Auto {
+        options.language = first
+            .0
+            .language
+            .as_deref()
+            .and_then(Language::from_code)
+            .unwrap_or_default();
+    }
+    context.language = options.language.code().map(str::to_string);
+
+    // Остальные участки параллельно, не больше размера пула Whisper
+    let (pcm, options, context) = (&pcm, &options, &context);
+    let (model, report) = (&model, &report);
+    let rest: Vec<_> = futures::stream::iter(segments[1..].to_vec())
+        .map(|range| async move {
+            let result = transcribe_segment(state, model, &pcm[range], options, context).await;
+            report();
+            result
+        })
+        .buffered(model.pool_stats().size.max(1))
+        .try_collect()
+        .await?;
+
+    let mut transcript = Transcript::default();
+    let mut texts = Vec::with_capacity(total);
+    for (range, (part, text)) in segments.iter().zip(std::iter::once(first).chain(rest)) {
+        transcript.append(part, range.start as f64 / WHISPER_SAMPLE_RATE as f64);
+        if !text.is_empty() {
+            texts.push(text);
+        }
+    }
+
+    Ok(JobResult {
+        text: texts.join(" "),
+        duration,
+        transcript,
+    })
+}
+
+/// Распознаёт и постобрабатывает один участок речи
+///
+/// Задания работают в фоне, поэтому «сервер занят» не ошибка: участок
+/// повторно встаёт в очередь пула Whisper.
+async fn transcribe_segment(
+    state: &AppState,
+    model: &WhisperModel,
+    samples: &[f32],
+    options: &TranscribeOptions,
+    context: &PostProcessContext,
+) -> Result<(Transcript, String), String> {
+    let transcript = loop {
+        match model.transcribe(samples, options).await {
+            Ok(transcript) => break transcript,
+            Err(TranscribeError::Busy(_)) => tokio::time::sleep(BUSY_RETRY_DELAY).await,
+            Err(e) => return Err(e.to_string()),
+        }
+    };
+
+    let text = if transcript.is_empty() {
+        String::new()
+    } else {
+        state.post_process_detailed(transcript.text(), context).await.text
+    };
+    Ok((transcript, text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(dir: &Path) -> JobsConfig {
+        JobsConfig {
+            dir: dir.to_path_buf(),
+            ..JobsConfig::default()
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("alfavoice-jobs-{}-{}", name, uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_submit_persists_and_queues() {
+        let dir = temp_dir("submit");
+        let (jobs, mut queue) = JobManager::open(&config(&dir)).unwrap();
+        let mut events = jobs.subscribe();
+
+        let job = jobs.submit(b"audio", &TranscribeOptions::default(), None, None, &Owners::default()).await.unwrap();
+        assert_eq!(queue.recv().await.as_deref(), Some(job.id.as_str()));
+        assert_eq!(std::fs::read(jobs.audio_path(&job.id)).unwrap(), b"audio");
+        assert_eq!(read_job(&jobs.job_path(&job.id)).unwrap().status, JobStatus::Queued);
+
+        jobs.update(&job.id, |job| job.progress = 0.5);
+        let event = events.recv().await.unwrap();
+        assert_eq!(event.id, job.id);
+        assert_eq!(event.progress, 0.5);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_jobs_are_requeued() {
+        let dir = temp_dir("restore");
+        let (running, lost, finished) = {
+            let (jobs, _queue) = JobManager::open(&config(&dir)).unwrap();
+            let options = TranscribeOptions::default();
+            let running = jobs.submit(b"a", &options, None, None, &Owners::default()).await.unwrap();
+            let lost = jobs.submit(b"b", &options, None, None, &Owners::default()).await.unwrap();
+            let finished = jobs.submit(b"c", &options, None, None, &Owners::default()).await.unwrap();
+
+            jobs.update(&running.id, |job| job.status = JobStatus::Running);
+            std::fs::remove_file(jobs.audio_path(&lost.id)).unwrap();
+            jobs.update(&finished.id, |job| job.status = JobStatus::Completed);
+            (running.id, lost.id, finished.id)
+        };
+
+        // Повторное открытие каталога - как после перезапуска сервера
+        let (jobs, mut queue) = JobManager::open(&config(&dir)).unwrap();
+        assert_eq!(queue.recv().await, Some(running.clone()));
+        assert!(queue.try_recv().is_err());
+
+        assert_eq!(jobs.get(&running).unwrap().status, JobStatus::Queued);
+        assert_eq!(jobs.get(&lost).unwrap().status, JobStatus::Failed);
+        assert_eq!(jobs.get(&finished).unwrap().status, JobStatus::Completed);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -2,18 +2,35 @@
 //!
 //! Этот модуль предоставляет функциональность для загрузки и использования
 //! моделей Qwen для исправления ошибок распознавания речи и форматирования текста.
+//!
+//! Модель Qwen2 (и совместимая Qwen1.5) загружается из квантованного файла
+//! `*.gguf` или из каталога в формате HuggingFace: `config.json`,
+//! `tokenizer.json` и веса `*.safetensors`. Генерация выполняется на CPU.
 
-use std::path::Path;
+#[cfg(feature = "llm")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "llm")]
 use std::sync::Arc;
+#[cfg(feature = "llm")]
 use tokio::sync::Mutex;
-use tracing::{info, error, warn, debug};
+use tracing::{info, warn, debug};
+#[cfg(feature = "llm")]
+use tracing::error;
 
 #[cfg(feature = "llm")]
-use candle_core::{Device, Tensor};
+use candle_core::{DType, Device, Tensor};
 #[cfg(feature = "llm")]
 use candle_nn::VarBuilder;
 #[cfg(feature = "llm")]
-use candle_transformers::models::qwen2::{Config as QwenConfig, Qwen2};
+use candle_transformers::generation::LogitsProcessor;
+#[cfg(feature = "llm")]
+use candle_core::quantized::gguf_file;
+#[cfg(feature = "llm")]
+use candle_transformers::models::qwen2::{Config as QwenConfig, ModelForCausalLM};
+#[cfg(feature = "llm")]
+use candle_transformers::models::quantized_qwen2;
+
+use crate::config::LlmConfig;
 
 /// Ошибки модуля LLM
 #[derive(Debug)]
@@ -43,22 +60,80 @@ impl std::fmt::Display for LlmError {
 
 impl std::error::Error for LlmError {}
 
+/// Токены конца ответа в chat template Qwen
+#[cfg(feature = "llm")]
+const STOP_TOKENS: [&str; 2] = ["<|im_end|>", "<|endoftext|>"];
+
+/// Системный промпт коррекции распознанного текста
+#[cfg_attr(not(feature = "llm"), allow(dead_code))]
+const SYSTEM_PROMPT: &str = "Ты исправляешь ошибки распознавания речи. \
+    Расставь знаки препинания и заглавные буквы, исправь явно неверно распознанные слова. \
+    Не добавляй ничего от себя, не пересказывай и не меняй смысл. \
+    Ответь только исправленным текстом.";
+
+/// Ответ на исправление не длиннее удвоенного текста
+const CORRECTION_GROWTH: usize = 2;
+
+/// Переписанный текст может быть заметно длиннее (письмо, список)
+const REWRITE_GROWTH: usize = 4;
+
+/// Инструкция переписывания текста в стиле
+#[derive(Debug, Clone, Copy)]
+pub struct Rewrite<'a> {
+    /// Системный промпт стиля
+    pub instructions: &'a str,
+    /// Температура вместо `llm.temperature`
+    pub temperature: Option<f64>,
+}
+
 /// Обёртка для LLM модели Qwen
 pub struct LlmModel {
     /// Внутренняя реализация модели (опциональная, зависит от feature)
     #[cfg(feature = "llm")]
     model: Arc<Mutex<Option<QwenModelInner>>>,
-    
+
+    /// Параметры генерации
+    #[cfg(feature = "llm")]
+    config: LlmConfig,
+
     /// Флаг включения постобработки
     enabled: bool,
 }
 
+/// Веса Qwen2: полные из safetensors или квантованные из GGUF
+#[cfg(feature = "llm")]
+enum QwenWeights {
+    Full(ModelForCausalLM),
+    Quantized(quantized_qwen2::ModelWeights),
+}
+
+#[cfg(feature = "llm")]
+impl QwenWeights {
+    /// Логиты последней позиции, форма `(vocab)`
+    ///
+    /// Квантованная модель сбрасывает KV-кэш сама при `offset == 0`.
+    fn forward(&mut self, input: &Tensor, offset: usize) -> candle_core::Result<Tensor> {
+        let logits = match self {
+            Self::Full(model) => {
+                if offset == 0 {
+                    model.clear_kv_cache();
+                }
+                model.forward(input, offset)?.squeeze(1)?
+            }
+            Self::Quantized(model) => model.forward(input, offset)?,
+        };
+        logits.squeeze(0)?.to_dtype(DType::F32)
+    }
+}
+
 /// Внутренняя реализация модели Qwen (только при включённом feature "llm")
 #[cfg(feature = "llm")]
 struct QwenModelInner {
-    model: Qwen2,
+    model: QwenWeights,
     tokenizer: tokenizers::Tokenizer,
     device: Device,
+    /// Id токенов, завершающих ответ
+    stop_tokens: Vec<u32>,
 }
 
 impl LlmModel {
@@ -66,57 +141,38 @@ impl LlmModel {
     ///
     /// # Аргументы
     /// * `model_path` - путь к модели (опционально, если None - модель не загружается)
-    /// * `enabled` - включена ли постобработка
-    ///
-    /// # Переменные окружения
-    /// * `LLM_MODEL_PATH` - путь к модели Qwen (если не указан в аргументах)
-    /// * `LLM_ENABLED` - включить/выключить постобработку (true/false)
-    pub fn new(model_path: Option<&str>, enabled: bool) -> Result<Self, LlmError> {
-        // Проверяем переменную окружения для включения/выключения
-        let enabled = std::env::var("LLM_ENABLED")
-            .ok()
-            .and_then(|v| v.parse::<bool>().ok())
-            .unwrap_or(enabled);
-
+    /// * `enabled` - включена ли постобработка (`postprocessing.llm_enabled`)
+    /// * `config` - параметры генерации (секция `[llm]`)
+    pub fn new(model_path: Option<&str>, enabled: bool, config: LlmConfig) -> Result<Self, LlmError> {
         if !enabled {
-            info!("LLM постобработка отключена (LLM_ENABLED=false)");
-            return Ok(Self {
-                #[cfg(feature = "llm")]
-                model: Arc::new(Mutex::new(None)),
-                enabled: false,
-            });
+            info!("LLM постобработка отключена (postprocessing.llm_enabled = false)");
+            return Ok(Self::disabled(config));
         }
 
         // Проверяем, включён ли feature "llm"
         #[cfg(not(feature = "llm"))]
         {
+            let _ = model_path;
             warn!("Feature 'llm' не включён. Постобработка LLM недоступна.");
             warn!("Для включения соберите проект с: cargo build --features llm");
-            return Ok(Self {
-                enabled: false,
-            });
+            Ok(Self::disabled(config))
         }
 
         #[cfg(feature = "llm")]
         {
-            // Получаем путь к модели
-            let model_path = model_path.or_else(|| {
-                std::env::var("LLM_MODEL_PATH").ok()
-            });
-
             if let Some(path) = model_path {
                 info!("Загрузка LLM модели из: {}", path);
-                
+
                 // Проверяем существование модели
                 if !Path::new(path).exists() {
                     let error_msg = format!(
                         "Модель LLM не найдена по пути: {}\n\n\
                         Для работы с LLM необходимо:\n\
-                        1. Скачать модель Qwen (например, Qwen1.5-0.5B-Chat-GGUF)\n\
-                        2. Указать путь через переменную окружения LLM_MODEL_PATH\n\n\
+                        1. Скачать модель Qwen (например, Qwen2-0.5B-Instruct)\n\
+                        2. Указать путь в models.llm_model (alfavoice.toml) или ALFAVOICE_LLM_MODEL\n\n\
                         Рекомендуемые модели:\n\
-                        - Qwen1.5-0.5B-Chat: ~0.5GB (минимум VRAM)\n\
-                        - Qwen1.5-1.8B-Chat: ~1.5GB (баланс качество/VRAM)\n\
+                        - Qwen1.5-0.5B-Chat: ~0.5GB (минимум памяти)\n\
+                        - Qwen1.5-1.8B-Chat: ~1.5GB (баланс качество/память)\n\
                         - Qwen2-1.5B-Instruct: ~1.2GB (хорошее качество)\n\n\
                         Для скачивания моделей используйте HuggingFace:\n\
                         https://huggingface.co/Qwen",
@@ -126,75 +182,146 @@ impl LlmModel {
                     return Err(LlmError::LoadError(error_msg));
                 }
 
-                // Загружаем модель (будет реализовано ниже)
                 match Self::load_model(path) {
                     Ok(inner) => {
                         info!("LLM модель успешно загружена");
                         Ok(Self {
                             model: Arc::new(Mutex::new(Some(inner))),
+                            config,
                             enabled: true,
                         })
                     }
                     Err(e) => {
                         error!("Не удалось загрузить LLM модель: {}", e);
                         // Возвращаем модель в отключённом состоянии
-                        Ok(Self {
-                            model: Arc::new(Mutex::new(None)),
-                            enabled: false,
-                        })
+                        Ok(Self::disabled(config))
                     }
                 }
             } else {
                 info!("LLM модель не указана. Постобработка отключена.");
-                info!("Для включения установите переменную окружения LLM_MODEL_PATH или передайте путь при создании.");
-                Ok(Self {
-                    model: Arc::new(Mutex::new(None)),
-                    enabled: false,
-                })
+                info!("Для включения укажите models.llm_model в alfavoice.toml или ALFAVOICE_LLM_MODEL.");
+                Ok(Self::disabled(config))
             }
         }
     }
 
-    /// Загружает модель Qwen из указанного пути
+    /// Модель без загруженных весов: текст возвращается без изменений
+    fn disabled(config: LlmConfig) -> Self {
+        #[cfg(not(feature = "llm"))]
+        let _ = config;
+
+        Self {
+            #[cfg(feature = "llm")]
+            model: Arc::new(Mutex::new(None)),
+            #[cfg(feature = "llm")]
+            config,
+            enabled: false,
+        }
+    }
+
+    /// Загружает модель Qwen2: GGUF файл или каталог HuggingFace
     #[cfg(feature = "llm")]
     fn load_model(model_path: &str) -> Result<QwenModelInner, LlmError> {
-        use candle_core::DType;
-        
-        // Определяем устройство (GPU или CPU)
-        let device = if cfg!(feature = "cuda") {
-            info!("Используем CUDA для LLM");
-            Device::new_cuda(0).unwrap_or(Device::Cpu)
+        let path = Path::new(model_path);
+        let is_gguf = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("gguf"));
+
+        let (model, tokenizer) = if path.is_file() && is_gguf {
+            Self::load_gguf(path)?
+        } else if path.is_dir() {
+            Self::load_safetensors(path)?
         } else {
-            info!("Используем CPU для LLM");
-            Device::Cpu
+            return Err(LlmError::LoadError(format!(
+                "{}: ожидается файл *.gguf или каталог с config.json, tokenizer.json и *.safetensors",
+                path.display()
+            )));
         };
 
-        // Загружаем конфигурацию модели
-        let config_path = Path::new(model_path).join("config.json");
-        if !config_path.exists() {
+        let stop_tokens: Vec<u32> = STOP_TOKENS
+            .iter()
+            .filter_map(|token| tokenizer.token_to_id(token))
+            .collect();
+        if stop_tokens.is_empty() {
             return Err(LlmError::LoadError(
-                format!("Файл конфигурации не найден: {}", config_path.display())
+                "В токенизаторе нет токенов <|im_end|> и <|endoftext|>: это не chat модель Qwen".to_string(),
             ));
         }
 
+        Ok(QwenModelInner {
+            model,
+            tokenizer,
+            device: Device::Cpu,
+            stop_tokens,
+        })
+    }
+
+    /// Квантованная модель из GGUF: гиперпараметры и токенизатор берутся
+    /// из метаданных файла
+    #[cfg(feature = "llm")]
+    fn load_gguf(path: &Path) -> Result<(QwenWeights, tokenizers::Tokenizer), LlmError> {
+        let load_error = |e: candle_core::Error| {
+            LlmError::LoadError(format!("Некорректный GGUF {}: {}", path.display(), e))
+        };
+
+        let mut file = std::fs::File::open(path)
+            .map_err(|e| LlmError::LoadError(format!("Не удалось открыть {}: {}", path.display(), e)))?;
+        let content = gguf_file::Content::read(&mut file).map_err(load_error)?;
+
+        let architecture = content
+            .metadata
+            .get("general.architecture")
+            .and_then(|value| value.to_string().ok())
+            .cloned()
+            .unwrap_or_default();
+        if architecture != "qwen2" {
+            return Err(LlmError::LoadError(format!(
+                "{}: архитектура {:?} не поддерживается, нужна qwen2",
+                path.display(),
+                architecture
+            )));
+        }
+
+        let tokenizer = gguf_tokenizer(&content.metadata)?;
+        info!(
+            "Загрузка GGUF модели Qwen2: тензоров {}, словарь {} токенов",
+            content.tensor_infos.len(),
+            tokenizer.get_vocab_size(true)
+        );
+
+        let model = quantized_qwen2::ModelWeights::from_gguf(content, &mut file, &Device::Cpu).map_err(load_error)?;
+        Ok((QwenWeights::Quantized(model), tokenizer))
+    }
+
+    /// Модель из каталога HuggingFace: `config.json`, `tokenizer.json`, `*.safetensors`
+    #[cfg(feature = "llm")]
+    fn load_safetensors(dir: &Path) -> Result<(QwenWeights, tokenizers::Tokenizer), LlmError> {
+        // Загружаем конфигурацию модели
+        let config_path = dir.join("config.json");
+        let config_text = std::fs::read_to_string(&config_path).map_err(|e| {
+            LlmError::LoadError(format!("Не удалось прочитать {}: {}", config_path.display(), e))
+        })?;
+        let config: QwenConfig = serde_json::from_str(&config_text).map_err(|e| {
+            LlmError::LoadError(format!("Некорректный {}: {}", config_path.display(), e))
+        })?;
+
         // Загружаем токенизатор
-        let tokenizer_path = Path::new(model_path);
-        let tokenizer = tokenizers::Tokenizer::from_file(tokenizer_path.join("tokenizer.json"))
+        let tokenizer = tokenizers::Tokenizer::from_file(dir.join("tokenizer.json"))
             .map_err(|e| LlmError::LoadError(format!("Не удалось загрузить токенизатор: {}", e)))?;
 
-        // TODO: Полная реализация загрузки модели Qwen
-        // Это упрощённая версия - в production нужно:
-        // 1. Загрузить веса модели из safetensors или GGUF
-        // 2. Создать VarBuilder с правильным устройством
-        // 3. Инициализировать Qwen2 модель
-
-        warn!("Полная загрузка модели Qwen ещё не реализована. Используется заглушка.");
-        
-        // Временная заглушка для компиляции
-        // В production здесь будет реальная загрузка модели
-        Err(LlmError::LoadError(
-            "Полная загрузка модели Qwen ещё не реализована".to_string()
-        ))
+        // Веса: один файл или несколько частей
+        let weights = safetensors_files(dir)?;
+        info!("Загрузка весов Qwen2: {} файл(ов), слоёв: {}", weights.len(), config.num_hidden_layers);
+
+        // Только CPU: F32 поддерживается всеми операциями и не требует GPU.
+        // SAFETY: файлы весов отображаются в память только для чтения и не
+        // изменяются, пока модель загружена
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&weights, DType::F32, &Device::Cpu) }
+            .map_err(|e| LlmError::LoadError(format!("Не удалось прочитать веса: {}", e)))?;
+        let model = ModelForCausalLM::new(&config, vb)
+            .map_err(|e| LlmError::LoadError(format!("Не удалось создать модель Qwen2: {}", e)))?;
+
+        Ok((QwenWeights::Full(model), tokenizer))
     }
 
     /// Выполняет постобработку текста
@@ -211,37 +338,86 @@ impl LlmModel {
             debug!("LLM постобработка отключена, возвращаем исходный текст");
             return Ok(text.to_string());
         }
+        if text.trim().is_empty() {
+            return Ok(text.to_string());
+        }
 
+        match self.complete(text, SYSTEM_PROMPT, None, CORRECTION_GROWTH).await? {
+            Some(corrected) if accept_correction(text, &corrected) => Ok(corrected),
+            Some(_) => {
+                warn!("Ответ LLM отличается от исходного текста по длине, используем исходный текст");
+                Ok(text.to_string())
+            }
+            None => Ok(text.to_string()),
+        }
+    }
+
+    /// Переписывает текст по инструкции стиля
+    ///
+    /// `None`, если LLM отключена, не загружена или текст не помещается в
+    /// `llm.max_tokens`: тогда стиль применяется без LLM.
+    pub async fn rewrite(&self, text: &str, rewrite: &Rewrite<'_>) -> Result<Option<String>, LlmError> {
+        if !self.enabled || text.trim().is_empty() {
+            return Ok(None);
+        }
+
+        let rewritten = self
+            .complete(text, rewrite.instructions, rewrite.temperature, REWRITE_GROWTH)
+            .await?;
+        Ok(rewritten.filter(|rewritten| !rewritten.is_empty()))
+    }
+
+    /// Ответ модели на текст с системным промптом
+    ///
+    /// `growth` - во сколько раз ответ может быть длиннее текста (в токенах).
+    async fn complete(
+        &self,
+        text: &str,
+        system: &str,
+        temperature: Option<f64>,
+        growth: usize,
+    ) -> Result<Option<String>, LlmError> {
         #[cfg(not(feature = "llm"))]
         {
+            let _ = (text, system, temperature, growth);
             warn!("Feature 'llm' не включён. Возвращаем исходный текст.");
-            return Ok(text.to_string());
+            Ok(None)
         }
 
         #[cfg(feature = "llm")]
         {
-            let model_guard = self.model.lock().await;
-            
+            let mut model_guard = self.model.clone().lock_owned().await;
             if model_guard.is_none() {
                 warn!("LLM модель не загружена. Возвращаем исходный текст.");
-                return Ok(text.to_string());
+                return Ok(None);
             }
 
-            // Формируем промпт для коррекции
-            let prompt = format!(
-                "Ты - помощник, который исправляет ошибки распознавания речи. \
-                Исправь ошибки, расставь знаки препинания и улучши читаемость следующего текста:\n\n\
-                Текст: {}\n\n\
-                Исправленный текст:",
-                text
-            );
-
-            debug!("Отправляем промпт в LLM (длина: {} символов)", prompt.len());
-
-            // TODO: Реализовать генерацию через загруженную модель
-            // Временная заглушка
-            warn!("Генерация через LLM ещё не реализована. Возвращаем исходный текст.");
-            Ok(text.to_string())
+            debug!("Отправляем текст в LLM (длина: {} символов)", text.len());
+            let (system, text) = (system.to_string(), text.to_string());
+
+            // Генерация - CPU работа на секунды, выносим из async потока
+            let mut config = self.config.clone();
+            if let Some(temperature) = temperature {
+                config.temperature = temperature;
+            }
+            let started = std::time::Instant::now();
+            let completed = tokio::task::spawn_blocking(move || match model_guard.as_mut() {
+                Some(inner) => inner.complete(&system, &text, &config, growth),
+                None => Ok(None),
+            })
+            .await
+            .map_err(|e| LlmError::GenerationError(format!("Ошибка задачи генерации: {}", e)))??;
+
+            match completed {
+                Some(completed) => {
+                    debug!("LLM генерация за {} мс", started.elapsed().as_millis());
+                    Ok(Some(completed.trim().to_string()))
+                }
+                None => {
+                    debug!("Текст длиннее llm.max_tokens, LLM пропущена");
+                    Ok(None)
+                }
+            }
         }
     }
 
@@ -251,13 +427,199 @@ impl LlmModel {
     }
 }
 
+#[cfg(feature = "llm")]
+impl QwenModelInner {
+    /// Генерирует ответ; `None`, если текст не помещается в `max_tokens`
+    ///
+    /// Ответ ограничен длиной текста, умноженной на `growth`, но не больше
+    /// `max_tokens`: исправление примерно равно тексту по длине. Длина текста
+    /// в токенах - разница с тем же промптом без текста, так что системный
+    /// промпт и инструкции стиля любой длины в неё не входят.
+    fn complete(&mut self, system: &str, text: &str, config: &LlmConfig, growth: usize) -> Result<Option<String>, LlmError> {
+        let prompt_tokens = self.tokenize(&chat_prompt(system, text))?;
+        let template_tokens = self.tokenize(&chat_prompt(system, ""))?.len();
+
+        let text_tokens = prompt_tokens.len().saturating_sub(template_tokens);
+        if text_tokens > config.max_tokens {
+            return Ok(None);
+        }
+        let max_new = (text_tokens * growth + 16).min(config.max_tokens);
+
+        self.generate(prompt_tokens, max_new, config).map(Some)
+    }
+
+    fn tokenize(&self, text: &str) -> Result<Vec<u32>, LlmError> {
+        Ok(self
+            .tokenizer
+            .encode(text, false)
+            .map_err(|e| LlmError::TokenizationError(e.to_string()))?
+            .get_ids()
+            .to_vec())
+    }
+
+    /// Цикл генерации: токены сэмплируются до стоп-токена или `max_new` токенов
+    fn generate(&mut self, prompt_tokens: Vec<u32>, max_new: usize, config: &LlmConfig) -> Result<String, LlmError> {
+        let generation_error = |e: candle_core::Error| LlmError::GenerationError(e.to_string());
+
+        let temperature = (config.temperature > 0.0).then_some(config.temperature);
+        let mut sampler = LogitsProcessor::new(config.seed, temperature, Some(config.top_p));
+
+        let prompt_len = prompt_tokens.len();
+        let mut tokens = prompt_tokens;
+        for index in 0..max_new {
+            // Первый шаг - весь промпт, дальше по одному токену с KV-кэшем
+            let offset = if index == 0 { 0 } else { tokens.len() - 1 };
+            let input = Tensor::new(&tokens[offset..], &self.device)
+                .and_then(|t| t.unsqueeze(0))
+                .map_err(generation_error)?;
+            let logits = self.model.forward(&input, offset).map_err(generation_error)?;
+
+            let next = sampler.sample(&logits).map_err(generation_error)?;
+            if self.stop_tokens.contains(&next) {
+                break;
+            }
+            tokens.push(next);
+        }
+
+        self.tokenizer
+            .decode(&tokens[prompt_len..], true)
+            .map_err(|e| LlmError::TokenizationError(e.to_string()))
+    }
+}
+
+/// Файлы весов модели: `model.safetensors` или части из `model.safetensors.index.json`
+#[cfg(feature = "llm")]
+fn safetensors_files(dir: &Path) -> Result<Vec<PathBuf>, LlmError> {
+    let single = dir.join("model.safetensors");
+    if single.exists() {
+        return Ok(vec![single]);
+    }
+
+    let index_path = dir.join("model.safetensors.index.json");
+    let index_text = std::fs::read_to_string(&index_path).map_err(|_| {
+        LlmError::LoadError(format!(
+            "В {} нет model.safetensors и model.safetensors.index.json",
+            dir.display()
+        ))
+    })?;
+    let index: serde_json::Value = serde_json::from_str(&index_text)
+        .map_err(|e| LlmError::LoadError(format!("Некорректный {}: {}", index_path.display(), e)))?;
+
+    let mut files: Vec<PathBuf> = index["weight_map"]
+        .as_object()
+        .ok_or_else(|| LlmError::LoadError(format!("В {} нет weight_map", index_path.display())))?
+        .values()
+        .filter_map(|file| file.as_str())
+        .map(|file| dir.join(file))
+        .collect();
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// Pre-tokenizer Qwen2 (`tokenizer.ggml.pre = qwen2`), как в `tokenizer.json` модели
+#[cfg(feature = "llm")]
+const QWEN2_SPLIT_REGEX: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+";
+
+/// Тип служебного токена в `tokenizer.ggml.token_type`
+#[cfg(feature = "llm")]
+const GGUF_TOKEN_CONTROL: i32 = 3;
+
+/// Собирает byte-level BPE токенизатор из метаданных GGUF
+///
+/// В GGUF словарь (`tokenizer.ggml.tokens`) и слияния (`tokenizer.ggml.merges`)
+/// хранятся в byte-level кодировке GPT-2, как и в `tokenizer.json`, поэтому
+/// результат токенизации совпадает с оригинальным токенизатором Qwen2.
+#[cfg(feature = "llm")]
+fn gguf_tokenizer(
+    metadata: &std::collections::HashMap<String, gguf_file::Value>,
+) -> Result<tokenizers::Tokenizer, LlmError> {
+    use tokenizers::decoders::byte_level::ByteLevel as ByteLevelDecoder;
+    use tokenizers::models::bpe::BPE;
+    use tokenizers::normalizers::unicode::NFC;
+    use tokenizers::pre_tokenizers::byte_level::ByteLevel;
+    use tokenizers::pre_tokenizers::sequence::Sequence;
+    use tokenizers::pre_tokenizers::split::{Split, SplitPattern};
+    use tokenizers::{AddedToken, SplitDelimiterBehavior};
+
+    let tokenizer_error = |e: &dyn std::fmt::Display| LlmError::LoadError(format!("Токенизатор GGUF: {}", e));
+    let strings = |key: &str| -> Result<Vec<String>, LlmError> {
+        let values = metadata
+            .get(key)
+            .ok_or_else(|| tokenizer_error(&format!("нет {}", key)))?
+            .to_vec()
+            .map_err(|e| tokenizer_error(&e))?;
+        values
+            .iter()
+            .map(|value| value.to_string().cloned().map_err(|e| tokenizer_error(&e)))
+            .collect()
+    };
+
+    let model = metadata
+        .get("tokenizer.ggml.model")
+        .and_then(|value| value.to_string().ok())
+        .map(String::as_str);
+    if model != Some("gpt2") {
+        return Err(tokenizer_error(&format!("модель {:?} не поддерживается, нужна gpt2 (BPE)", model)));
+    }
+
+    let tokens = strings("tokenizer.ggml.tokens")?;
+    let merges = strings("tokenizer.ggml.merges")?
+        .into_iter()
+        .filter_map(|merge| merge.split_once(' ').map(|(a, b)| (a.to_string(), b.to_string())))
+        .collect();
+    let vocab = tokens.iter().enumerate().map(|(id, token)| (token.clone(), id as u32)).collect();
+
+    let bpe = BPE::builder()
+        .vocab_and_merges(vocab, merges)
+        .build()
+        .map_err(|e| tokenizer_error(&e))?;
+    let split = Split::new(SplitPattern::Regex(QWEN2_SPLIT_REGEX.to_string()), SplitDelimiterBehavior::Isolated, false)
+        .map_err(|e| tokenizer_error(&e))?;
+
+    let mut tokenizer = tokenizers::Tokenizer::new(bpe);
+    tokenizer
+        .with_normalizer(Some(NFC))
+        .with_pre_tokenizer(Some(Sequence::new(vec![
+            split.into(),
+            ByteLevel::new(false, false, false).into(),
+        ])))
+        .with_decoder(Some(ByteLevelDecoder::default()));
+
+    // Служебные токены (<|im_start|>, <|im_end|>, ...) не разбиваются BPE
+    if let Some(types) = metadata.get("tokenizer.ggml.token_type").and_then(|value| value.to_vec().ok()) {
+        let special: Vec<AddedToken> = tokens
+            .iter()
+            .zip(types)
+            .filter(|(_, kind)| kind.to_i32().is_ok_and(|kind| kind == GGUF_TOKEN_CONTROL))
+            .map(|(token, _)| AddedToken::from(token.clone(), true))
+            .collect();
+        tokenizer.add_special_tokens(&special);
+    }
+
+    Ok(tokenizer)
+}
+
+/// Применяет chat template Qwen (ChatML) к системному и пользовательскому сообщению
+#[cfg_attr(not(feature = "llm"), allow(dead_code))]
+fn chat_prompt(system: &str, user: &str) -> String {
+    format!(
+        "<|im_start|>system\n{}<|im_end|>\n<|im_start|>user\n{}<|im_end|>\n<|im_start|>assistant\n",
+        system, user
+    )
+}
+
+/// Похож ли ответ модели на исправление, а не на пересказ или обрыв
+#[cfg_attr(not(feature = "llm"), allow(dead_code))]
+fn accept_correction(original: &str, corrected: &str) -> bool {
+    let original = original.chars().count();
+    let corrected = corrected.chars().count();
+    corrected > 0 && corrected * 2 >= original && corrected <= original * 2 + 20
+}
+
 impl Default for LlmModel {
     fn default() -> Self {
-        Self::new(None, false).unwrap_or_else(|_| Self {
-            #[cfg(feature = "llm")]
-            model: Arc::new(Mutex::new(None)),
-            enabled: false,
-        })
+        Self::disabled(LlmConfig::default())
     }
 }
 
@@ -267,20 +629,20 @@ mod tests {
 
     #[test]
     fn test_llm_model_disabled() {
-        let model = LlmModel::new(None, false).unwrap();
+        let model = LlmModel::new(None, false, LlmConfig::default()).unwrap();
         assert!(!model.is_enabled());
     }
 
     #[test]
     fn test_llm_model_enabled_but_no_path() {
-        let model = LlmModel::new(None, true).unwrap();
+        let model = LlmModel::new(None, true, LlmConfig::default()).unwrap();
         // Модель должна быть отключена, если путь не указан
         assert!(!model.is_enabled());
     }
 
     #[tokio::test]
     async fn test_post_process_disabled() {
-        let model = LlmModel::new(None, false).unwrap();
+        let model = LlmModel::new(None, false, LlmConfig::default()).unwrap();
         let text = "тестовый текст";
         let result = model.post_process(text).await.unwrap();
         assert_eq!(result, text);
@@ -288,9 +650,46 @@ mod tests {
 
     #[tokio::test]
     async fn test_post_process_empty_text() {
-        let model = LlmModel::new(None, false).unwrap();
+        let model = LlmModel::new(None, false, LlmConfig::default()).unwrap();
         let text = "";
         let result = model.post_process(text).await.unwrap();
         assert_eq!(result, text);
     }
+
+    #[test]
+    fn test_chat_prompt() {
+        let prompt = chat_prompt("Исправь текст.", "привет как дела");
+        assert!(prompt.starts_with("<|im_start|>system\nИсправь текст.<|im_end|>\n"));
+        assert!(prompt.contains("<|im_start|>user\nпривет как дела<|im_end|>\n"));
+        assert!(prompt.ends_with("<|im_start|>assistant\n"));
+    }
+
+    #[test]
+    fn test_accept_correction() {
+        assert!(accept_correction("привет как дела", "Привет, как дела?"));
+        assert!(!accept_correction("привет как дела", ""));
+        assert!(!accept_correction("привет как дела", "Конечно! Вот исправленный текст, который вы прислали: Привет, как дела?"));
+    }
+
+    #[cfg(feature = "llm")]
+    #[test]
+    fn test_gguf_tokenizer() {
+        use gguf_file::Value;
+
+        let strings = |items: &[&str]| Value::Array(items.iter().map(|s| Value::String(s.to_string())).collect());
+        let metadata = std::collections::HashMap::from([
+            ("tokenizer.ggml.model".to_string(), Value::String("gpt2".to_string())),
+            ("tokenizer.ggml.tokens".to_string(), strings(&["a", "b", "ab", "Ġ", "Ġab", "<|im_end|>"])),
+            ("tokenizer.ggml.merges".to_string(), strings(&["a b", "Ġ ab"])),
+            (
+                "tokenizer.ggml.token_type".to_string(),
+                Value::Array([1, 1, 1, 1, 1, 3].into_iter().map(Value::I32).collect()),
+            ),
+        ]);
+
+        let tokenizer = gguf_tokenizer(&metadata).unwrap();
+        let encoding = tokenizer.encode("ab ab<|im_end|>", false).unwrap();
+        assert_eq!(encoding.get_ids(), &[2, 4, 5]);
+        assert_eq!(tokenizer.decode(&[2, 4, 5], true).unwrap(), "ab ab");
+    }
 }
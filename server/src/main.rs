@@ -1,30 +1,56 @@
 mod ws;
+mod api;
+mod openai;
+mod format;
+mod jobs;
+mod auth;
+mod store;
+mod phrases;
+mod dictionary;
+mod snippets;
+mod styles;
+mod voice_commands;
+mod formatting;
+mod tls;
 mod state;
+mod audio;
+mod resample;
+mod session;
+mod pool;
+mod vad;
+mod transcript;
 mod whisper;
 mod llm;
+mod postprocess;
 mod config;
+mod cli;
+mod commands;
 
 #[cfg(feature = "nlp")]
 mod nlp;
 
 use axum::{
-    extract::State,
+    extract::{DefaultBodyLimit, State},
+    middleware,
     response::Json,
-    routing::get,
+    routing::{get, post, put},
     Router,
 };
-use std::net::SocketAddr;
+use clap::Parser;
 use std::sync::Arc;
 use tokio::net::TcpListener;
 use tower_http::cors::CorsLayer;
 use tracing::{info, error, warn};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber;
-use rayon;
 
 #[derive(serde::Serialize)]
 struct HealthResponse {
     status: String,
     version: String,
+    /// Очередь и занятость пула Whisper (если модель загружена)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    whisper_pool: Option<pool::PoolStats>,
 }
 
 /// Логирует характеристики системы
@@ -74,7 +100,8 @@ fn log_system_info() {
 /// Настраивает Rayon global thread pool для параллельных задач
 ///
 /// Примечание: Whisper использует свою собственную систему потоков через n_threads.
-/// Rayon предназначен для других параллельных задач (например, пакетная обработка).
+/// В пуле Rayon частями считаются ресемплинг и признаки VAD длинного аудио
+/// (задания `/v1/jobs`, загрузки файлов).
 fn setup_rayon_thread_pool() {
     let available_threads = std::thread::available_parallelism()
         .map(|n| n.get())
@@ -95,31 +122,87 @@ fn setup_rayon_thread_pool() {
 }
 
 /// Обработчик проверки здоровья сервера
-async fn health_check() -> Json<HealthResponse> {
+async fn health_check(State(state): State<Arc<state::AppState>>) -> Json<HealthResponse> {
     Json(HealthResponse {
         status: "ok".to_string(),
         version: env!("CARGO_PKG_VERSION").to_string(),
+        whisper_pool: state.whisper_model.as_ref().map(|model| model.pool_stats()),
     })
 }
 
 #[tokio::main]
 async fn main() {
+    let cli = cli::Cli::parse();
+
+    // Загружаем конфигурацию: файл -> переменные окружения -> флаги
+    let (app_config, config_source) = match config::AppConfig::load(cli.config.as_deref(), &cli.overrides()) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    // Сервер пишет логи в stdout, остальные команды - в stderr,
+    // чтобы их вывод можно было перенаправить
+    let command = cli.command.unwrap_or(cli::Command::Serve);
+    let writer = match command {
+        cli::Command::Serve => BoxMakeWriter::new(std::io::stdout),
+        _ => BoxMakeWriter::new(std::io::stderr),
+    };
+
     // Инициализируем логирование
     tracing_subscriber::fmt()
         .with_env_filter(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "alfavoice_server=debug,tower_http=debug,axum=debug".into()),
+                .unwrap_or_else(|_| app_config.logging.filter.as_str().into()),
         )
+        .with_ansi(app_config.logging.ansi)
+        .with_writer(writer)
         .init();
 
+    match &config_source {
+        Some(path) => info!("Конфигурация загружена из {}", path.display()),
+        None => info!("Файл {} не найден, используется конфигурация по умолчанию", config::AppConfig::DEFAULT_PATH),
+    }
+
+    let result = match command {
+        cli::Command::Serve => {
+            serve(app_config).await;
+            Ok(())
+        }
+        cli::Command::CheckModels => commands::check_models(&app_config),
+        cli::Command::Transcribe { file, language, task, json } => {
+            let options = whisper::TranscribeOptions {
+                language,
+                task,
+                ..whisper::TranscribeOptions::default()
+            };
+            commands::transcribe_file(&app_config, &file, &options, json).await
+        }
+        cli::Command::Bench { file, iterations, concurrency } => {
+            log_system_info();
+            commands::bench(&app_config, &file, iterations, concurrency).await
+        }
+        cli::Command::PrintConfig => commands::print_config(&app_config),
+    };
+
+    if let Err(e) = result {
+        error!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Запускает HTTP/WebSocket сервер
+async fn serve(app_config: config::AppConfig) {
     // Логируем характеристики системы
     log_system_info();
 
-    // Настраиваем Rayon global thread pool для Whisper
+    // Настраиваем Rayon global thread pool для ресемплинга и VAD
     setup_rayon_thread_pool();
 
-    // Настраиваем пути к моделям
-    let model_paths = config::ModelPaths::default();
+    // Пути к моделям
+    let model_paths = &app_config.models;
     
     // Проверяем наличие моделей перед запуском
     info!("Проверка наличия моделей...");
@@ -130,11 +213,17 @@ async fn main() {
     }
     info!("Все необходимые модели найдены.");
 
-    // Настраиваем конфигурацию инференса Whisper
-    // Используем дефолтные настройки (оптимальный баланс скорость/качество)
-    let whisper_config = config::WhisperConfig::default();
-    info!("Whisper конфигурация: threads={}, beam_size={}, beam_search={}",
-        whisper_config.n_threads, whisper_config.beam_size, whisper_config.use_beam_search);
+    if let Err(e) = audio::check_codecs() {
+        error!("{}", e);
+        error!("Сервер не может обслуживать браузерных клиентов без декодера Opus.");
+        std::process::exit(1);
+    }
+
+    // Конфигурация инференса Whisper: пресет с переопределениями из секции [whisper]
+    let whisper_config = app_config.whisper.to_config();
+    info!("Whisper конфигурация: preset={:?}, threads={}, beam_size={}, beam_search={}, pool_size={}",
+        app_config.whisper.preset, whisper_config.n_threads, whisper_config.beam_size,
+        whisper_config.use_beam_search, whisper_config.pool_size);
 
     // Загружаем Whisper модель
     let whisper_model = match whisper::WhisperModel::load(&model_paths.whisper_model, Some(whisper_config)) {
@@ -151,7 +240,7 @@ async fn main() {
 
     // Загружаем LLM модель для постобработки
     let llm_model_path = model_paths.llm_model.as_deref();
-    let llm_model = match llm::LlmModel::new(llm_model_path, true) {
+    let llm_model = match llm::LlmModel::new(llm_model_path, app_config.postprocessing.llm_enabled, app_config.llm.clone()) {
         Ok(model) => {
             if model.is_enabled() {
                 info!("LLM модель для постобработки загружена");
@@ -169,8 +258,11 @@ async fn main() {
 
     // Загружаем BERT модель для NLP (если включен feature nlp)
     #[cfg(feature = "nlp")]
-    let bert_model = {
-        let bert_config = nlp::BertConfig::default();
+    let bert_model = if app_config.postprocessing.bert_enabled {
+        let bert_config = nlp::BertConfig {
+            local_model_path: model_paths.punctuation_model.as_ref().map(std::path::PathBuf::from),
+            ..nlp::BertConfig::default()
+        };
         let mut model = nlp::BertModel::new(bert_config);
         
         match model.initialize() {
@@ -184,30 +276,154 @@ async fn main() {
                 None
             }
         }
+    } else {
+        info!("BERT постобработка отключена (postprocessing.bert_enabled = false)");
+        None
     };
 
-    // Создаём состояние приложения
+    // Создаём состояние приложения: модели подключаются до сборки конвейера
+    let mut app_state = state::AppState::new().with_llm_model(llm_model);
+    if let Some(model) = whisper_model {
+        app_state = app_state.with_whisper_model(model);
+    }
     #[cfg(feature = "nlp")]
-    let app_state = Arc::new(state::AppState::with_all_models(whisper_model, llm_model, bert_model));
-    
-    #[cfg(not(feature = "nlp"))]
-    let app_state = Arc::new(state::AppState::with_models(whisper_model, llm_model));
+    if let Some(model) = bert_model {
+        app_state = app_state.with_bert_model(model);
+    }
+
+    let mut app_state = app_state.configure(&app_config);
+
+    // Очередь заданий: без каталога заданий сервер работает, но /v1/jobs недоступен
+    let job_queue = match jobs::JobManager::open(&app_config.jobs) {
+        Ok((manager, queue)) => {
+            info!("Каталог заданий: {}", app_config.jobs.dir.display());
+            app_state = app_state.with_jobs(manager.clone());
+            Some((manager, queue))
+        }
+        Err(e) => {
+            warn!("Очередь заданий отключена: {}", e);
+            None
+        }
+    };
+
+    // Словари, сниппеты и стили: без них распознавание работает без подсказок и замен
+    match dictionary::DictionaryStore::open(&app_config.dictionaries) {
+        Ok(store) => {
+            info!("Каталог словарей: {}", app_config.dictionaries.dir.display());
+            app_state = app_state.with_dictionaries(store);
+        }
+        Err(e) => warn!("Словари отключены: {}", e),
+    }
+    match snippets::SnippetStore::open(&app_config.snippets) {
+        Ok(store) => {
+            info!("Каталог сниппетов: {}", app_config.snippets.dir.display());
+            app_state = app_state.with_snippets(store);
+        }
+        Err(e) => warn!("Сниппеты отключены: {}", e),
+    }
+    match styles::StyleStore::open(&app_config.styles) {
+        Ok(store) => {
+            info!("Профили стилей: {}", app_config.styles.path.display());
+            app_state = app_state.with_styles(store);
+        }
+        Err(e) => warn!("Стили отключены: {}", e),
+    }
+
+    // Аутентификация: без доступного ключа сервер не запускается открытым
+    if app_config.auth.enabled {
+        match auth::Authenticator::new(&app_config.auth) {
+            Ok(authenticator) => {
+                info!(
+                    "Аутентификация включена: API ключей {}, JWT {}",
+                    app_config.auth.api_keys.len(),
+                    if app_config.auth.jwt.is_some() { "да" } else { "нет" }
+                );
+                app_state = app_state.with_auth(authenticator);
+            }
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        warn!("Аутентификация отключена (auth.enabled = false): сервер доступен всем");
+    }
+
+    let app_state = Arc::new(app_state);
+    if let Some((manager, queue)) = job_queue {
+        jobs::spawn_workers(app_state.clone(), manager, queue, app_config.jobs.workers);
+    }
+
+    let upload_limit = DefaultBodyLimit::max(app_config.limits.max_upload_bytes);
 
     // Создаем роутер
+    // Все маршруты, кроме /health, проходят аутентификацию
+    let api = Router::new()
+        .route("/ws", get(ws::websocket_handler))
+        .route("/v1/transcriptions", post(api::create_transcription).layer(upload_limit))
+        .route("/v1/audio/transcriptions", post(openai::create_transcription).layer(upload_limit))
+        .route("/v1/audio/translations", post(openai::create_translation).layer(upload_limit))
+        .route("/v1/jobs", post(api::create_job).layer(upload_limit))
+        .route("/v1/jobs/:id", get(api::get_job))
+        .route(
+            "/v1/dictionaries/:scope/:owner",
+            get(api::list_dictionary_entries).post(api::add_dictionary_entry),
+        )
+        .route(
+            "/v1/dictionaries/:scope/:owner/:id",
+            put(api::update_dictionary_entry).delete(api::delete_dictionary_entry),
+        )
+        .route("/v1/snippets/:scope/:owner", get(api::list_snippets).post(api::add_snippet))
+        .route("/v1/snippets/:scope/:owner/:id", put(api::update_snippet).delete(api::delete_snippet))
+        .route("/v1/styles", get(api::list_styles))
+        .route("/v1/styles/:id", get(api::get_style).put(api::put_style).delete(api::delete_style))
+        .route_layer(middleware::from_fn_with_state(app_state.clone(), auth::authenticate));
     let app = Router::new()
         .route("/health", get(health_check))
-        .route("/ws", get(ws::websocket_handler))
+        .merge(api)
         .layer(CorsLayer::permissive())
         .with_state(app_state);
 
     // Запускаем сервер
-    let addr = SocketAddr::from(([127, 0, 0, 1], 8081));
-    let listener = TcpListener::bind(addr)
-        .await
-        .expect("Failed to bind to address");
+    let addr = app_config.server.bind;
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Не удалось занять адрес {} (server.bind): {}", addr, e);
+            std::process::exit(1);
+        }
+    };
+
+    // Встроенный TLS: сертификаты перечитываются при изменении файлов
+    if let Some(tls_config) = &app_config.server.tls {
+        let acceptor = match tls::TlsAcceptor::new(tls_config) {
+            Ok(acceptor) => acceptor,
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(1);
+            }
+        };
+        acceptor.spawn_reload();
 
+        info!("AlfaVoice Server listening on {} (TLS)", addr);
+        info!("WebSocket endpoint: wss://{}/ws", addr);
+        info!("REST endpoint: https://{}/v1/transcriptions", addr);
+        if tls_config.client_ca.is_some() {
+            info!(
+                "Проверка сертификатов клиентов (mTLS): {}",
+                if tls_config.require_client_cert { "обязательна" } else { "необязательна" }
+            );
+        }
+        tls::serve(listener, app, acceptor).await;
+        return;
+    }
+
+    if !addr.ip().is_loopback() {
+        warn!("Сервер доступен по сети без TLS (server.tls не задан): трафик не шифруется");
+    }
     info!("AlfaVoice Server listening on {}", addr);
-    info!("WebSocket endpoint: ws://{}", addr);
+    info!("WebSocket endpoint: ws://{}/ws", addr);
+    info!("REST endpoint: http://{}/v1/transcriptions", addr);
 
     if let Err(e) = axum::serve(listener, app).await {
         error!("Server error: {}", e);
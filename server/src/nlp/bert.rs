@@ -1,25 +1,32 @@
-//! BERT модель для обработки текста на русском языке
+//! Модель восстановления пунктуации и регистра (token classification)
 //!
-//! Использует rust-bert для загрузки модели DeepPavlov/rubert-base-cased
-//! и выполнения задач NLP (MaskedLM, классификация и т.д.)
+//! Использует rust-bert для загрузки из локального каталога модели BERT
+//! или XLM-RoBERTa, размечающей каждое слово знаком препинания после него
+//! и регистром (например, RUPunct для русского или punctuate-all для
+//! русского и английского). Каталог модели содержит `config.json` с
+//! `id2label`, веса `rust_model.ot` или `model.safetensors` и словарь
+//! токенизатора (`vocab.txt` или `sentencepiece.bpe.model`).
 
 use std::path::PathBuf;
-use std::sync::Arc;
 use std::time::Instant;
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use super::punctuation::{self, Label};
+
 #[cfg(feature = "nlp")]
-use rust_bert::pipelines::masked_language::{
-    MaskedLanguageConfig, MaskedLanguageModel,
-};
+use std::path::Path;
 #[cfg(feature = "nlp")]
-use rust_bert::resources::{LocalResource, RemoteResource};
+use std::sync::Mutex;
 #[cfg(feature = "nlp")]
-use rust_bert::Config;
+use rust_bert::pipelines::common::{ModelResource, ModelType};
+#[cfg(feature = "nlp")]
+use rust_bert::pipelines::token_classification::{
+    LabelAggregationOption, TokenClassificationConfig, TokenClassificationModel,
+};
 #[cfg(feature = "nlp")]
-use tch;
+use rust_bert::resources::LocalResource;
 
 /// Ошибки, возникающие при работе с BERT моделью
 #[derive(Error, Debug)]
@@ -51,23 +58,27 @@ pub struct ProcessResult {
     pub used_gpu: bool,
 }
 
+/// Служебные токены, которые токенизатор добавляет ко входу
+/// (`[CLS]`/`[SEP]` у BERT, `<s>`/`</s>` у XLM-RoBERTa)
+const SPECIAL_TOKENS: usize = 2;
+
 /// Конфигурация BERT модели
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BertConfig {
-    /// Имя модели (например, "DeepPavlov/rubert-base-cased")
+    /// Название модели для логов (например, "RUPunct/RUPunct_big")
     pub model_name: String,
-    /// Путь к локальной модели (если None - скачивать из HuggingFace)
+    /// Каталог модели (`models.punctuation_model`)
     pub local_model_path: Option<PathBuf>,
     /// Использовать GPU если доступно
     pub use_gpu: bool,
-    /// Максимальная длина входной последовательности
+    /// Максимальная длина входной последовательности (токенов)
     pub max_length: usize,
 }
 
 impl Default for BertConfig {
     fn default() -> Self {
         Self {
-            model_name: "DeepPavlov/rubert-base-cased".to_string(),
+            model_name: "RUPunct/RUPunct_big".to_string(),
             local_model_path: None,
             use_gpu: true,
             max_length: 512,
@@ -77,8 +88,11 @@ impl Default for BertConfig {
 
 /// BERT модель для обработки текста
 pub struct BertModel {
+    /// Пайплайн rust-bert не `Sync`, запросы к модели выполняются по очереди
+    #[cfg(feature = "nlp")]
+    model: Option<Mutex<TokenClassificationModel>>,
     #[cfg(feature = "nlp")]
-    model: Option<Arc<MaskedLanguageModel>>,
+    used_gpu: bool,
     config: BertConfig,
     is_initialized: bool,
 }
@@ -89,6 +103,8 @@ impl BertModel {
         Self {
             #[cfg(feature = "nlp")]
             model: None,
+            #[cfg(feature = "nlp")]
+            used_gpu: false,
             config,
             is_initialized: false,
         }
@@ -104,37 +120,36 @@ impl BertModel {
     pub fn initialize(&mut self) -> Result<(), BertError> {
         let start = Instant::now();
 
-        tracing::info!(
-            "Инициализация BERT модели: {}",
-            self.config.model_name
-        );
-
-        // Определяем ресурсы модели
-        let config_resource = if let Some(local_path) = &self.config.local_model_path {
-            tracing::info!("Используем локальную модель: {:?}", local_path);
-            LocalResource::from(local_path.clone())
-        } else {
-            tracing::info!("Скачиваем модель из HuggingFace: {}", self.config.model_name);
-            RemoteResource::from_pretrained(&self.config.model_name)
-        };
-
-        let model_resource = if let Some(local_path) = &self.config.local_model_path {
-            LocalResource::from(local_path.join("model.safetensors"))
-        } else {
-            RemoteResource::from_pretrained(&self.config.model_name)
-        };
-
-        let vocab_resource = if let Some(local_path) = &self.config.local_model_path {
-            LocalResource::from(local_path.join("vocab.txt"))
-        } else {
-            RemoteResource::from_pretrained(&self.config.model_name)
+        let dir = self.config.local_model_path.clone().ok_or_else(|| {
+            BertError::ConfigError("Не указан каталог модели пунктуации (models.punctuation_model)".to_string())
+        })?;
+        if !dir.is_dir() {
+            return Err(BertError::ConfigError(format!("Каталог модели пунктуации не найден: {}", dir.display())));
+        }
+        tracing::info!("Инициализация модели пунктуации {} из {:?}", self.config.model_name, dir);
+
+        // Тип модели определяет токенизатор и файл словаря
+        let config_path = dir.join("config.json");
+        let model_type = read_model_type(&config_path)?;
+        let (model_type, vocab) = match model_type.as_str() {
+            "bert" => (ModelType::Bert, "vocab.txt"),
+            "distilbert" => (ModelType::DistilBert, "vocab.txt"),
+            "xlm-roberta" => (ModelType::XLMRoberta, "sentencepiece.bpe.model"),
+            other => {
+                return Err(BertError::ConfigError(format!(
+                    "Тип модели {:?} не поддерживается: нужен bert, distilbert или xlm-roberta",
+                    other
+                )))
+            }
         };
 
-        let merges_resource = if let Some(local_path) = &self.config.local_model_path {
-            LocalResource::from(local_path.join("merges.txt"))
-        } else {
-            RemoteResource::from_pretrained(&self.config.model_name)
-        };
+        let weights = ["rust_model.ot", "model.safetensors"]
+            .into_iter()
+            .map(|file| dir.join(file))
+            .find(|path| path.exists())
+            .ok_or_else(|| {
+                BertError::InitializationError(format!("В {:?} нет весов rust_model.ot или model.safetensors", dir))
+            })?;
 
         // Определяем устройство для инференса
         let device = if self.config.use_gpu && tch::Cuda::is_available() {
@@ -145,29 +160,31 @@ impl BertModel {
             tch::Device::Cpu
         };
 
-        // Создаем конфигурацию пайплайна
-        let masked_lm_config = MaskedLanguageConfig::new(
-            MaskedLanguageConfig::default()
-                .with_device(device)
-                .with_max_length(self.config.max_length),
+        // Регистр и ё важны для меток, поэтому текст не нормализуется токенизатором:
+        // слова приводятся к нижнему регистру перед разметкой
+        let mut classification_config = TokenClassificationConfig::new(
+            model_type,
+            ModelResource::Torch(Box::new(LocalResource::from(weights))),
+            LocalResource::from(config_path),
+            LocalResource::from(dir.join(vocab)),
+            None,
+            false,
+            false,
+            None::<bool>,
+            LabelAggregationOption::First,
         );
+        classification_config.device = device;
+
+        let model = TokenClassificationModel::new(classification_config)
+            .map_err(|e| BertError::InitializationError(e.to_string()))?;
 
-        // Создаем модель
-        let model = MaskedLanguageModel::new(
-            masked_lm_config,
-            config_resource,
-            vocab_resource,
-            merges_resource,
-            model_resource,
-        )
-        .map_err(|e| BertError::InitializationError(e.to_string()))?;
-
-        self.model = Some(Arc::new(model));
+        self.model = Some(Mutex::new(model));
+        self.used_gpu = device.is_cuda();
         self.is_initialized = true;
 
         let duration = start.elapsed();
         tracing::info!(
-            "BERT модель инициализирована за {:.2}s",
+            "Модель пунктуации инициализирована за {:.2}s",
             duration.as_secs_f64()
         );
 
@@ -183,14 +200,11 @@ impl BertModel {
         ))
     }
 
-
-    /// Обрабатывает текст (постобработка после Whisper)
+    /// Восстанавливает пунктуацию и регистр в тексте Whisper
     ///
-    /// В текущей реализации выполняет базовую нормализацию текста.
-    /// В будущем можно добавить:
-    /// - Исправление опечаток через MaskedLM
-    /// - Восстановление пунктуации
-    /// - Коррекцию грамматики
+    /// Существующие знаки препинания удаляются и расставляются моделью
+    /// заново. Длинный текст размечается частями, которые вместе со
+    /// служебными токенами укладываются в `max_length` токенов.
     pub fn process_text(&self, input: &str) -> Result<ProcessResult, BertError> {
         if !self.is_initialized {
             return Err(BertError::NotInitialized);
@@ -202,59 +216,100 @@ impl BertModel {
 
         let start = Instant::now();
 
-        #[cfg(feature = "nlp")]
-        let used_gpu = if let Some(model) = &self.model {
-            // Проверяем, используется ли CUDA
-            model.device().is_cuda()
-        } else {
-            false
-        };
+        let words = punctuation::words(input);
+        if words.is_empty() {
+            return Err(BertError::InvalidInput("В тексте нет слов".to_string()));
+        }
 
-        #[cfg(not(feature = "nlp"))]
-        let used_gpu = false;
+        let max_tokens = self.config.max_length.saturating_sub(SPECIAL_TOKENS).max(1);
+        let tokens = self.count_tokens(&words)?;
+        let chunks: Vec<_> = punctuation::chunks(&tokens, max_tokens)
+            .into_iter()
+            .map(|range| punctuation::model_input(&words[range]))
+            .collect();
+        let inputs: Vec<&str> = chunks.iter().map(|(input, _)| input.as_str()).collect();
+        let predictions = self.predict(&inputs)?;
 
-        // Базовая постобработка текста
-        let processed_text = self.basic_postprocessing(input);
+        let labels: Vec<Label> = chunks
+            .iter()
+            .zip(&predictions)
+            .flat_map(|((_, ranges), tokens)| punctuation::word_labels(ranges, tokens))
+            .collect();
+        let processed_text = punctuation::restore(&words, &labels);
 
         let duration = start.elapsed();
 
         Ok(ProcessResult {
             text: processed_text,
             processing_time_ms: duration.as_millis() as u64,
-            used_gpu,
+            used_gpu: self.used_gpu(),
         })
     }
 
-    /// Базовая постобработка текста (без ML)
-    ///
-    /// Выполняет:
-    /// - Удаление лишних пробелов
-    /// - Коррекцию заглавных букв в начале предложений
-    /// - Удаление повторяющихся слов
-    fn basic_postprocessing(&self, text: &str) -> String {
-        // Разбиваем на предложения
-        let sentences: Vec<&str> = text.split(&['.', '!', '?'][..]).collect();
-
-        let processed: Vec<String> = sentences
+    /// Число токенов каждого слова на входе модели
+    #[cfg(feature = "nlp")]
+    fn count_tokens(&self, words: &[&str]) -> Result<Vec<usize>, BertError> {
+        let model = self.model.as_ref().ok_or(BertError::NotInitialized)?;
+        let model = model
+            .lock()
+            .map_err(|_| BertError::InferenceError("Модель недоступна после сбоя".to_string()))?;
+
+        let tokenizer = model.get_tokenizer();
+        Ok(words
             .iter()
-            .filter(|s| !s.trim().is_empty())
-            .map(|s| {
-                let mut sentence = s.trim().to_string();
-
-                // Делаем первую букву заглавной
-                if let Some(first_char) = sentence.chars().next() {
-                    let mut chars = sentence.chars();
-                    let first_upper = first_char.to_uppercase().collect::<String>();
-                    let rest: String = chars.skip(1).collect();
-                    sentence = format!("{}{}", first_upper, rest);
-                }
-
-                // Удаляем лишние пробелы
-                sentence.split_whitespace().collect::<Vec<_>>().join(" ")
+            .map(|word| tokenizer.tokenize(&word.to_lowercase()).len().max(1))
+            .collect())
+    }
+
+    #[cfg(not(feature = "nlp"))]
+    fn count_tokens(&self, _words: &[&str]) -> Result<Vec<usize>, BertError> {
+        Err(BertError::NotInitialized)
+    }
+
+    /// Размечает входы модели: начало токена (в символах) и метка
+    #[cfg(feature = "nlp")]
+    fn predict(&self, inputs: &[&str]) -> Result<Vec<Vec<(usize, Label)>>, BertError> {
+        let model = self.model.as_ref().ok_or(BertError::NotInitialized)?;
+        let model = model
+            .lock()
+            .map_err(|_| BertError::InferenceError("Модель недоступна после сбоя".to_string()))?;
+
+        let predictions = model.predict(inputs, true, false);
+        if predictions.len() != inputs.len() {
+            return Err(BertError::InferenceError(format!(
+                "Модель вернула {} результатов на {} входов",
+                predictions.len(),
+                inputs.len()
+            )));
+        }
+
+        Ok(predictions
+            .into_iter()
+            .map(|tokens| {
+                tokens
+                    .into_iter()
+                    .filter_map(|token| {
+                        let offset = token.offset?;
+                        Some((offset.begin as usize, Label::parse(&token.label)))
+                    })
+                    .collect()
             })
-            .collect();
+            .collect())
+    }
+
+    #[cfg(not(feature = "nlp"))]
+    fn predict(&self, _inputs: &[&str]) -> Result<Vec<Vec<(usize, Label)>>, BertError> {
+        Err(BertError::NotInitialized)
+    }
+
+    #[cfg(feature = "nlp")]
+    fn used_gpu(&self) -> bool {
+        self.used_gpu
+    }
 
-        processed.join(". ") + "."
+    #[cfg(not(feature = "nlp"))]
+    fn used_gpu(&self) -> bool {
+        false
     }
 
     /// Проверяет, инициализирована ли модель
@@ -268,30 +323,29 @@ impl BertModel {
     }
 }
 
+/// Читает `model_type` из `config.json` модели
+#[cfg(feature = "nlp")]
+fn read_model_type(path: &Path) -> Result<String, BertError> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| BertError::ConfigError(format!("Не удалось прочитать {:?}: {}", path, e)))?;
+    let config: serde_json::Value = serde_json::from_str(&text)
+        .map_err(|e| BertError::ConfigError(format!("Некорректный {:?}: {}", path, e)))?;
+    if config.get("id2label").is_none() {
+        return Err(BertError::ConfigError(format!(
+            "В {:?} нет id2label: это не модель token classification",
+            path
+        )));
+    }
+    config["model_type"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| BertError::ConfigError(format!("В {:?} нет model_type", path)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_basic_postprocessing() {
-        let model = BertModel::with_default_config();
-
-        // Тест с лишними пробелами
-        let input = "привет   мир   это   тест";
-        let output = model.basic_postprocessing(input);
-        assert_eq!(output, "Привет мир это тест.");
-
-        // Тест с заглавными буквами
-        let input = "привет мир. это тест";
-        let output = model.basic_postprocessing(input);
-        assert_eq!(output, "Привет мир. Это тест.");
-
-        // Тест с пустым текстом
-        let input = "";
-        let output = model.basic_postprocessing(input);
-        assert_eq!(output, ".");
-    }
-
     #[test]
     fn test_process_text_not_initialized() {
         let model = BertModel::with_default_config();
@@ -308,12 +362,15 @@ mod tests {
         let result = model.process_text("");
         assert!(result.is_err());
         assert!(matches!(result, Err(BertError::InvalidInput(_))));
+
+        let result = model.process_text(" — ... ");
+        assert!(matches!(result, Err(BertError::InvalidInput(_))));
     }
 
     #[test]
     fn test_bert_config_default() {
         let config = BertConfig::default();
-        assert_eq!(config.model_name, "DeepPavlov/rubert-base-cased");
+        assert_eq!(config.model_name, "RUPunct/RUPunct_big");
         assert!(config.use_gpu);
         assert_eq!(config.max_length, 512);
         assert!(config.local_model_path.is_none());
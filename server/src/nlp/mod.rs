@@ -1,10 +1,11 @@
 //! NLP модуль для обработки текста с использованием rust-bert
 //!
 //! Модуль предоставляет функционал для:
-//! - Загрузки и использования BERT-моделей (ruBERT)
-//! - Постобработки текста (исправление ошибок, пунктуация)
+//! - Загрузки модели token classification (BERT, XLM-RoBERTa)
+//! - Восстановления пунктуации и регистра в тексте Whisper
 //! - Интеграции с пайплайном распознавания речи
 
 pub mod bert;
+pub mod punctuation;
 
 pub use bert::{BertModel, BertConfig, ProcessResult};
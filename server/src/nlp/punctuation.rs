@@ -0,0 +1,333 @@
+//! Восстановление пунктуации и регистра по меткам token classification
+//!
+//! Модель размечает каждое слово меткой: знак после слова и (если модель
+//! обучена на регистре) регистр слова. Поддерживаются две распространённые
+//! схемы меток:
+//! - `LOWER_O`, `UPPER_COMMA`, `UPPER_TOTAL_PERIOD`, ... (RUPunct);
+//! - `0`, `.`, `,`, `?`, `-`, `:` (punctuate-all и аналоги, без регистра).
+//!
+//! Сама модель получает слова без знаков препинания в нижнем регистре,
+//! а текст собирается заново из исходных слов и меток.
+
+/// Регистр слова
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    /// Модель не размечает регистр: слово остаётся как у Whisper
+    Keep,
+    Lower,
+    /// Первая буква заглавная
+    Capital,
+    /// Всё слово заглавными (аббревиатуры)
+    Upper,
+}
+
+/// Знак после слова
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mark {
+    Period,
+    Comma,
+    Question,
+    Exclamation,
+    Colon,
+    Semicolon,
+    /// Тире между словами: `слово — слово`
+    Dash,
+    /// Дефис внутри слова: `кто-то`
+    Hyphen,
+}
+
+impl Mark {
+    fn as_str(self) -> &'static str {
+        match self {
+            Mark::Period => ".",
+            Mark::Comma => ",",
+            Mark::Question => "?",
+            Mark::Exclamation => "!",
+            Mark::Colon => ":",
+            Mark::Semicolon => ";",
+            Mark::Dash => " —",
+            Mark::Hyphen => "-",
+        }
+    }
+
+    /// Заканчивает ли знак предложение
+    fn ends_sentence(self) -> bool {
+        matches!(self, Mark::Period | Mark::Question | Mark::Exclamation)
+    }
+}
+
+/// Метка слова
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Label {
+    pub case: Case,
+    pub mark: Option<Mark>,
+}
+
+impl Default for Label {
+    fn default() -> Self {
+        Self {
+            case: Case::Keep,
+            mark: None,
+        }
+    }
+}
+
+impl Label {
+    /// Разбирает метку из `id2label` модели; неизвестные знаки игнорируются
+    pub fn parse(label: &str) -> Self {
+        let label = label.trim();
+        let upper = label.to_uppercase();
+        let (case, mark) = if let Some(rest) = upper.strip_prefix("UPPER_TOTAL_") {
+            (Case::Upper, rest)
+        } else if let Some(rest) = upper.strip_prefix("UPPER_") {
+            (Case::Capital, rest)
+        } else if let Some(rest) = upper.strip_prefix("LOWER_") {
+            (Case::Lower, rest)
+        } else {
+            (Case::Keep, upper.as_str())
+        };
+
+        let mark = match mark {
+            "PERIOD" | "." => Some(Mark::Period),
+            "COMMA" | "," => Some(Mark::Comma),
+            "QUESTION" | "?" => Some(Mark::Question),
+            "VOSKL" | "EXCLAMATION" | "!" => Some(Mark::Exclamation),
+            "DVOETOCHIE" | "COLON" | ":" => Some(Mark::Colon),
+            "PERIODCOMMA" | "SEMICOLON" | ";" => Some(Mark::Semicolon),
+            "TIRE" | "DASH" | "-" | "—" => Some(Mark::Dash),
+            "DEFIS" | "HYPHEN" => Some(Mark::Hyphen),
+            _ => None,
+        };
+
+        Self { case, mark }
+    }
+}
+
+/// Знаки, которые расставляет модель: по краям слов они отбрасываются
+const MARKS: &[char] = &['.', ',', '?', '!', ':', ';', '…', '—', '–', '-'];
+
+/// Слова текста без знаков препинания по краям
+///
+/// Отбрасываются только знаки, которые модель ставит сама (`MARKS`):
+/// символы вроде `%`, `$`, `+`, `#`, скобок и кавычек остаются частью слова,
+/// как и минус перед числом. Отдельные знаки (`—`, `...`) пропадают целиком.
+pub fn words(text: &str) -> Vec<&str> {
+    text.split_whitespace()
+        .map(strip_marks)
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+fn strip_marks(word: &str) -> &str {
+    let word = word.trim_end_matches(MARKS);
+    let stripped = word.trim_start_matches(MARKS);
+    let start = word.len() - stripped.len();
+    if stripped.starts_with(|c: char| c.is_ascii_digit()) && word[..start].ends_with('-') {
+        &word[start - 1..]
+    } else {
+        stripped
+    }
+}
+
+/// Делит слова на части, каждая из которых укладывается в `max_tokens`
+///
+/// `tokens` - число токенов каждого слова. Слово длиннее `max_tokens`
+/// становится отдельной частью, его хвост модель обрежет сама.
+pub fn chunks(tokens: &[usize], max_tokens: usize) -> Vec<std::ops::Range<usize>> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut used = 0;
+    for (i, &count) in tokens.iter().enumerate() {
+        if i > start && used + count > max_tokens {
+            chunks.push(start..i);
+            start = i;
+            used = 0;
+        }
+        used += count;
+    }
+    if start < tokens.len() {
+        chunks.push(start..tokens.len());
+    }
+    chunks
+}
+
+/// Вход модели и диапазоны слов в нём (в символах)
+pub fn model_input(words: &[&str]) -> (String, Vec<std::ops::Range<usize>>) {
+    let mut input = String::new();
+    let mut ranges = Vec::with_capacity(words.len());
+    let mut position = 0;
+    for word in words {
+        if !input.is_empty() {
+            input.push(' ');
+            position += 1;
+        }
+        let lower = word.to_lowercase();
+        let length = lower.chars().count();
+        input.push_str(&lower);
+        ranges.push(position..position + length);
+        position += length;
+    }
+    (input, ranges)
+}
+
+/// Сопоставляет метки токенов словам: слово получает метку первого токена
+///
+/// `tokens` - начало токена (в символах входа модели) и его метка.
+pub fn word_labels(ranges: &[std::ops::Range<usize>], tokens: &[(usize, Label)]) -> Vec<Label> {
+    ranges
+        .iter()
+        .map(|range| {
+            tokens
+                .iter()
+                .find(|(begin, _)| range.contains(begin))
+                .map(|(_, label)| *label)
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Собирает текст из слов и меток
+///
+/// Первое слово предложения всегда с заглавной буквы, в конце текста
+/// ставится точка, если модель не поставила знак конца предложения.
+pub fn restore(words: &[&str], labels: &[Label]) -> String {
+    let mut out = String::new();
+    let mut sentence_start = true;
+    let mut glue = false;
+
+    for (word, label) in words.iter().zip(labels) {
+        if !out.is_empty() && !glue {
+            out.push(' ');
+        }
+
+        let mut word = match label.case {
+            Case::Keep => word.to_string(),
+            Case::Lower => word.to_lowercase(),
+            Case::Capital => capitalize(&word.to_lowercase()),
+            Case::Upper => word.to_uppercase(),
+        };
+        if sentence_start {
+            word = capitalize(&word);
+        }
+        out.push_str(&word);
+
+        sentence_start = false;
+        glue = false;
+        if let Some(mark) = label.mark {
+            out.push_str(mark.as_str());
+            sentence_start = mark.ends_sentence();
+            glue = mark == Mark::Hyphen;
+        }
+    }
+
+    let last_mark = labels.get(words.len().saturating_sub(1)).and_then(|label| label.mark);
+    if !out.is_empty() && !last_mark.is_some_and(Mark::ends_sentence) {
+        // Висящее тире или запятая в конце заменяются точкой
+        let trimmed = out.trim_end_matches([',', ':', ';', '-', '—', ' ']).len();
+        out.truncate(trimmed);
+        out.push('.');
+    }
+    out
+}
+
+/// Делает заглавной первую букву слова, пропуская символы перед ней (`«`, `(`)
+fn capitalize(word: &str) -> String {
+    match word.char_indices().find(|(_, c)| c.is_alphanumeric()) {
+        Some((i, first)) => {
+            let rest = &word[i + first.len_utf8()..];
+            word[..i].chars().chain(first.to_uppercase()).chain(rest.chars()).collect()
+        }
+        None => word.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_labels() {
+        assert_eq!(
+            Label::parse("UPPER_COMMA"),
+            Label {
+                case: Case::Capital,
+                mark: Some(Mark::Comma)
+            }
+        );
+        assert_eq!(Label::parse("UPPER_TOTAL_O").case, Case::Upper);
+        assert_eq!(Label::parse("LOWER_QUESTION").mark, Some(Mark::Question));
+        assert_eq!(Label::parse("?"), Label { case: Case::Keep, mark: Some(Mark::Question) });
+        assert_eq!(Label::parse("0"), Label::default());
+    }
+
+    #[test]
+    fn test_restore_russian() {
+        let source = "привет как дела кто то звонил мне из мвд";
+        let words = words(source);
+        let labels: Vec<Label> = ["LOWER_COMMA", "LOWER_O", "LOWER_QUESTION", "LOWER_DEFIS", "LOWER_O", "LOWER_O", "LOWER_O", "LOWER_O", "UPPER_TOTAL_O"]
+            .into_iter()
+            .map(Label::parse)
+            .collect();
+        assert_eq!(restore(&words, &labels), "Привет, как дела? Кто-то звонил мне из МВД.");
+    }
+
+    #[test]
+    fn test_restore_without_case_labels() {
+        let words = words("so, John said — it works");
+        assert_eq!(words, ["so", "John", "said", "it", "works"]);
+        let labels: Vec<Label> = [",", "0", "-", "0", "0"].into_iter().map(Label::parse).collect();
+        assert_eq!(restore(&words, &labels), "So, John said — it works.");
+    }
+
+    #[test]
+    fn test_words_keep_symbols() {
+        let words = words("скидка 10%, цена $5 на C++ — звоните +7 (495) «сейчас». -5 градусов #tag...");
+        assert_eq!(
+            words,
+            ["скидка", "10%", "цена", "$5", "на", "C++", "звоните", "+7", "(495)", "«сейчас»", "-5", "градусов", "#tag"]
+        );
+
+        let labels: Vec<Label> = ["LOWER_O", "LOWER_PERIOD", "UPPER_O"]
+            .into_iter()
+            .map(Label::parse)
+            .collect();
+        assert_eq!(restore(&["«сейчас»", "10%", "«да»"], &labels), "«Сейчас» 10%. «Да».");
+    }
+
+    #[test]
+    fn test_chunks_fit_token_limit() {
+        // Длинные слова разбиваются токенизатором на несколько токенов
+        let tokens: Vec<usize> = (0..1000).map(|i| 1 + i % 4).collect();
+        let total: usize = tokens.iter().sum();
+        assert!(total > 510);
+
+        let parts = chunks(&tokens, 510);
+        assert!(parts.len() > 1);
+        assert_eq!(parts.first().unwrap().start, 0);
+        assert_eq!(parts.last().unwrap().end, tokens.len());
+        for (part, next) in parts.iter().zip(parts.iter().skip(1)) {
+            assert_eq!(part.end, next.start);
+        }
+        for part in &parts {
+            assert!(tokens[part.clone()].iter().sum::<usize>() <= 510);
+        }
+
+        // Слово больше лимита занимает отдельную часть
+        assert_eq!(chunks(&[2, 600, 3], 510), [0..1, 1..2, 2..3]);
+        assert!(chunks(&[], 510).is_empty());
+    }
+
+    #[test]
+    fn test_word_labels_from_tokens() {
+        let (input, ranges) = model_input(&["Привет", "мир"]);
+        assert_eq!(input, "привет мир");
+        assert_eq!(ranges, [0..6, 7..10]);
+
+        // Слово "привет" разбито на два токена, метка берётся у первого
+        let tokens = [(0, Label::parse("UPPER_O")), (3, Label::parse("LOWER_COMMA")), (7, Label::parse("LOWER_PERIOD"))];
+        let labels = word_labels(&ranges, &tokens);
+        assert_eq!(labels[0].case, Case::Capital);
+        assert_eq!(labels[0].mark, None);
+        assert_eq!(labels[1].mark, Some(Mark::Period));
+    }
+}
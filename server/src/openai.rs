@@ -0,0 +1,286 @@
+//! OpenAI-совместимый API распознавания
+//!
+//! `POST /v1/audio/transcriptions` и `POST /v1/audio/translations` повторяют
+//! форму OpenAI Whisper API, поэтому редакторы, приложения заметок и скрипты,
+//! умеющие работать с OpenAI, подключаются к AlfaVoice сменой base URL.
+//! Поле `model` принимается для совместимости, распознаёт всегда локальная
+//! модель Whisper.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use tracing::debug;
+
+use crate::api::{self, ApiError, FormParams};
+use crate::audio::PcmSpec;
+use crate::auth::Caller;
+use crate::store::Owners;
+use crate::format;
+use crate::state::AppState;
+use crate::transcript::Word;
+use crate::whisper::{Language, Task, TranscribeOptions};
+
+/// Формат ответа (`response_format`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResponseFormat {
+    #[default]
+    Json,
+    Text,
+    Srt,
+    Vtt,
+    VerboseJson,
+}
+
+impl ResponseFormat {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(Self::Json),
+            "text" => Some(Self::Text),
+            "srt" => Some(Self::Srt),
+            "vtt" => Some(Self::Vtt),
+            "verbose_json" => Some(Self::VerboseJson),
+            _ => None,
+        }
+    }
+}
+
+/// Поля формы запроса OpenAI
+#[derive(Debug, Clone, Default)]
+pub struct OpenAiParams {
+    pub model: Option<String>,
+    /// Код языка ISO 639-1; без него язык определяется автоматически
+    pub language: Option<Language>,
+    pub prompt: Option<String>,
+    pub response_format: ResponseFormat,
+    pub temperature: Option<f32>,
+    /// `timestamp_granularities[]=word`: слова в `verbose_json`
+    pub word_timestamps: bool,
+    /// Идентификатор пользователя OpenAI: выбирает его словарь
+    pub user: Option<String>,
+}
+
+impl FormParams for OpenAiParams {
+    fn set_field(&mut self, name: &str, value: &str) -> Result<(), ApiError> {
+        let value = value.trim();
+        match name {
+            "model" => self.model = Some(value.to_string()),
+            "language" if value.is_empty() => self.language = None,
+            "language" => {
+                let language = Language::from_code(value).ok_or_else(|| {
+                    ApiError::bad_request(format!("Язык {} не поддерживается, доступны: ru, en", value))
+                })?;
+                self.language = Some(language);
+            }
+            "prompt" => self.prompt = Some(value.to_string()).filter(|p| !p.is_empty()),
+            "response_format" => {
+                self.response_format = ResponseFormat::from_name(value).ok_or_else(|| {
+                    ApiError::bad_request(format!(
+                        "Неизвестный response_format {}, доступны: json, text, srt, vtt, verbose_json",
+                        value
+                    ))
+                })?;
+            }
+            "temperature" => {
+                let temperature: f32 = value
+                    .parse()
+                    .map_err(|_| ApiError::bad_request(format!("Некорректная temperature: {}", value)))?;
+                if !(0.0..=1.0).contains(&temperature) {
+                    return Err(ApiError::bad_request("temperature должна быть от 0 до 1"));
+                }
+                self.temperature = Some(temperature);
+            }
+            "user" => self.user = Some(value.to_string()).filter(|u| !u.is_empty()),
+            "timestamp_granularities[]" | "timestamp_granularities" if value == "word" => {
+                self.word_timestamps = true;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Ошибка в формате OpenAI: `{"error": {"message", "type", "param", "code"}}`
+#[derive(Debug)]
+pub struct OpenAiError(ApiError);
+
+impl From<ApiError> for OpenAiError {
+    fn from(e: ApiError) -> Self {
+        Self(e)
+    }
+}
+
+impl IntoResponse for OpenAiError {
+    fn into_response(self) -> Response {
+        let kind = if self.0.status.is_client_error() {
+            "invalid_request_error"
+        } else {
+            "server_error"
+        };
+        let body = serde_json::json!({
+            "error": {
+                "message": self.0.message,
+                "type": kind,
+                "param": null,
+                "code": null,
+            }
+        });
+        (self.0.status, Json(body)).into_response()
+    }
+}
+
+/// Ответ `verbose_json`
+#[derive(Debug, Serialize)]
+struct VerboseResponse {
+    task: &'static str,
+    /// Название языка, как у OpenAI: `russian`, `english`
+    language: String,
+    duration: f64,
+    text: String,
+    segments: Vec<VerboseSegment>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    words: Option<Vec<Word>>,
+}
+
+#[derive(Debug, Serialize)]
+struct VerboseSegment {
+    id: usize,
+    seek: usize,
+    start: f64,
+    end: f64,
+    text: String,
+    temperature: f32,
+    avg_logprob: f32,
+    no_speech_prob: f32,
+}
+
+/// `POST /v1/audio/transcriptions`
+pub async fn create_transcription(
+    State(state): State<Arc<AppState>>,
+    caller: Caller,
+    request: Request,
+) -> Result<Response, OpenAiError> {
+    handle(&state, &caller, request, Task::Transcribe).await
+}
+
+/// `POST /v1/audio/translations`: перевод речи на английский
+pub async fn create_translation(
+    State(state): State<Arc<AppState>>,
+    caller: Caller,
+    request: Request,
+) -> Result<Response, OpenAiError> {
+    handle(&state, &caller, request, Task::Translate).await
+}
+
+async fn handle(state: &AppState, caller: &Caller, request: Request, task: Task) -> Result<Response, OpenAiError> {
+    let upload = api::read_upload(OpenAiParams::default(), request).await?;
+    let params = upload.params;
+    debug!("OpenAI API: model={:?}, format={:?}", params.model, params.response_format);
+
+    let options = TranscribeOptions {
+        language: params.language.unwrap_or(Language::Auto),
+        task,
+        prompt: params.prompt.clone(),
+        temperature: params.temperature,
+    };
+    let owners = Owners {
+        user: params.user.clone(),
+        workspace: None,
+    };
+    let context = state.postprocess_context(&caller.owners(owners));
+    let result = api::transcribe_audio(state, upload.audio, PcmSpec::default(), &options, context).await?;
+
+    let response = match params.response_format {
+        ResponseFormat::Json => Json(serde_json::json!({ "text": result.text })).into_response(),
+        ResponseFormat::Text => text_response("text/plain; charset=utf-8", result.text),
+        ResponseFormat::Srt => text_response("application/x-subrip; charset=utf-8", format::srt(&result.transcript)),
+        ResponseFormat::Vtt => text_response("text/vtt; charset=utf-8", format::vtt(&result.transcript)),
+        ResponseFormat::VerboseJson => {
+            let transcript = result.transcript;
+            let words = params
+                .word_timestamps
+                .then(|| transcript.segments.iter().flat_map(|s| s.words.clone()).collect());
+            let segments = transcript
+                .segments
+                .into_iter()
+                .enumerate()
+                .map(|(id, segment)| VerboseSegment {
+                    id,
+                    seek: 0,
+                    start: segment.start,
+                    end: segment.end,
+                    text: segment.text,
+                    temperature: options.temperature.unwrap_or(0.0),
+                    avg_logprob: segment.avg_logprob,
+                    no_speech_prob: segment.no_speech_prob.unwrap_or(0.0),
+                })
+                .collect();
+
+            Json(VerboseResponse {
+                task: match task {
+                    Task::Transcribe => "transcribe",
+                    Task::Translate => "translate",
+                },
+                language: language_name(transcript.language.as_deref()),
+                duration: result.duration,
+                text: result.text,
+                segments,
+                words,
+            })
+            .into_response()
+        }
+    };
+
+    Ok(response)
+}
+
+fn text_response(content_type: &'static str, body: String) -> Response {
+    (StatusCode::OK, [(header::CONTENT_TYPE, content_type)], body).into_response()
+}
+
+/// Название языка в `verbose_json` по коду ISO 639-1
+fn language_name(code: Option<&str>) -> String {
+    match code {
+        Some("ru") => "russian".to_string(),
+        Some("en") => "english".to_string(),
+        Some(other) => other.to_string(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_form_fields() {
+        let mut params = OpenAiParams::default();
+        params.set_field("model", "whisper-1").unwrap();
+        params.set_field("language", "en").unwrap();
+        params.set_field("response_format", "verbose_json").unwrap();
+        params.set_field("temperature", "0.2").unwrap();
+        params.set_field("timestamp_granularities[]", "word").unwrap();
+
+        assert_eq!(params.model.as_deref(), Some("whisper-1"));
+        assert_eq!(params.language, Some(Language::En));
+        assert_eq!(params.response_format, ResponseFormat::VerboseJson);
+        assert_eq!(params.temperature, Some(0.2));
+        assert!(params.word_timestamps);
+    }
+
+    #[test]
+    fn test_invalid_fields() {
+        let mut params = OpenAiParams::default();
+        assert!(params.set_field("language", "de").is_err());
+        assert!(params.set_field("response_format", "xml").is_err());
+        assert!(params.set_field("temperature", "1.5").is_err());
+
+        let error = params.set_field("temperature", "abc").unwrap_err();
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,128 @@
+//! Поиск фраз в распознанном тексте
+//!
+//! Фраза совпадает целыми словами без учёта регистра. Слова фразы в тексте
+//! могут разделяться пробелами или дефисом (`альфа-войс` совпадает с
+//! `альфа войс`), но не знаками препинания. Из нескольких фраз, начинающихся
+//! в одном месте, выбирается самая длинная.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Слова текста (буквы и цифры) в нижнем регистре и их байтовые диапазоны
+pub fn words(text: &str) -> Vec<(String, Range<usize>)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (index, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(index),
+            (false, Some(begin)) => {
+                words.push((text[begin..index].to_lowercase(), begin..index));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
+
+/// Набор фраз со связанными значениями
+#[derive(Debug)]
+pub struct Phrases<V> {
+    /// Фразы по первому слову, длинные раньше коротких
+    by_first: HashMap<String, Vec<(Vec<String>, V)>>,
+}
+
+impl<V> Default for Phrases<V> {
+    fn default() -> Self {
+        Self { by_first: HashMap::new() }
+    }
+}
+
+impl<V> Phrases<V> {
+    /// Добавляет фразу; пустая фраза или уже добавленная не добавляются
+    ///
+    /// При совпадении фраз остаётся значение, добавленное первым.
+    pub fn insert(&mut self, phrase: &str, value: V) -> bool {
+        let words: Vec<String> = words(phrase).into_iter().map(|(word, _)| word).collect();
+        let Some(first) = words.first().cloned() else {
+            return false;
+        };
+        let candidates = self.by_first.entry(first).or_default();
+        if candidates.iter().any(|(existing, _)| *existing == words) {
+            return false;
+        }
+        let position = candidates
+            .iter()
+            .position(|(existing, _)| existing.len() < words.len())
+            .unwrap_or(candidates.len());
+        candidates.insert(position, (words, value));
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_first.is_empty()
+    }
+
+    /// Заменяет найденные фразы результатом `render`
+    pub fn replace(&self, text: &str, mut render: impl FnMut(&V) -> String) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut cursor = 0;
+        for (range, value) in self.find(text) {
+            out.push_str(&text[cursor..range.start]);
+            out.push_str(&render(value));
+            cursor = range.end;
+        }
+        out.push_str(&text[cursor..]);
+        out
+    }
+
+    /// Найденные фразы слева направо: байтовый диапазон и значение
+    pub fn find(&self, text: &str) -> Vec<(Range<usize>, &V)> {
+        let words = words(text);
+        let mut found = Vec::new();
+        let mut index = 0;
+
+        while index < words.len() {
+            let matched = self.by_first.get(&words[index].0).and_then(|candidates| {
+                candidates.iter().find(|(phrase, _)| {
+                    let end = index + phrase.len();
+                    end <= words.len()
+                        && words[index..end].iter().zip(phrase).all(|((word, _), expected)| word == expected)
+                        && words[index..end].windows(2).all(|pair| {
+                            text[pair[0].1.end..pair[1].1.start].chars().all(|c| c.is_whitespace() || c == '-')
+                        })
+                })
+            });
+
+            match matched {
+                Some((phrase, value)) => {
+                    let last = index + phrase.len() - 1;
+                    found.push((words[index].1.start..words[last].1.end, value));
+                    index = last + 1;
+                }
+                None => index += 1,
+            }
+        }
+
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_longest_whole_words() {
+        let mut phrases = Phrases::default();
+        assert!(phrases.insert("альфа", "A"));
+        assert!(phrases.insert("Альфа войс", "AV"));
+        assert!(!phrases.insert("альфа  ВОЙС", "dup"));
+        assert!(!phrases.insert(" - ", "empty"));
+
+        assert_eq!(
+            phrases.replace("Альфа-войс и альфа, войс; альфабет", |v| v.to_string()),
+            "AV и A, войс; альфабет"
+        );
+    }
+}
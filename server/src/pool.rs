@@ -0,0 +1,237 @@
+//! Пул ресурсов с честной очередью
+//!
+//! Используется для состояний Whisper: несколько `WhisperState` на одном
+//! контексте позволяют распознавать речь нескольких клиентов параллельно.
+//! Ожидающие обслуживаются строго по очереди (FIFO семафор tokio), а если
+//! свободного ресурса нет дольше `max_wait`, запрос получает ошибку
+//! «сервер занят» вместо бесконечного ожидания.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Ошибки получения ресурса из пула
+#[derive(Debug, Error)]
+pub enum PoolError {
+    #[error("Сервер занят: все {size} обработчиков заняты, ожидание превысило {waited_ms} мс")]
+    Busy { size: usize, waited_ms: u64 },
+
+    #[error("Пул закрыт")]
+    Closed,
+}
+
+/// Метрики пула
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolStats {
+    /// Размер пула
+    pub size: usize,
+    /// Занято ресурсов
+    pub in_use: usize,
+    /// Запросов в очереди
+    pub waiting: usize,
+    /// Выдано ресурсов всего
+    pub acquired_total: u64,
+    /// Отказов по таймауту всего
+    pub rejected_total: u64,
+    /// Среднее время ожидания в очереди (мс)
+    pub avg_wait_ms: f64,
+}
+
+/// Пул из фиксированного набора ресурсов
+pub struct Pool<T> {
+    items: Mutex<Vec<T>>,
+    semaphore: Arc<Semaphore>,
+    size: usize,
+    max_wait: Duration,
+    waiting: AtomicUsize,
+    acquired_total: AtomicU64,
+    rejected_total: AtomicU64,
+    wait_us_total: AtomicU64,
+}
+
+impl<T> Pool<T> {
+    /// Создаёт пул из готовых ресурсов
+    pub fn new(items: Vec<T>, max_wait: Duration) -> Self {
+        let size = items.len();
+        Self {
+            items: Mutex::new(items),
+            semaphore: Arc::new(Semaphore::new(size)),
+            size,
+            max_wait,
+            waiting: AtomicUsize::new(0),
+            acquired_total: AtomicU64::new(0),
+            rejected_total: AtomicU64::new(0),
+            wait_us_total: AtomicU64::new(0),
+        }
+    }
+
+    /// Берёт ресурс из пула, дожидаясь своей очереди не дольше `max_wait`
+    ///
+    /// Ресурс возвращается в пул при удалении `Pooled`.
+    pub async fn acquire(self: &Arc<Self>) -> Result<Pooled<T>, PoolError> {
+        let started = Instant::now();
+
+        let permit = {
+            let _waiting = WaitingGuard::new(&self.waiting);
+            match tokio::time::timeout(self.max_wait, self.semaphore.clone().acquire_owned()).await {
+                Ok(Ok(permit)) => permit,
+                Ok(Err(_)) => return Err(PoolError::Closed),
+                Err(_) => {
+                    self.rejected_total.fetch_add(1, Ordering::Relaxed);
+                    return Err(PoolError::Busy {
+                        size: self.size,
+                        waited_ms: started.elapsed().as_millis() as u64,
+                    });
+                }
+            }
+        };
+
+        self.acquired_total.fetch_add(1, Ordering::Relaxed);
+        self.wait_us_total
+            .fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+
+        // Семафор гарантирует, что свободный ресурс есть
+        let item = self.lock_items().pop().ok_or(PoolError::Closed)?;
+
+        Ok(Pooled {
+            pool: Arc::clone(self),
+            item: Some(item),
+            _permit: permit,
+        })
+    }
+
+    /// Текущие метрики пула
+    pub fn stats(&self) -> PoolStats {
+        let acquired_total = self.acquired_total.load(Ordering::Relaxed);
+        let wait_us_total = self.wait_us_total.load(Ordering::Relaxed);
+
+        PoolStats {
+            size: self.size,
+            in_use: self.size - self.semaphore.available_permits(),
+            waiting: self.waiting.load(Ordering::Relaxed),
+            acquired_total,
+            rejected_total: self.rejected_total.load(Ordering::Relaxed),
+            avg_wait_ms: if acquired_total == 0 {
+                0.0
+            } else {
+                wait_us_total as f64 / acquired_total as f64 / 1000.0
+            },
+        }
+    }
+
+    fn lock_items(&self) -> std::sync::MutexGuard<'_, Vec<T>> {
+        self.items.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Ресурс, взятый из пула
+pub struct Pooled<T> {
+    pool: Arc<Pool<T>>,
+    item: Option<T>,
+    /// Освобождается после возврата ресурса в пул (поля удаляются после `drop`)
+    _permit: OwnedSemaphorePermit,
+}
+
+impl<T> Deref for Pooled<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.item.as_ref().expect("ресурс уже возвращён в пул")
+    }
+}
+
+impl<T> DerefMut for Pooled<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.item.as_mut().expect("ресурс уже возвращён в пул")
+    }
+}
+
+impl<T> Drop for Pooled<T> {
+    fn drop(&mut self) {
+        if let Some(item) = self.item.take() {
+            self.pool.lock_items().push(item);
+        }
+    }
+}
+
+/// Счётчик ожидающих, уменьшается и при отмене ожидания
+struct WaitingGuard<'a>(&'a AtomicUsize);
+
+impl<'a> WaitingGuard<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(counter)
+    }
+}
+
+impl Drop for WaitingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_acquire_and_release() {
+        let pool = Arc::new(Pool::new(vec![1, 2], Duration::from_millis(100)));
+
+        let a = pool.acquire().await.unwrap();
+        let b = pool.acquire().await.unwrap();
+        assert_eq!(*a + *b, 3);
+        assert_eq!(pool.stats().in_use, 2);
+
+        drop(a);
+        let stats = pool.stats();
+        assert_eq!(stats.in_use, 1);
+        assert_eq!(stats.acquired_total, 2);
+    }
+
+    #[tokio::test]
+    async fn test_busy_after_max_wait() {
+        let pool = Arc::new(Pool::new(vec![()], Duration::from_millis(20)));
+        let _held = pool.acquire().await.unwrap();
+
+        let result = pool.acquire().await;
+        assert!(matches!(result, Err(PoolError::Busy { size: 1, .. })));
+
+        let stats = pool.stats();
+        assert_eq!(stats.rejected_total, 1);
+        assert_eq!(stats.waiting, 0);
+    }
+
+    #[tokio::test]
+    async fn test_waiters_served_in_order() {
+        let pool = Arc::new(Pool::new(vec![()], Duration::from_secs(5)));
+        let held = pool.acquire().await.unwrap();
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let mut tasks = Vec::new();
+        for i in 0..3 {
+            let task_pool = pool.clone();
+            let order = order.clone();
+            tasks.push(tokio::spawn(async move {
+                let _item = task_pool.acquire().await.unwrap();
+                order.lock().unwrap().push(i);
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }));
+            // Гарантируем порядок постановки в очередь
+            while pool.stats().waiting < i + 1 {
+                tokio::task::yield_now().await;
+            }
+        }
+
+        drop(held);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2]);
+    }
+}
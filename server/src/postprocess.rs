@@ -0,0 +1,592 @@
+//! Конвейер постобработки распознанного текста
+//!
+//! Каждый этап реализует `PostProcessor` и получает текст предыдущего.
+//! Состав и порядок этапов задаются в `postprocessing.stages`, конвейер
+//! собирается один раз при запуске и хранится в `AppState`. Для каждого
+//! этапа замеряется время, а ошибка или превышение `timeout_ms` обрабатываются
+//! по политике этапа (`on_error`), не прерывая распознавание. Этап с
+//! `fallback` подменяет предыдущий: он выполняется, только если тот не
+//! включён в конвейер или завершился ошибкой.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use serde::Serialize;
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::dictionary::Vocabulary;
+use crate::formatting::Formatting;
+use crate::snippets::Snippets;
+use crate::styles::StyleProfile;
+use crate::config::{FailurePolicy, PostProcessingConfig, StageConfig, StageKind};
+use crate::llm::{LlmModel, Rewrite};
+
+#[cfg(feature = "nlp")]
+use crate::nlp::BertModel;
+
+/// Ошибка этапа постобработки
+#[derive(Debug, Error)]
+pub enum PostProcessError {
+    #[error("{0}")]
+    Failed(String),
+    #[error("превышено время {0} мс")]
+    Timeout(u64),
+}
+
+/// Сведения о распознанном тексте, доступные этапам
+#[derive(Debug, Clone, Default)]
+pub struct PostProcessContext {
+    /// Определённый Whisper язык (`ru`, `en`)
+    pub language: Option<String>,
+    /// Словарь пользователя и рабочего пространства запроса
+    pub vocabulary: Option<Arc<Vocabulary>>,
+    /// Сниппеты пользователя и рабочего пространства запроса
+    pub snippets: Option<Arc<Snippets>>,
+    /// Стиль переписывания, выбранный для запроса
+    pub style: Option<Arc<StyleProfile>>,
+    /// Точка и регистр для приложения запроса
+    pub formatting: Formatting,
+}
+
+impl PostProcessContext {
+    /// Подсказка Whisper: подсказка клиента и термины словаря
+    pub fn prompt(&self, base: Option<&str>) -> Option<String> {
+        match &self.vocabulary {
+            Some(vocabulary) => vocabulary.prompt(base),
+            None => base.map(str::to_string),
+        }
+    }
+}
+
+/// Этап постобработки
+pub trait PostProcessor: Send + Sync {
+    /// Название этапа для логов и метрик
+    fn name(&self) -> &'static str;
+
+    /// Обрабатывает текст предыдущего этапа
+    fn process<'a>(
+        &'a self,
+        text: String,
+        context: &'a PostProcessContext,
+    ) -> BoxFuture<'a, Result<String, PostProcessError>>;
+}
+
+/// Итог выполнения этапа
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StageStatus {
+    Ok,
+    Failed,
+    Timeout,
+}
+
+/// Время и итог этапа
+#[derive(Debug, Clone, Serialize)]
+pub struct StageTiming {
+    pub stage: &'static str,
+    pub ms: u64,
+    pub status: StageStatus,
+}
+
+/// Результат конвейера
+#[derive(Debug, Clone, Default)]
+pub struct Processed {
+    pub text: String,
+    pub stages: Vec<StageTiming>,
+}
+
+/// Модели, из которых собираются этапы
+pub struct Models {
+    pub llm: Arc<LlmModel>,
+    #[cfg(feature = "nlp")]
+    pub bert: Option<Arc<BertModel>>,
+}
+
+struct Stage {
+    processor: Arc<dyn PostProcessor>,
+    on_error: FailurePolicy,
+    timeout: Option<Duration>,
+    fallback: bool,
+}
+
+/// Упорядоченный набор этапов постобработки
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+}
+
+impl Pipeline {
+    /// Собирает конвейер по конфигурации
+    ///
+    /// Этапы, модели которых не загружены или отключены, не включаются.
+    pub fn build(config: &PostProcessingConfig, models: &Models) -> Self {
+        Self::assemble(
+            config
+                .stages
+                .iter()
+                .map(|stage| (Self::processor(stage.name, models), stage)),
+        )
+    }
+
+    /// Собирает конвейер из доступных этапов
+    ///
+    /// Этап с `fallback` после недоступного этапа занимает его место
+    /// и выполняется всегда.
+    fn assemble<'a>(
+        stages: impl IntoIterator<Item = (Option<Arc<dyn PostProcessor>>, &'a StageConfig)>,
+    ) -> Self {
+        let mut pipeline = Self::default();
+        let mut previous_available = false;
+        for (processor, stage) in stages {
+            match processor {
+                Some(processor) => {
+                    pipeline = pipeline.with_stage(processor, stage);
+                    if !previous_available {
+                        if let Some(added) = pipeline.stages.last_mut() {
+                            added.fallback = false;
+                        }
+                    }
+                    previous_available = true;
+                }
+                None => {
+                    debug!("Этап постобработки {:?} недоступен и пропущен", stage.name);
+                    previous_available = false;
+                }
+            }
+        }
+        pipeline
+    }
+
+    fn processor(kind: StageKind, models: &Models) -> Option<Arc<dyn PostProcessor>> {
+        match kind {
+            StageKind::Normalize => Some(Arc::new(Normalize)),
+            StageKind::Dictionary => Some(Arc::new(DictionaryStage)),
+            StageKind::Snippets => Some(Arc::new(SnippetsStage)),
+            StageKind::Style => Some(Arc::new(StyleStage(models.llm.clone()))),
+            StageKind::Format => Some(Arc::new(FormatStage)),
+            StageKind::Llm => models
+                .llm
+                .is_enabled()
+                .then(|| Arc::new(LlmStage(models.llm.clone())) as Arc<dyn PostProcessor>),
+            #[cfg(feature = "nlp")]
+            StageKind::Bert => models
+                .bert
+                .as_ref()
+                .filter(|model| model.is_ready())
+                .map(|model| Arc::new(BertStage(model.clone())) as Arc<dyn PostProcessor>),
+            #[cfg(not(feature = "nlp"))]
+            StageKind::Bert => None,
+        }
+    }
+
+    /// Добавляет этап в конец конвейера
+    pub fn with_stage(mut self, processor: Arc<dyn PostProcessor>, config: &StageConfig) -> Self {
+        self.stages.push(Stage {
+            processor,
+            on_error: config.on_error,
+            timeout: config.timeout_ms.map(Duration::from_millis),
+            fallback: config.fallback,
+        });
+        self
+    }
+
+    /// Названия этапов в порядке применения
+    pub fn names(&self) -> Vec<&'static str> {
+        self.stages.iter().map(|stage| stage.processor.name()).collect()
+    }
+
+    /// Прогоняет текст через все этапы
+    pub async fn run(&self, text: String, context: &PostProcessContext) -> Processed {
+        let mut processed = Processed {
+            text,
+            stages: Vec::with_capacity(self.stages.len()),
+        };
+        if processed.text.trim().is_empty() {
+            return processed;
+        }
+
+        let mut previous = None;
+        for stage in &self.stages {
+            if stage.fallback && previous == Some(StageStatus::Ok) {
+                continue;
+            }
+
+            let name = stage.processor.name();
+            let started = Instant::now();
+            let future = stage.processor.process(processed.text.clone(), context);
+            let result = match stage.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, future)
+                    .await
+                    .unwrap_or(Err(PostProcessError::Timeout(timeout.as_millis() as u64))),
+                None => future.await,
+            };
+            let ms = started.elapsed().as_millis() as u64;
+
+            let status = match result {
+                Ok(text) => {
+                    debug!(
+                        "Постобработка {} ({}): {} мс",
+                        name,
+                        context.language.as_deref().unwrap_or("язык не определён"),
+                        ms
+                    );
+                    processed.text = text;
+                    StageStatus::Ok
+                }
+                Err(e) => {
+                    warn!("Ошибка этапа постобработки {} ({} мс): {}", name, ms, e);
+                    match e {
+                        PostProcessError::Timeout(_) => StageStatus::Timeout,
+                        PostProcessError::Failed(_) => StageStatus::Failed,
+                    }
+                }
+            };
+            processed.stages.push(StageTiming { stage: name, ms, status });
+            previous = Some(status);
+
+            if status != StageStatus::Ok && stage.on_error == FailurePolicy::Stop {
+                break;
+            }
+        }
+
+        processed
+    }
+}
+
+/// Схлопывает повторяющиеся пробелы в строках, сохраняя переводы строк
+struct Normalize;
+
+impl PostProcessor for Normalize {
+    fn name(&self) -> &'static str {
+        "normalize"
+    }
+
+    fn process<'a>(&'a self, text: String, _: &'a PostProcessContext) -> BoxFuture<'a, Result<String, PostProcessError>> {
+        Box::pin(async move {
+            let lines: Vec<_> = text
+                .trim()
+                .lines()
+                .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+                .collect();
+            Ok(lines.join("\n"))
+        })
+    }
+}
+
+/// Замена вариантов терминов из словаря запроса
+struct DictionaryStage;
+
+impl PostProcessor for DictionaryStage {
+    fn name(&self) -> &'static str {
+        "dictionary"
+    }
+
+    fn process<'a>(&'a self, text: String, context: &'a PostProcessContext) -> BoxFuture<'a, Result<String, PostProcessError>> {
+        Box::pin(async move {
+            Ok(match &context.vocabulary {
+                Some(vocabulary) => vocabulary.apply(&text),
+                None => text,
+            })
+        })
+    }
+}
+
+/// Раскрытие сниппетов запроса
+struct SnippetsStage;
+
+impl PostProcessor for SnippetsStage {
+    fn name(&self) -> &'static str {
+        "snippets"
+    }
+
+    fn process<'a>(&'a self, text: String, context: &'a PostProcessContext) -> BoxFuture<'a, Result<String, PostProcessError>> {
+        Box::pin(async move {
+            Ok(match &context.snippets {
+                Some(snippets) => snippets.expand(&text, &chrono::Local::now()),
+                None => text,
+            })
+        })
+    }
+}
+
+/// Переписывание в стиле запроса
+///
+/// Без LLM (отключена, не загружена или ошибка) ограничения стиля
+/// применяются детерминированно.
+struct StyleStage(Arc<LlmModel>);
+
+impl PostProcessor for StyleStage {
+    fn name(&self) -> &'static str {
+        "style"
+    }
+
+    fn process<'a>(&'a self, text: String, context: &'a PostProcessContext) -> BoxFuture<'a, Result<String, PostProcessError>> {
+        Box::pin(async move {
+            let Some(style) = &context.style else {
+                return Ok(text);
+            };
+            if style.settings.prompt.trim().is_empty() {
+                return Ok(style.apply(&text));
+            }
+
+            let rewrite = Rewrite {
+                instructions: &style.settings.prompt,
+                temperature: style.settings.temperature,
+            };
+            Ok(match self.0.rewrite(&text, &rewrite).await {
+                Ok(Some(rewritten)) => style.finish(&rewritten),
+                Ok(None) => style.apply(&text),
+                Err(e) => {
+                    warn!("Стиль {}: ошибка LLM, применяется без неё: {}", style.id, e);
+                    style.apply(&text)
+                }
+            })
+        })
+    }
+}
+
+/// Точка и регистр по правилам форматирования
+struct FormatStage;
+
+impl PostProcessor for FormatStage {
+    fn name(&self) -> &'static str {
+        "format"
+    }
+
+    fn process<'a>(&'a self, text: String, context: &'a PostProcessContext) -> BoxFuture<'a, Result<String, PostProcessError>> {
+        Box::pin(async move { Ok(context.formatting.apply(&text)) })
+    }
+}
+
+/// Исправление текста LLM
+struct LlmStage(Arc<LlmModel>);
+
+impl PostProcessor for LlmStage {
+    fn name(&self) -> &'static str {
+        "llm"
+    }
+
+    fn process<'a>(&'a self, text: String, _: &'a PostProcessContext) -> BoxFuture<'a, Result<String, PostProcessError>> {
+        Box::pin(async move {
+            self.0
+                .post_process(&text)
+                .await
+                .map_err(|e| PostProcessError::Failed(e.to_string()))
+        })
+    }
+}
+
+/// Восстановление пунктуации и регистра
+#[cfg(feature = "nlp")]
+struct BertStage(Arc<BertModel>);
+
+#[cfg(feature = "nlp")]
+impl PostProcessor for BertStage {
+    fn name(&self) -> &'static str {
+        "bert"
+    }
+
+    fn process<'a>(&'a self, text: String, _: &'a PostProcessContext) -> BoxFuture<'a, Result<String, PostProcessError>> {
+        let model = self.0.clone();
+        Box::pin(async move {
+            // Инференс модели - CPU/GPU работа, выносим из async потока
+            tokio::task::spawn_blocking(move || model.process_text(&text))
+                .await
+                .map_err(|e| PostProcessError::Failed(e.to_string()))?
+                .map(|result| result.text)
+                .map_err(|e| PostProcessError::Failed(e.to_string()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Этап, добавляющий суффикс или падающий
+    struct Append(&'static str, Option<Duration>);
+
+    impl PostProcessor for Append {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn process<'a>(&'a self, text: String, _: &'a PostProcessContext) -> BoxFuture<'a, Result<String, PostProcessError>> {
+            Box::pin(async move {
+                match self.1 {
+                    Some(delay) => {
+                        tokio::time::sleep(delay).await;
+                        Ok(format!("{} {}", text, self.0))
+                    }
+                    None if self.0 == "fail" => Err(PostProcessError::Failed("сбой".to_string())),
+                    None => Ok(format!("{} {}", text, self.0)),
+                }
+            })
+        }
+    }
+
+    fn stage(on_error: FailurePolicy, timeout_ms: Option<u64>) -> StageConfig {
+        StageConfig {
+            on_error,
+            timeout_ms,
+            ..StageConfig::new(StageKind::Normalize)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failure_policies() {
+        let pipeline = Pipeline::default()
+            .with_stage(Arc::new(Normalize), &stage(FailurePolicy::Skip, None))
+            .with_stage(Arc::new(Append("fail", None)), &stage(FailurePolicy::Skip, None))
+            .with_stage(Arc::new(Append("b", None)), &stage(FailurePolicy::Skip, None));
+        let processed = pipeline.run("  привет   мир ".to_string(), &PostProcessContext::default()).await;
+        assert_eq!(processed.text, "привет мир b");
+        let statuses: Vec<_> = processed.stages.iter().map(|s| s.status).collect();
+        assert_eq!(statuses, [StageStatus::Ok, StageStatus::Failed, StageStatus::Ok]);
+
+        let pipeline = Pipeline::default()
+            .with_stage(Arc::new(Append("a", None)), &stage(FailurePolicy::Skip, None))
+            .with_stage(Arc::new(Append("fail", None)), &stage(FailurePolicy::Stop, None))
+            .with_stage(Arc::new(Append("b", None)), &stage(FailurePolicy::Skip, None));
+        let processed = pipeline.run("текст".to_string(), &PostProcessContext::default()).await;
+        assert_eq!(processed.text, "текст a");
+        assert_eq!(processed.stages.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_normalize_keeps_line_breaks() {
+        let text = Normalize
+            .process("  Список:\n-  хлеб \n\n- молоко  ".to_string(), &PostProcessContext::default())
+            .await
+            .unwrap();
+        assert_eq!(text, "Список:\n- хлеб\n\n- молоко");
+    }
+
+    #[tokio::test]
+    async fn test_fallback_runs_only_after_failure() {
+        let fallback = StageConfig {
+            fallback: true,
+            ..stage(FailurePolicy::Skip, None)
+        };
+
+        let pipeline = Pipeline::default()
+            .with_stage(Arc::new(Append("bert", None)), &stage(FailurePolicy::Skip, None))
+            .with_stage(Arc::new(Append("llm", None)), &fallback)
+            .with_stage(Arc::new(Append("b", None)), &stage(FailurePolicy::Skip, None));
+        let processed = pipeline.run("текст".to_string(), &PostProcessContext::default()).await;
+        assert_eq!(processed.text, "текст bert b");
+        assert_eq!(processed.stages.len(), 2);
+
+        let pipeline = Pipeline::default()
+            .with_stage(Arc::new(Append("fail", None)), &stage(FailurePolicy::Skip, None))
+            .with_stage(Arc::new(Append("llm", None)), &fallback);
+        let processed = pipeline.run("текст".to_string(), &PostProcessContext::default()).await;
+        assert_eq!(processed.text, "текст llm");
+
+        // Предыдущий этап не загружен - fallback выполняется всегда
+        let config = PostProcessingConfig::default();
+        let index = config.stages.iter().position(|s| s.name == StageKind::Bert).unwrap();
+        let (bert, llm) = (&config.stages[index], &config.stages[index + 1]);
+        assert_eq!((bert.name, llm.name, llm.fallback), (StageKind::Bert, StageKind::Llm, true));
+        let pipeline = Pipeline::assemble([
+            (None, bert),
+            (Some(Arc::new(Append("llm", None)) as Arc<dyn PostProcessor>), llm),
+        ]);
+        let processed = pipeline.run("текст".to_string(), &PostProcessContext::default()).await;
+        assert_eq!(processed.text, "текст llm");
+    }
+
+    #[tokio::test]
+    async fn test_stage_timeout() {
+        let pipeline = Pipeline::default()
+            .with_stage(Arc::new(Append("slow", Some(Duration::from_secs(5)))), &stage(FailurePolicy::Skip, Some(10)));
+        let processed = pipeline.run("текст".to_string(), &PostProcessContext::default()).await;
+        assert_eq!(processed.text, "текст");
+        assert_eq!(processed.stages[0].status, StageStatus::Timeout);
+    }
+
+    #[tokio::test]
+    async fn test_style_without_llm() {
+        use crate::styles::{Constraints, Layout, StyleSettings};
+
+        let stage = StyleStage(Arc::new(LlmModel::default()));
+        let context = PostProcessContext {
+            style: Some(Arc::new(StyleProfile {
+                id: "bullet_list".to_string(),
+                settings: StyleSettings {
+                    name: "Список".to_string(),
+                    prompt: "Оформи текст списком.".to_string(),
+                    temperature: None,
+                    max_chars: None,
+                    constraints: Constraints {
+                        layout: Layout::BulletList,
+                        ..Constraints::default()
+                    },
+                },
+            })),
+            ..PostProcessContext::default()
+        };
+
+        let text = stage.process("Купить хлеб. Забрать посылку.".to_string(), &context).await.unwrap();
+        assert_eq!(text, "- Купить хлеб\n- Забрать посылку");
+        let text = stage.process("Без стиля.".to_string(), &PostProcessContext::default()).await.unwrap();
+        assert_eq!(text, "Без стиля.");
+    }
+
+    #[test]
+    fn test_build_skips_unavailable_stages() {
+        let models = Models {
+            llm: Arc::new(LlmModel::default()),
+            #[cfg(feature = "nlp")]
+            bert: None,
+        };
+        let pipeline = Pipeline::build(&PostProcessingConfig::default(), &models);
+        assert_eq!(pipeline.names(), ["normalize", "dictionary", "snippets", "style", "format"]);
+    }
+
+    #[tokio::test]
+    async fn test_snippets_expand_before_models() {
+        use crate::snippets::{Snippet, Snippets};
+
+        // Модель расставляет запятые между словами: после неё триггер не нашёлся бы
+        struct Punctuate;
+
+        impl PostProcessor for Punctuate {
+            fn name(&self) -> &'static str {
+                "bert"
+            }
+
+            fn process<'a>(&'a self, text: String, _: &'a PostProcessContext) -> BoxFuture<'a, Result<String, PostProcessError>> {
+                Box::pin(async move { Ok(format!("{}.", text.split_whitespace().collect::<Vec<_>>().join(", "))) })
+            }
+        }
+
+        let models = Models {
+            llm: Arc::new(LlmModel::default()),
+            #[cfg(feature = "nlp")]
+            bert: None,
+        };
+        let config = PostProcessingConfig::default();
+        let pipeline = Pipeline::assemble(config.stages.iter().map(|stage| match stage.name {
+            StageKind::Bert => (Some(Arc::new(Punctuate) as Arc<dyn PostProcessor>), stage),
+            kind => (Pipeline::processor(kind, &models), stage),
+        }));
+        assert_eq!(pipeline.names(), ["normalize", "dictionary", "snippets", "bert", "style", "format"]);
+
+        let context = PostProcessContext {
+            snippets: Some(Arc::new(Snippets::new(vec![Snippet {
+                id: "signature".to_string(),
+                trigger: "вставь мою подпись".to_string(),
+                body: "Иван Петров".to_string(),
+                created_at: chrono::Utc::now(),
+                updated_at: chrono::Utc::now(),
+            }]))),
+            ..PostProcessContext::default()
+        };
+        let processed = pipeline.run("спасибо вставь мою подпись".to_string(), &context).await;
+        assert!(processed.text.contains("Иван, Петров"), "{}", processed.text);
+        assert!(!processed.text.contains("подпись"));
+    }
+}
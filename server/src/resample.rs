@@ -0,0 +1,355 @@
+//! Ресемплинг аудио (polyphase windowed-sinc)
+//!
+//! Whisper принимает только 16kHz mono, а браузеры и файлы приходят
+//! в 8/22.05/44.1/48kHz. Фильтр - sinc с окном Кайзера, коэффициенты
+//! заранее считаются для каждой фазы, поэтому на сэмпл нет тригонометрии.
+//! Буфер целиком ресемплируется частями параллельно в пуле rayon.
+
+use rayon::prelude::*;
+
+/// Максимальное количество фаз в таблице фильтра
+///
+/// Для типичных частот (48k/44.1k/22.05k/8k -> 16k) фаз меньше, и ресемплинг
+/// точный. Для «неудобных» частот позиция округляется до ближайшей фазы.
+const MAX_PHASES: usize = 1024;
+
+/// Количество переходов через ноль sinc с каждой стороны
+const ZERO_CROSSINGS: usize = 16;
+
+/// Запас полосы до частоты Найквиста
+const ROLLOFF: f64 = 0.945;
+
+/// Параметр окна Кайзера (~80 дБ подавления в полосе задерживания)
+const KAISER_BETA: f64 = 8.6;
+
+/// Минимум выходных сэмплов на одну задачу rayon
+const PARALLEL_CHUNK: usize = 16384;
+
+/// Polyphase ресемплер с фиксированным соотношением частот
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    /// Шаг по входу в долях 1/up (up/down - несократимая дробь to/from)
+    up: u64,
+    down: u64,
+    /// Количество фаз в таблице (up или MAX_PHASES)
+    phases: usize,
+    /// Половина длины фильтра во входных сэмплах
+    half_taps: usize,
+    /// Коэффициенты: phases x (2 * half_taps)
+    table: Vec<f32>,
+}
+
+impl Resampler {
+    /// Создаёт ресемплер из `from_rate` в `to_rate`
+    pub fn new(from_rate: u32, to_rate: u32) -> Self {
+        assert!(from_rate > 0 && to_rate > 0, "частота дискретизации должна быть > 0");
+
+        let g = gcd(from_rate as u64, to_rate as u64);
+        let up = to_rate as u64 / g;
+        let down = from_rate as u64 / g;
+        let phases = (up as usize).min(MAX_PHASES);
+
+        // Частота среза относительно входной частоты Найквиста
+        let cutoff = (to_rate as f64 / from_rate as f64).min(1.0) * ROLLOFF;
+        let half_taps = (ZERO_CROSSINGS as f64 / cutoff).ceil() as usize;
+        let taps = 2 * half_taps;
+
+        let mut table = vec![0.0f32; phases * taps];
+        for (phase, row) in table.chunks_exact_mut(taps).enumerate() {
+            let frac = phase as f64 / phases as f64;
+            let mut sum = 0.0;
+            let mut coeffs = Vec::with_capacity(taps);
+            for j in 0..taps {
+                // Расстояние от точки интерполяции до входного сэмпла
+                let x = frac + half_taps as f64 - 1.0 - j as f64;
+                let h = cutoff * sinc(cutoff * x) * kaiser(x / half_taps as f64);
+                sum += h;
+                coeffs.push(h);
+            }
+            // Нормируем каждую фазу на единичное усиление по постоянной составляющей
+            for (dst, h) in row.iter_mut().zip(coeffs) {
+                *dst = (h / sum) as f32;
+            }
+        }
+
+        Self {
+            up,
+            down,
+            phases,
+            half_taps,
+            table,
+        }
+    }
+
+    /// Количество выходных сэмплов для входа длиной `input_len`
+    pub fn output_len(&self, input_len: usize) -> usize {
+        (input_len as u64 * self.up).div_ceil(self.down) as usize
+    }
+
+    /// Ресемплирует весь буфер целиком
+    ///
+    /// Выходные сэмплы независимы друг от друга, поэтому части выхода
+    /// считаются параллельно и результат не зависит от разбиения.
+    pub fn process(&self, input: &[f32]) -> Vec<f32> {
+        if self.up == self.down {
+            return input.to_vec();
+        }
+
+        (0..self.output_len(input.len()))
+            .into_par_iter()
+            .with_min_len(PARALLEL_CHUNK)
+            .map(|n| self.sample_at(input, 0, n as u64))
+            .collect()
+    }
+
+    /// Индекс входного сэмпла в центре окна для выходного сэмпла `n`
+    /// и фаза фильтра
+    fn position(&self, n: u64) -> (i64, usize) {
+        let pos = n * self.down;
+        let index = (pos / self.up) as i64;
+        let remainder = pos % self.up;
+
+        if self.phases as u64 == self.up {
+            return (index, remainder as usize);
+        }
+
+        // Округляем до ближайшей фазы таблицы
+        let p = ((remainder * self.phases as u64 * 2 + self.up) / (self.up * 2)) as usize;
+        if p == self.phases {
+            (index + 1, 0)
+        } else {
+            (index, p)
+        }
+    }
+
+    /// Выходной сэмпл `n`, если `input[0]` - входной сэмпл с индексом `offset`
+    ///
+    /// Сэмплы за границами `input` считаются нулями.
+    fn sample_at(&self, input: &[f32], offset: u64, n: u64) -> f32 {
+        let taps = 2 * self.half_taps;
+        let (index, phase) = self.position(n);
+        let coeffs = &self.table[phase * taps..(phase + 1) * taps];
+        let start = index - self.half_taps as i64 + 1 - offset as i64;
+        let len = input.len() as i64;
+
+        if start >= 0 && start + taps as i64 <= len {
+            let window = &input[start as usize..start as usize + taps];
+            window.iter().zip(coeffs).map(|(x, h)| x * h).sum()
+        } else {
+            // Край буфера: сэмплы за границами считаем нулями
+            coeffs
+                .iter()
+                .enumerate()
+                .filter_map(|(j, h)| {
+                    let k = start + j as i64;
+                    (0..len).contains(&k).then(|| input[k as usize] * h)
+                })
+                .sum()
+        }
+    }
+
+    /// Последний входной сэмпл (не включительно), нужный выходному сэмплу `n`
+    fn window_end(&self, n: u64) -> u64 {
+        let (index, _) = self.position(n);
+        (index + self.half_taps as i64 + 1).max(0) as u64
+    }
+}
+
+/// Потоковый ресемплер: вход поступает частями
+///
+/// Выходной сэмпл окончательный, когда всё окно фильтра уже пришло, поэтому
+/// `push` отдаёт только такие сэмплы и каждый вход обрабатывается один раз.
+/// Хвост, которому не хватает входа, `tail` считает с нулями за концом, как
+/// `Resampler::process`: результат совпадает с ресемплингом всего буфера.
+#[derive(Debug, Clone)]
+pub struct StreamResampler {
+    resampler: Resampler,
+    /// Входные сэмплы, ещё нужные фильтру
+    input: Vec<f32>,
+    /// Индекс `input[0]` от начала потока
+    input_offset: u64,
+    /// Количество уже отданных окончательных выходных сэмплов
+    produced: u64,
+}
+
+impl StreamResampler {
+    /// Создаёт потоковый ресемплер из `from_rate` в `to_rate`
+    pub fn new(from_rate: u32, to_rate: u32) -> Self {
+        Self {
+            resampler: Resampler::new(from_rate, to_rate),
+            input: Vec::new(),
+            input_offset: 0,
+            produced: 0,
+        }
+    }
+
+    /// Добавляет вход и возвращает новые окончательные выходные сэмплы
+    pub fn push(&mut self, input: &[f32]) -> Vec<f32> {
+        let r = &self.resampler;
+        if r.up == r.down {
+            self.produced += input.len() as u64;
+            return input.to_vec();
+        }
+
+        self.input.extend_from_slice(input);
+        let available = self.input_offset + self.input.len() as u64;
+
+        let mut output = Vec::new();
+        while r.window_end(self.produced) <= available {
+            output.push(r.sample_at(&self.input, self.input_offset, self.produced));
+            self.produced += 1;
+        }
+
+        // Отбрасываем вход левее окна следующего выходного сэмпла
+        let (index, _) = r.position(self.produced);
+        let needed = (index - r.half_taps as i64 + 1).max(0) as u64;
+        let drop = needed.saturating_sub(self.input_offset).min(self.input.len() as u64);
+        self.input.drain(..drop as usize);
+        self.input_offset += drop;
+
+        output
+    }
+
+    /// Выходные сэмплы после окончательных, если бы поток закончился сейчас
+    pub fn tail(&self) -> Vec<f32> {
+        let r = &self.resampler;
+        if r.up == r.down {
+            return Vec::new();
+        }
+
+        let available = self.input_offset + self.input.len() as u64;
+        (self.produced..r.output_len(available as usize) as u64)
+            .map(|n| r.sample_at(&self.input, self.input_offset, n))
+            .collect()
+    }
+}
+
+/// Ресемплирует буфер из `from_rate` в `to_rate`
+pub fn resample(input: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate || input.is_empty() {
+        return input.to_vec();
+    }
+    Resampler::new(from_rate, to_rate).process(input)
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    a
+}
+
+/// Нормированный sinc: sin(pi x) / (pi x)
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-9 {
+        1.0
+    } else {
+        let px = std::f64::consts::PI * x;
+        px.sin() / px
+    }
+}
+
+/// Окно Кайзера на отрезке [-1, 1]
+fn kaiser(x: f64) -> f64 {
+    if x.abs() > 1.0 {
+        return 0.0;
+    }
+    bessel_i0(KAISER_BETA * (1.0 - x * x).sqrt()) / bessel_i0(KAISER_BETA)
+}
+
+/// Модифицированная функция Бесселя первого рода нулевого порядка
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    for k in 1..50 {
+        term *= half / k as f64;
+        let t2 = term * term;
+        sum += t2;
+        if t2 < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f64, rate: u32, seconds: f64) -> Vec<f32> {
+        let n = (rate as f64 * seconds) as usize;
+        (0..n)
+            .map(|i| (2.0 * std::f64::consts::PI * freq * i as f64 / rate as f64).sin() as f32 * 0.5)
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn test_output_length() {
+        for from in [8000, 22050, 44100, 48000] {
+            let input = vec![0.0f32; from as usize];
+            let output = resample(&input, from, 16000);
+            assert_eq!(output.len(), 16000, "from {} Hz", from);
+        }
+    }
+
+    #[test]
+    fn test_same_rate_is_identity() {
+        let input = sine(440.0, 16000, 0.1);
+        assert_eq!(resample(&input, 16000, 16000), input);
+    }
+
+    #[test]
+    fn test_passband_tone_preserved() {
+        // 1 кГц должен пройти без заметного изменения амплитуды
+        for from in [8000, 22050, 44100, 48000] {
+            let output = resample(&sine(1000.0, from, 0.5), from, 16000);
+            let middle = &output[1000..output.len() - 1000];
+            let level = rms(middle);
+            assert!((level - 0.3536).abs() < 0.01, "from {} Hz: rms {}", from, level);
+        }
+    }
+
+    #[test]
+    fn test_stopband_tone_rejected() {
+        // 12 кГц выше новой частоты Найквиста (8 кГц) и должен быть подавлен
+        let output = resample(&sine(12000.0, 48000, 0.5), 48000, 16000);
+        let middle = &output[1000..output.len() - 1000];
+        assert!(rms(middle) < 0.001, "rms {}", rms(middle));
+    }
+
+    #[test]
+    fn test_stream_matches_whole_buffer() {
+        for from in [8000, 44100, 48000, 44099] {
+            let input = sine(440.0, from, 0.3);
+            let whole = resample(&input, from, 16000);
+
+            let mut stream = StreamResampler::new(from, 16000);
+            let mut output = Vec::new();
+            for chunk in input.chunks(997) {
+                output.extend(stream.push(chunk));
+            }
+            output.extend(stream.tail());
+
+            assert_eq!(output.len(), whole.len(), "from {} Hz", from);
+            let diff = output.iter().zip(&whole).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max);
+            assert!(diff < 1e-6, "from {} Hz: diff {}", from, diff);
+        }
+    }
+
+    #[test]
+    fn test_unusual_rate_uses_rounded_phases() {
+        let resampler = Resampler::new(44099, 16000);
+        assert_eq!(resampler.phases, MAX_PHASES);
+
+        let output = resampler.process(&sine(1000.0, 44099, 0.5));
+        let middle = &output[1000..output.len() - 1000];
+        assert!((rms(middle) - 0.3536).abs() < 0.01);
+    }
+}
@@ -0,0 +1,475 @@
+//! Потоковая сессия распознавания
+//!
+//! Клиент открывает сессию сообщением `start`, присылает аудио частями и
+//! завершает её сообщением `stop`. Сессия декодирует и ресемплирует каждый
+//! фрагмент один раз и отдаёт 16kHz буфер текущего высказывания для
+//! промежуточных (`partial`) и финального результата.
+
+use std::time::Instant;
+
+use crate::audio::{self, AudioError, AudioFormat, ContainerStream, PcmSpec};
+use crate::config::StreamingConfig;
+use crate::postprocess::PostProcessContext;
+use crate::resample::StreamResampler;
+use crate::transcript::Transcript;
+use crate::vad::Vad;
+use crate::whisper::{TranscribeOptions, WHISPER_SAMPLE_RATE};
+
+/// Источник аудио сессии
+enum SessionAudio {
+    /// Ещё не пришло ни одного фрагмента
+    Empty,
+    /// Контейнер (WebM/Ogg от MediaRecorder): продолжения не имеют заголовка,
+    /// поэтому поток живёт всю сессию, в том числе между высказываниями
+    Container(ContainerStream),
+    /// Сырой PCM: каждый фрагмент декодируется сам по себе
+    Pcm,
+}
+
+/// Параметры сессии, заданные клиентом в сообщении `start`
+#[derive(Debug, Clone, Default)]
+pub struct SessionOptions {
+    /// Отправлять сегменты с таймкодами слов в `final`
+    pub details: bool,
+    /// Параметры распознавания (язык, задача, подсказка со словарём)
+    pub transcribe: TranscribeOptions,
+    /// Словарь и сниппеты пользователя и рабочего пространства сессии
+    pub postprocess: PostProcessContext,
+    /// Распознавать голосовые команды редактирования
+    pub commands: bool,
+}
+
+impl SessionOptions {
+    /// Контекст постобработки результата с определённым языком
+    pub fn postprocess_context(&self, language: Option<String>) -> PostProcessContext {
+        PostProcessContext {
+            language,
+            ..self.postprocess.clone()
+        }
+    }
+}
+
+/// Потоковая сессия одного WebSocket соединения
+pub struct StreamSession {
+    id: String,
+    spec: PcmSpec,
+    options: SessionOptions,
+    config: StreamingConfig,
+    audio: SessionAudio,
+    /// Ресемплер в 16kHz, создаётся, когда известна частота источника
+    resampler: Option<StreamResampler>,
+    /// 16kHz сэмплы текущего высказывания: окончательные, затем хвост ресемплера
+    pcm: Vec<f32>,
+    /// Длина хвоста в конце `pcm`; он пересчитывается при поступлении аудио
+    tail_len: usize,
+    /// Количество окончательных 16kHz сэмплов от начала сессии
+    produced: usize,
+    /// Количество 16kHz сэмплов от начала сессии, уже вошедших в финальные
+    /// результаты (индекс `pcm[0]`): в следующее высказывание они не попадают
+    consumed: usize,
+    /// Номер текущего высказывания (увеличивается при сбросе буфера)
+    utterance: u64,
+    /// Количество 16kHz сэмплов на момент последнего partial
+    last_partial_samples: usize,
+    /// Текст последнего partial (повторно не отправляем)
+    last_partial_text: String,
+    /// Все финальные результаты сессии с таймкодами от её начала (для экспорта)
+    transcript: Transcript,
+    /// Тексты финальных результатов после постобработки
+    texts: Vec<String>,
+    /// Длительность аудио, уже зафиксированного в финальных результатах (сек)
+    finished_secs: f64,
+    started_at: Instant,
+}
+
+impl StreamSession {
+    /// Создаёт новую сессию
+    pub fn new(spec: PcmSpec, options: SessionOptions, config: StreamingConfig) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            spec,
+            options,
+            config,
+            audio: SessionAudio::Empty,
+            resampler: None,
+            pcm: Vec::new(),
+            tail_len: 0,
+            produced: 0,
+            consumed: 0,
+            utterance: 0,
+            last_partial_samples: 0,
+            last_partial_text: String::new(),
+            transcript: Transcript::default(),
+            texts: Vec::new(),
+            finished_secs: 0.0,
+            started_at: Instant::now(),
+        }
+    }
+
+    /// Идентификатор сессии
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Параметры сессии
+    pub fn options(&self) -> &SessionOptions {
+        &self.options
+    }
+
+    /// Номер текущего высказывания сессии
+    pub fn utterance(&self) -> u64 {
+        self.utterance
+    }
+
+    /// Время с начала сессии в миллисекундах
+    pub fn elapsed_ms(&self) -> u64 {
+        self.started_at.elapsed().as_millis() as u64
+    }
+
+    /// Добавляет фрагмент аудио в буфер сессии
+    ///
+    /// Контейнер декодируется в потоке декодера, здесь только ожидаем
+    /// его сэмплы. После `max_session_secs` аудио фрагменты отклоняются.
+    pub async fn push_chunk(&mut self, chunk: &[u8]) -> Result<(), AudioError> {
+        if chunk.is_empty() {
+            return Ok(());
+        }
+        let max_secs = self.config.max_session_secs;
+        if self.produced >= ms_to_samples(max_secs * 1000) {
+            return Err(AudioError::TooLong { max_secs });
+        }
+
+        let format = audio::detect_format(chunk);
+        let (samples, rate) = match &mut self.audio {
+            SessionAudio::Empty if format != AudioFormat::RawPcm => {
+                let mut stream = ContainerStream::new(format);
+                stream.push(chunk)?;
+                let samples = stream.decode_new().await?;
+                let rate = stream.sample_rate();
+                self.audio = SessionAudio::Container(stream);
+                (samples, rate)
+            }
+            SessionAudio::Container(stream) => {
+                stream.push(chunk)?;
+                (stream.decode_new().await?, stream.sample_rate())
+            }
+            SessionAudio::Empty | SessionAudio::Pcm => {
+                let samples = audio::decode(chunk, self.spec)?.samples;
+                self.audio = SessionAudio::Pcm;
+                (samples, Some(self.spec.sample_rate))
+            }
+        };
+
+        self.append_source(&samples, rate);
+        Ok(())
+    }
+
+    /// Декодирует остаток контейнера перед финальным результатом `stop`
+    ///
+    /// Пакет, обрезанный границей последнего фрагмента, декодируется
+    /// только при завершении потока.
+    pub async fn finish(&mut self) -> Result<(), AudioError> {
+        if let SessionAudio::Container(stream) = &mut self.audio {
+            let samples = stream.finish().await?;
+            let rate = stream.sample_rate();
+            self.append_source(&samples, rate);
+        }
+        Ok(())
+    }
+
+    /// Ресемплирует декодированные сэмплы источника в буфер высказывания
+    fn append_source(&mut self, samples: &[f32], rate: Option<u32>) {
+        let Some(rate) = rate else {
+            return;
+        };
+        let resampler = self
+            .resampler
+            .get_or_insert_with(|| StreamResampler::new(rate, WHISPER_SAMPLE_RATE));
+        let stable = resampler.push(samples);
+        let tail = resampler.tail();
+
+        self.pcm.truncate(self.pcm.len() - self.tail_len);
+        self.append_pcm(self.produced, &stable);
+        self.produced += stable.len();
+        let before = self.pcm.len();
+        self.append_pcm(self.produced, &tail);
+        self.tail_len = self.pcm.len() - before;
+    }
+
+    /// Добавляет 16kHz сэмплы, первый из которых имеет индекс `start` от
+    /// начала сессии, пропуская уже имеющиеся в буфере или вошедшие в финал
+    fn append_pcm(&mut self, start: usize, samples: &[f32]) {
+        let end = self.consumed + self.pcm.len();
+        let skip = end.saturating_sub(start).min(samples.len());
+        self.pcm.extend_from_slice(&samples[skip..]);
+    }
+
+    /// Возвращает аудио текущего высказывания в 16kHz mono
+    pub fn pcm(&self) -> &[f32] {
+        &self.pcm
+    }
+
+    /// Пора ли отправить промежуточный результат
+    ///
+    /// Partial считается, когда с прошлого раза набралось не меньше
+    /// `partial_interval_ms` нового аудио.
+    pub fn partial_due(&self) -> bool {
+        let interval = ms_to_samples(self.config.partial_interval_ms);
+        let total = self.pcm.len();
+        total >= ms_to_samples(self.config.min_partial_audio_ms)
+            && total.saturating_sub(self.last_partial_samples) >= interval
+    }
+
+    /// Превышен ли максимальный размер буфера
+    ///
+    /// При переполнении накопленное аудио фиксируется как финальный результат
+    /// и буфер начинается заново, чтобы длинная диктовка не росла бесконечно.
+    pub fn buffer_full(&self) -> bool {
+        self.pcm.len() >= ms_to_samples(self.config.max_buffer_secs * 1000)
+    }
+
+    /// Завершено ли высказывание: после речи наступила пауза
+    /// не короче `end_of_utterance_ms`
+    pub fn utterance_complete(&self, vad: &Vad) -> bool {
+        let silence = vad.trailing_silence_ms(&self.pcm);
+        silence.is_some_and(|ms| ms >= self.config.end_of_utterance_ms)
+    }
+
+    /// Начинает partial: возвращает конец высказывания длиной не больше
+    /// `partial_window_secs` для распознавания
+    pub fn start_partial(&mut self) -> Vec<f32> {
+        self.last_partial_samples = self.pcm.len();
+        let window = ms_to_samples(self.config.partial_window_secs * 1000);
+        self.pcm[self.pcm.len().saturating_sub(window)..].to_vec()
+    }
+
+    /// Запоминает отправленный partial; возвращает false, если текст не изменился
+    pub fn record_partial(&mut self, text: &str) -> bool {
+        if text == self.last_partial_text {
+            return false;
+        }
+        self.last_partial_text = text.to_string();
+        true
+    }
+
+    /// Добавляет финальный результат высказывания длительностью `audio_secs`
+    /// в общий результат сессии
+    pub fn record_final(&mut self, transcript: Transcript, text: &str, audio_secs: f64) {
+        self.transcript.append(transcript, self.finished_secs);
+        if !text.is_empty() {
+            self.texts.push(text.to_string());
+        }
+        self.finished_secs += audio_secs;
+    }
+
+    /// Результат всей сессии: сегменты и текст после постобработки
+    pub fn transcript(&self) -> (&Transcript, String) {
+        (&self.transcript, self.texts.join(" "))
+    }
+
+    /// Сбрасывает буфер после финального результата
+    ///
+    /// Источник (заголовок контейнера, состояние декодера и ресемплера)
+    /// сохраняется: следующее высказывание продолжает тот же поток.
+    pub fn reset(&mut self) {
+        self.consumed += self.pcm.len();
+        self.pcm.clear();
+        self.tail_len = 0;
+        self.utterance += 1;
+        self.last_partial_samples = 0;
+        self.last_partial_text.clear();
+    }
+}
+
+fn ms_to_samples(ms: u64) -> usize {
+    (ms * WHISPER_SAMPLE_RATE as u64 / 1000) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pcm_bytes(samples: usize) -> Vec<u8> {
+        vec![0u8; samples * 2]
+    }
+
+    /// Заголовок потокового WAV (float 32-bit, 16kHz mono) без длины данных
+    fn streaming_wav_header() -> Vec<u8> {
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&u32::MAX.to_le_bytes());
+        wav.extend_from_slice(b"WAVE");
+        wav.extend_from_slice(b"fmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&3u16.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&16000u32.to_le_bytes());
+        wav.extend_from_slice(&(16000u32 * 4).to_le_bytes());
+        wav.extend_from_slice(&4u16.to_le_bytes());
+        wav.extend_from_slice(&32u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(u32::MAX - 36).to_le_bytes());
+        wav
+    }
+
+    fn float_bytes(value: f32, samples: usize) -> Vec<u8> {
+        (0..samples).flat_map(|_| value.to_le_bytes()).collect()
+    }
+
+    fn session() -> StreamSession {
+        StreamSession::new(PcmSpec::default(), SessionOptions::default(), StreamingConfig::default())
+    }
+
+    #[tokio::test]
+    async fn test_push_raw_pcm_chunks() {
+        let mut session = session();
+        session.push_chunk(&pcm_bytes(8000)).await.unwrap();
+        session.push_chunk(&pcm_bytes(8000)).await.unwrap();
+
+        assert_eq!(session.pcm().len(), 16000);
+    }
+
+    #[tokio::test]
+    async fn test_declared_rate_is_resampled() {
+        let spec = PcmSpec::from_client(Some(48000), None).unwrap();
+        let mut session = StreamSession::new(spec, SessionOptions::default(), StreamingConfig::default());
+        session.push_chunk(&pcm_bytes(48000)).await.unwrap();
+
+        assert_eq!(session.pcm().len(), 16000);
+    }
+
+    #[tokio::test]
+    async fn test_partial_due_after_interval() {
+        let config = StreamingConfig::default();
+        let interval = ms_to_samples(config.partial_interval_ms);
+        let mut session = StreamSession::new(PcmSpec::default(), SessionOptions::default(), config);
+
+        session.push_chunk(&pcm_bytes(interval / 2)).await.unwrap();
+        assert!(!session.partial_due());
+
+        session.push_chunk(&pcm_bytes(interval)).await.unwrap();
+        assert!(session.partial_due());
+
+        assert_eq!(session.start_partial().len(), interval * 3 / 2);
+        assert!(!session.partial_due());
+        assert!(session.record_partial("привет"));
+        // Тот же текст повторно не отправляется
+        assert!(!session.record_partial("привет"));
+    }
+
+    #[tokio::test]
+    async fn test_partial_window() {
+        let config = StreamingConfig {
+            partial_window_secs: 1,
+            ..StreamingConfig::default()
+        };
+        let mut session = StreamSession::new(PcmSpec::default(), SessionOptions::default(), config);
+        session.push_chunk(&pcm_bytes(40000)).await.unwrap();
+
+        // Распознаётся только последняя секунда высказывания
+        assert_eq!(session.start_partial().len(), 16000);
+        session.reset();
+        assert_eq!(session.utterance(), 1);
+        assert!(session.start_partial().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_utterance_complete_after_pause() {
+        let vad = Vad::new(crate::config::VadConfig::default());
+        let mut session = session();
+
+        // Тон 200 Гц, затем тишина
+        let tone: Vec<u8> = (0..16000)
+            .map(|i| ((2.0 * std::f32::consts::PI * 200.0 * i as f32 / 16000.0).sin() * 8000.0) as i16)
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        session.push_chunk(&tone).await.unwrap();
+        assert!(!session.utterance_complete(&vad));
+
+        session.push_chunk(&pcm_bytes(16000)).await.unwrap();
+        assert!(session.utterance_complete(&vad));
+    }
+
+    #[test]
+    fn test_record_final_offsets_segments() {
+        let utterance = |text: &str| Transcript {
+            segments: vec![crate::transcript::Segment {
+                start: 0.5,
+                end: 1.0,
+                text: text.to_string(),
+                avg_logprob: -0.1,
+                no_speech_prob: None,
+                words: Vec::new(),
+            }],
+            ..Transcript::default()
+        };
+        let mut session = session();
+        session.record_final(utterance("раз"), "Раз.", 2.0);
+        session.record_final(Transcript::default(), "", 1.0);
+        session.record_final(utterance("два"), "Два.", 2.0);
+
+        let (transcript, text) = session.transcript();
+        assert_eq!(text, "Раз. Два.");
+        assert_eq!(transcript.segments[1].start, 3.5);
+    }
+
+    #[tokio::test]
+    async fn test_container_continues_after_reset() {
+        let mut session = session();
+
+        // Пакет, обрезанный границей фрагмента, декодируется со следующим
+        let mut first = streaming_wav_header();
+        first.extend(float_bytes(0.5, 1000));
+        session.push_chunk(&first).await.unwrap();
+        assert!(session.pcm().is_empty());
+
+        session.push_chunk(&float_bytes(0.5, 1304)).await.unwrap();
+        assert_eq!(session.pcm().len(), 2304);
+        session.push_chunk(&float_bytes(0.25, 1152)).await.unwrap();
+        assert_eq!(session.pcm().len(), 3456);
+        session.reset();
+
+        // Продолжение без заголовка декодируется тем же контейнером, а не как сырой PCM
+        session.push_chunk(&float_bytes(0.25, 100)).await.unwrap();
+        assert!(session.pcm().is_empty());
+        session.finish().await.unwrap();
+        let pcm = session.pcm();
+        assert_eq!(pcm.len(), 100);
+        assert!(pcm.iter().all(|s| (s - 0.25).abs() < 1e-6));
+    }
+
+    #[tokio::test]
+    async fn test_session_duration_limit() {
+        let config = StreamingConfig {
+            max_session_secs: 1,
+            ..StreamingConfig::default()
+        };
+        let mut session = StreamSession::new(PcmSpec::default(), SessionOptions::default(), config);
+        session.push_chunk(&pcm_bytes(16000)).await.unwrap();
+
+        let result = session.push_chunk(&pcm_bytes(160)).await;
+        assert!(matches!(result, Err(AudioError::TooLong { max_secs: 1 })));
+    }
+
+    #[tokio::test]
+    async fn test_reset_skips_resampler_tail() {
+        let spec = PcmSpec::from_client(Some(48000), None).unwrap();
+        let mut session = StreamSession::new(spec, SessionOptions::default(), StreamingConfig::default());
+        session.push_chunk(&pcm_bytes(4800)).await.unwrap();
+        assert_eq!(session.pcm().len(), 1600);
+        session.reset();
+
+        // Хвост прошлого высказывания повторно не попадает в следующее
+        session.push_chunk(&pcm_bytes(4800)).await.unwrap();
+        assert_eq!(session.pcm().len(), 1600);
+    }
+
+    #[tokio::test]
+    async fn test_reset_clears_buffer() {
+        let mut session = session();
+        session.push_chunk(&pcm_bytes(1600)).await.unwrap();
+        session.reset();
+
+        assert!(session.pcm().is_empty());
+    }
+}
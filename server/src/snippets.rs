@@ -0,0 +1,333 @@
+//! Голосовые сниппеты: фраза-триггер раскрывается в сохранённый текст
+//!
+//! Пользователь или рабочее пространство хранит сниппеты («вставь мою
+//! подпись» -> текст подписи). Этап постобработки `snippets` находит
+//! фразы-триггеры в финальном тексте (целыми словами, без учёта регистра)
+//! и подставляет тело сниппета. В теле доступны переменные времени сервера:
+//! `{date}`, `{time}`, `{datetime}` и `{date:<strftime>}`; `{{` и `}}` -
+//! фигурные скобки.
+
+use std::sync::Arc;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::config::SnippetsConfig;
+use crate::phrases::{self, Phrases};
+use crate::store::{OwnedStore, Owners, Record, Scope, StoreError};
+
+/// Максимальная длина фразы-триггера (символов)
+const MAX_TRIGGER_CHARS: usize = 100;
+
+/// Максимальная длина тела сниппета (символов)
+const MAX_BODY_CHARS: usize = 10_000;
+
+/// Сниппет
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snippet {
+    pub id: String,
+    /// Фраза, которую произносит пользователь: `вставь мою подпись`
+    pub trigger: String,
+    /// Текст для подстановки, может содержать переменные
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Record for Snippet {
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+/// Тело запроса на создание и изменение сниппета
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SnippetInput {
+    pub trigger: String,
+    pub body: String,
+}
+
+impl SnippetInput {
+    fn normalized(self) -> Result<Self, StoreError> {
+        let trigger = self.trigger.trim().to_string();
+        if phrases::words(&trigger).is_empty() {
+            return Err(StoreError::Invalid("trigger должен содержать буквы или цифры".to_string()));
+        }
+        if trigger.chars().count() > MAX_TRIGGER_CHARS {
+            return Err(StoreError::Invalid(format!("trigger длиннее {} символов", MAX_TRIGGER_CHARS)));
+        }
+        if self.body.trim().is_empty() {
+            return Err(StoreError::Invalid("body не может быть пустым".to_string()));
+        }
+        if self.body.chars().count() > MAX_BODY_CHARS {
+            return Err(StoreError::Invalid(format!("body длиннее {} символов", MAX_BODY_CHARS)));
+        }
+        Template::parse(&self.body).map_err(StoreError::Invalid)?;
+
+        Ok(Self { trigger, body: self.body })
+    }
+}
+
+/// Часть тела сниппета
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    /// Текущее время в формате strftime
+    Now(String),
+}
+
+/// Разобранное тело сниппета
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template(Vec<Part>);
+
+impl Template {
+    /// Разбирает переменные `{name}` и `{name:format}`
+    pub fn parse(body: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = body.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let variable: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Now(Self::format(&variable)?));
+                }
+                '}' => return Err("Непарная } в body: используйте }}".to_string()),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Ok(Self(parts))
+    }
+
+    /// Формат strftime переменной
+    fn format(variable: &str) -> Result<String, String> {
+        let (name, format) = match variable.split_once(':') {
+            Some((name, format)) => (name.trim(), Some(format)),
+            None => (variable.trim(), None),
+        };
+        let default = match name {
+            "date" => "%d.%m.%Y",
+            "time" => "%H:%M",
+            "datetime" => "%d.%m.%Y %H:%M",
+            _ => {
+                return Err(format!(
+                    "Неизвестная переменная {{{}}}, доступны: date, time, datetime",
+                    variable
+                ))
+            }
+        };
+
+        let format = format.unwrap_or(default);
+        if format.is_empty() || StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+            return Err(format!("Некорректный формат {:?} в {{{}}}", format, variable));
+        }
+        Ok(format.to_string())
+    }
+
+    /// Текст сниппета на момент `now`
+    pub fn render(&self, now: &DateTime<Local>) -> String {
+        self.0
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Now(format) => now.format(format).to_string(),
+            })
+            .collect()
+    }
+}
+
+/// Хранилище сниппетов
+pub struct SnippetStore {
+    snippets: OwnedStore<Snippet>,
+}
+
+impl SnippetStore {
+    /// Открывает каталог сниппетов
+    pub fn open(config: &SnippetsConfig) -> Result<Arc<Self>, StoreError> {
+        Ok(Arc::new(Self {
+            snippets: OwnedStore::open("Сниппеты", &config.dir, config.max_snippets)?,
+        }))
+    }
+
+    /// Сниппеты владельца в порядке добавления
+    pub fn list(&self, scope: Scope, owner: &str) -> Result<Arc<Vec<Snippet>>, StoreError> {
+        self.snippets.list(scope, owner)
+    }
+
+    /// Добавляет сниппет
+    pub fn add(&self, scope: Scope, owner: &str, input: SnippetInput) -> Result<Snippet, StoreError> {
+        let input = input.normalized()?;
+        self.check_unique(scope, owner, &input.trigger, None)?;
+        let now = Utc::now();
+        let snippet = Snippet {
+            id: uuid::Uuid::new_v4().to_string(),
+            trigger: input.trigger,
+            body: input.body,
+            created_at: now,
+            updated_at: now,
+        };
+        self.snippets.insert(scope, owner, snippet)
+    }
+
+    /// Заменяет триггер и тело сниппета
+    pub fn update(&self, scope: Scope, owner: &str, id: &str, input: SnippetInput) -> Result<Snippet, StoreError> {
+        let input = input.normalized()?;
+        self.check_unique(scope, owner, &input.trigger, Some(id))?;
+        self.snippets.update(scope, owner, id, |snippet| {
+            snippet.trigger = input.trigger;
+            snippet.body = input.body;
+            snippet.updated_at = Utc::now();
+        })
+    }
+
+    /// Удаляет сниппет
+    pub fn remove(&self, scope: Scope, owner: &str, id: &str) -> Result<(), StoreError> {
+        self.snippets.remove(scope, owner, id)
+    }
+
+    /// Сниппеты пользователя и рабочего пространства
+    ///
+    /// При одинаковых триггерах выигрывает сниппет пользователя.
+    pub fn for_owners(&self, owners: &Owners) -> Option<Arc<Snippets>> {
+        let snippets = Snippets::new(self.snippets.merged(owners));
+        (!snippets.is_empty()).then(|| Arc::new(snippets))
+    }
+
+    /// Триггер не должен совпадать с триггером другого сниппета владельца
+    fn check_unique(&self, scope: Scope, owner: &str, trigger: &str, id: Option<&str>) -> Result<(), StoreError> {
+        let words = |text: &str| phrases::words(text).into_iter().map(|(word, _)| word).collect::<Vec<_>>();
+        let trigger_words = words(trigger);
+        let duplicate = self
+            .list(scope, owner)?
+            .iter()
+            .any(|snippet| Some(snippet.id.as_str()) != id && words(&snippet.trigger) == trigger_words);
+        if duplicate {
+            return Err(StoreError::Invalid(format!("Сниппет с фразой «{}» уже есть", trigger)));
+        }
+        Ok(())
+    }
+}
+
+/// Сниппеты, применяемые к одному запросу
+#[derive(Debug)]
+pub struct Snippets {
+    triggers: Phrases<Arc<Template>>,
+}
+
+impl Snippets {
+    pub fn new(snippets: Vec<Snippet>) -> Self {
+        let mut triggers = Phrases::default();
+        for snippet in snippets {
+            match Template::parse(&snippet.body) {
+                Ok(template) => {
+                    triggers.insert(&snippet.trigger, Arc::new(template));
+                }
+                Err(e) => warn!("Сниппет {} пропущен: {}", snippet.id, e),
+            }
+        }
+        Self { triggers }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triggers.is_empty()
+    }
+
+    /// Раскрывает триггеры в тексте
+    pub fn expand(&self, text: &str, now: &DateTime<Local>) -> String {
+        self.triggers.replace(text, |template| template.render(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn snippet(trigger: &str, body: &str) -> Snippet {
+        Snippet {
+            id: trigger.to_string(),
+            trigger: trigger.to_string(),
+            body: body.to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_template_variables() {
+        let now = Local.with_ymd_and_hms(2026, 10, 17, 9, 5, 0).unwrap();
+        let template = Template::parse("{date} в {time}, {{дата}} {date:%Y-%m-%d} {datetime}").unwrap();
+        assert_eq!(template.render(&now), "17.10.2026 в 09:05, {дата} 2026-10-17 17.10.2026 09:05");
+
+        assert!(Template::parse("{weather}").unwrap_err().contains("weather"));
+        assert!(Template::parse("{date:%Q}").is_err());
+        assert!(Template::parse("a } b").is_err());
+    }
+
+    #[test]
+    fn test_expand_triggers() {
+        let now = Local.with_ymd_and_hms(2026, 10, 17, 9, 5, 0).unwrap();
+        let snippets = Snippets::new(vec![
+            snippet("вставь мою подпись", "С уважением,\nИван"),
+            snippet("адрес офиса", "Москва, ул. Тверская, 1"),
+            snippet("сегодняшняя дата", "{date}"),
+        ]);
+
+        assert_eq!(
+            snippets.expand("Спасибо. Вставь мою подпись", &now),
+            "Спасибо. С уважением,\nИван"
+        );
+        assert_eq!(
+            snippets.expand("Приезжайте, адрес офиса. Встреча сегодняшняя дата.", &now),
+            "Приезжайте, Москва, ул. Тверская, 1. Встреча 17.10.2026."
+        );
+        assert_eq!(snippets.expand("адрес, офиса", &now), "адрес, офиса");
+    }
+
+    #[test]
+    fn test_store_rejects_duplicate_triggers() {
+        let dir = std::env::temp_dir().join(format!("alfavoice-snippets-{}", uuid::Uuid::new_v4()));
+        let config = SnippetsConfig {
+            dir: dir.clone(),
+            ..SnippetsConfig::default()
+        };
+        let store = SnippetStore::open(&config).unwrap();
+        let input = |trigger: &str, body: &str| SnippetInput {
+            trigger: trigger.to_string(),
+            body: body.to_string(),
+        };
+
+        let added = store.add(Scope::User, "u1", input("адрес офиса", "Тверская, 1")).unwrap();
+        assert!(matches!(store.add(Scope::User, "u1", input("Адрес  офиса", "x")), Err(StoreError::Invalid(_))));
+        assert!(matches!(store.add(Scope::User, "u1", input("подпись", "{unknown}")), Err(StoreError::Invalid(_))));
+        store.update(Scope::User, "u1", &added.id, input("адрес офиса", "Тверская, 2")).unwrap();
+
+        let owners = Owners {
+            user: Some("u1".to_string()),
+            workspace: None,
+        };
+        let snippets = store.for_owners(&owners).unwrap();
+        assert_eq!(snippets.expand("адрес офиса", &Local::now()), "Тверская, 2");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
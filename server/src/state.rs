@@ -1,8 +1,20 @@
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tracing::info;
 
+use crate::config::{AppConfig, LimitsConfig, StreamingConfig, VadConfig};
+use crate::dictionary::DictionaryStore;
+use crate::snippets::SnippetStore;
+use crate::styles::StyleStore;
+use crate::voice_commands::Grammar;
+use crate::formatting::{AppContext, Formatting, Rules};
+use crate::auth::Authenticator;
+use crate::jobs::JobManager;
+use crate::vad::Vad;
 use crate::whisper::WhisperModel;
 use crate::llm::LlmModel;
+use crate::postprocess::{Models, Pipeline, PostProcessContext, Processed};
+use crate::store::{Owners, StoreError};
 
 #[cfg(feature = "nlp")]
 use crate::nlp::BertModel;
@@ -15,6 +27,25 @@ pub struct AppState {
     pub llm_model: Arc<LlmModel>,
     #[cfg(feature = "nlp")]
     pub bert_model: Option<Arc<BertModel>>,
+    /// Конвейер постобработки, собранный из моделей и `postprocessing.stages`
+    pub postprocessor: Arc<Pipeline>,
+    pub streaming_config: StreamingConfig,
+    pub vad: Arc<Vad>,
+    pub limits: LimitsConfig,
+    /// Очередь заданий (`None`, если каталог заданий недоступен)
+    pub jobs: Option<Arc<JobManager>>,
+    /// Словари пользователей и рабочих пространств (`None`, если каталог недоступен)
+    pub dictionaries: Option<Arc<DictionaryStore>>,
+    /// Сниппеты пользователей и рабочих пространств (`None`, если каталог недоступен)
+    pub snippets: Option<Arc<SnippetStore>>,
+    /// Профили стилей (`None`, если файл профилей недоступен)
+    pub styles: Option<Arc<StyleStore>>,
+    /// Грамматика голосовых команд (`None`, если команды отключены)
+    pub voice_commands: Option<Arc<Grammar>>,
+    /// Правила форматирования по приложению (`None`, если отключены)
+    pub formatting: Option<Arc<Rules>>,
+    /// Проверка API ключей и JWT (`None`, если аутентификация отключена)
+    pub auth: Option<Arc<Authenticator>>,
 }
 
 /// Информация о подключенном клиенте
@@ -22,6 +53,8 @@ pub struct AppState {
 pub struct ClientInfo {
     pub id: String,
     pub connected_at: chrono::DateTime<chrono::Utc>,
+    /// Пользователь соединения (с аутентификацией)
+    pub user: Option<String>,
 }
 
 impl AppState {
@@ -32,69 +65,139 @@ impl AppState {
             llm_model: Arc::new(LlmModel::default()),
             #[cfg(feature = "nlp")]
             bert_model: None,
+            postprocessor: Arc::new(Pipeline::default()),
+            streaming_config: StreamingConfig::default(),
+            vad: Arc::new(Vad::new(VadConfig::default())),
+            limits: LimitsConfig::default(),
+            jobs: None,
+            dictionaries: None,
+            snippets: None,
+            styles: None,
+            voice_commands: None,
+            formatting: None,
+            auth: None,
         }
     }
 
-    pub fn with_whisper_model(whisper_model: Arc<WhisperModel>) -> Self {
-        Self {
-            clients: Arc::new(RwLock::new(Vec::new())),
-            whisper_model: Some(whisper_model),
-            llm_model: Arc::new(LlmModel::default()),
-            #[cfg(feature = "nlp")]
-            bert_model: None,
-        }
+    /// Подключает модель Whisper
+    pub fn with_whisper_model(mut self, whisper_model: Arc<WhisperModel>) -> Self {
+        self.whisper_model = Some(whisper_model);
+        self
     }
 
-    pub fn with_llm_model(llm_model: Arc<LlmModel>) -> Self {
-        Self {
-            clients: Arc::new(RwLock::new(Vec::new())),
-            whisper_model: None,
-            llm_model,
-            #[cfg(feature = "nlp")]
-            bert_model: None,
-        }
+    /// Подключает модель LLM для постобработки
+    pub fn with_llm_model(mut self, llm_model: Arc<LlmModel>) -> Self {
+        self.llm_model = llm_model;
+        self
     }
 
-    pub fn with_models(whisper_model: Option<Arc<WhisperModel>>, llm_model: Arc<LlmModel>) -> Self {
-        Self {
-            clients: Arc::new(RwLock::new(Vec::new())),
-            whisper_model,
-            llm_model,
+    /// Подключает модель BERT для постобработки
+    #[cfg(feature = "nlp")]
+    pub fn with_bert_model(mut self, bert_model: Arc<BertModel>) -> Self {
+        self.bert_model = Some(bert_model);
+        self
+    }
+
+    /// Применяет настройки потоковых сессий, VAD и ограничений из конфигурации
+    /// и собирает конвейер постобработки из подключённых моделей
+    pub fn configure(mut self, config: &AppConfig) -> Self {
+        let models = Models {
+            llm: self.llm_model.clone(),
             #[cfg(feature = "nlp")]
-            bert_model: None,
-        }
+            bert: self.bert_model.clone(),
+        };
+        let pipeline = Pipeline::build(&config.postprocessing, &models);
+        info!("Конвейер постобработки: [{}]", pipeline.names().join(", "));
+        self.postprocessor = Arc::new(pipeline);
+        self.streaming_config = config.streaming.clone();
+        self.vad = Arc::new(Vad::new(config.vad.clone()));
+        self.limits = config.limits.clone();
+        self.voice_commands = config
+            .voice_commands
+            .enabled
+            .then(|| Arc::new(Grammar::new(&config.voice_commands)));
+        self.formatting = config.formatting.enabled.then(|| Arc::new(Rules::new(&config.formatting)));
+        self
     }
 
-    #[cfg(feature = "nlp")]
-    pub fn with_bert_model(bert_model: Arc<BertModel>) -> Self {
-        Self {
-            clients: Arc::new(RwLock::new(Vec::new())),
-            whisper_model: None,
-            llm_model: Arc::new(LlmModel::default()),
-            bert_model: Some(bert_model),
-        }
+    /// Подключает очередь заданий
+    pub fn with_jobs(mut self, jobs: Arc<JobManager>) -> Self {
+        self.jobs = Some(jobs);
+        self
     }
 
-    #[cfg(feature = "nlp")]
-    pub fn with_all_models(
-        whisper_model: Option<Arc<WhisperModel>>,
-        llm_model: Arc<LlmModel>,
-        bert_model: Option<Arc<BertModel>>,
-    ) -> Self {
-        Self {
-            clients: Arc::new(RwLock::new(Vec::new())),
-            whisper_model,
-            llm_model,
-            bert_model,
+    /// Подключает словари
+    pub fn with_dictionaries(mut self, dictionaries: Arc<DictionaryStore>) -> Self {
+        self.dictionaries = Some(dictionaries);
+        self
+    }
+
+    /// Подключает сниппеты
+    pub fn with_snippets(mut self, snippets: Arc<SnippetStore>) -> Self {
+        self.snippets = Some(snippets);
+        self
+    }
+
+    /// Включает аутентификацию
+    pub fn with_auth(mut self, auth: Arc<Authenticator>) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Подключает профили стилей
+    pub fn with_styles(mut self, styles: Arc<StyleStore>) -> Self {
+        self.styles = Some(styles);
+        self
+    }
+
+    /// Стиль и форматирование запроса по выбору клиента и приложению
+    ///
+    /// Ошибка - только если клиент выбрал несуществующий стиль.
+    pub fn apply_target(
+        &self,
+        context: &mut PostProcessContext,
+        style: Option<&str>,
+        target: &AppContext,
+    ) -> Result<(), StoreError> {
+        let formatting = self.formatting.as_ref().map(|rules| rules.resolve(target)).unwrap_or_default();
+        context.style = match (&self.styles, style) {
+            (Some(styles), _) => styles.select(style, formatting.style.as_deref(), target.app.as_deref())?,
+            (None, Some(id)) => return Err(StoreError::NotFound(format!("Стиль {}", id))),
+            (None, None) => None,
+        };
+        context.formatting = formatting;
+        Ok(())
+    }
+
+    /// Контекст постобработки со словарём и сниппетами пользователя и
+    /// рабочего пространства запроса
+    pub fn postprocess_context(&self, owners: &Owners) -> PostProcessContext {
+        PostProcessContext {
+            language: None,
+            vocabulary: self.dictionaries.as_ref().and_then(|store| store.vocabulary(owners)),
+            snippets: self.snippets.as_ref().and_then(|store| store.for_owners(owners)),
+            style: None,
+            formatting: Formatting::default(),
         }
     }
 
+    /// Постобработка текста конвейером этапов
+    pub async fn post_process(&self, text: String) -> String {
+        self.postprocessor.run(text, &PostProcessContext::default()).await.text
+    }
+
+    /// Постобработка с контекстом распознавания и временем этапов
+    pub async fn post_process_detailed(&self, text: String, context: &PostProcessContext) -> Processed {
+        self.postprocessor.run(text, context).await
+    }
+
     /// Добавляет клиента в список
-    pub async fn add_client(&self, client_id: String) {
+    pub async fn add_client(&self, client_id: String, user: Option<String>) {
         let mut clients = self.clients.write().await;
         clients.push(ClientInfo {
             id: client_id,
             connected_at: chrono::Utc::now(),
+            user,
         });
     }
 
@@ -108,6 +211,11 @@ impl AppState {
     pub async fn client_count(&self) -> usize {
         self.clients.read().await.len()
     }
+
+    /// Количество соединений пользователя
+    pub async fn user_client_count(&self, user: &str) -> usize {
+        self.clients.read().await.iter().filter(|c| c.user.as_deref() == Some(user)).count()
+    }
 }
 
 impl Default for AppState {
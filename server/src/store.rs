@@ -0,0 +1,250 @@
+//! Хранилище записей пользователей и рабочих пространств
+//!
+//! Записи одного владельца (словарь, набор сниппетов) хранятся JSON массивом
+//! в `<dir>/<scope>/<owner>.json`, читаются при первом обращении и
+//! перезаписываются атомарно (через временный файл) при каждом изменении.
+//! `read_json` и `write_json` используют и другие хранилища в JSON файлах.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{info, warn};
+
+/// Ошибки хранилища
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error("Ошибка ввода-вывода {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Некорректный файл {path}: {source}")]
+    Corrupted {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Некорректный id владельца: {0:?}")]
+    InvalidOwner(String),
+
+    #[error("{0}")]
+    Invalid(String),
+
+    #[error("Запись {0} не найдена")]
+    NotFound(String),
+
+    #[error("Достигнут предел: максимум {0} записей")]
+    Full(usize),
+}
+
+/// Владелец записей
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    User,
+    Workspace,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::User => "user",
+            Scope::Workspace => "workspace",
+        }
+    }
+}
+
+/// Пользователь и рабочее пространство запроса
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Owners {
+    pub user: Option<String>,
+    pub workspace: Option<String>,
+}
+
+/// Запись с уникальным в пределах владельца id
+pub trait Record: Clone + Serialize + DeserializeOwned {
+    fn id(&self) -> &str;
+}
+
+type Key = (Scope, String);
+
+/// Записи владельцев одного вида
+pub struct OwnedStore<T> {
+    /// Название для логов: «Словарь», «Сниппеты»
+    label: &'static str,
+    dir: PathBuf,
+    max_records: usize,
+    records: Mutex<HashMap<Key, Arc<Vec<T>>>>,
+}
+
+impl<T: Record> OwnedStore<T> {
+    /// Открывает каталог, создавая подкаталоги `user` и `workspace`
+    pub fn open(label: &'static str, dir: &Path, max_records: usize) -> Result<Self, StoreError> {
+        for scope in [Scope::User, Scope::Workspace] {
+            let dir = dir.join(scope.as_str());
+            std::fs::create_dir_all(&dir).map_err(|source| StoreError::Io { path: dir, source })?;
+        }
+
+        Ok(Self {
+            label,
+            dir: dir.to_path_buf(),
+            max_records,
+            records: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Записи владельца в порядке добавления
+    pub fn list(&self, scope: Scope, owner: &str) -> Result<Arc<Vec<T>>, StoreError> {
+        let key = key(scope, owner)?;
+        let mut records = self.lock();
+        self.records(&mut records, &key)
+    }
+
+    /// Записи пользователя, затем его рабочего пространства
+    ///
+    /// Недоступные записи не мешают распознаванию: ошибка только пишется в лог.
+    pub fn merged(&self, owners: &Owners) -> Vec<T> {
+        let sources = [(Scope::User, &owners.user), (Scope::Workspace, &owners.workspace)];
+        let mut merged = Vec::new();
+        for (scope, owner) in sources {
+            let Some(owner) = owner else {
+                continue;
+            };
+            match self.list(scope, owner) {
+                Ok(records) => merged.extend(records.iter().cloned()),
+                Err(e) => warn!("{} {} {} недоступен: {}", self.label, scope.as_str(), owner, e),
+            }
+        }
+        merged
+    }
+
+    /// Добавляет запись
+    pub fn insert(&self, scope: Scope, owner: &str, record: T) -> Result<T, StoreError> {
+        let max_records = self.max_records;
+        self.modify(scope, owner, |records| {
+            if records.len() >= max_records {
+                return Err(StoreError::Full(max_records));
+            }
+            records.push(record.clone());
+            Ok(record)
+        })
+    }
+
+    /// Изменяет запись по id
+    pub fn update(
+        &self,
+        scope: Scope,
+        owner: &str,
+        id: &str,
+        change: impl FnOnce(&mut T),
+    ) -> Result<T, StoreError> {
+        self.modify(scope, owner, |records| {
+            let record = records
+                .iter_mut()
+                .find(|record| record.id() == id)
+                .ok_or_else(|| StoreError::NotFound(id.to_string()))?;
+            change(record);
+            Ok(record.clone())
+        })
+    }
+
+    /// Удаляет запись по id
+    pub fn remove(&self, scope: Scope, owner: &str, id: &str) -> Result<(), StoreError> {
+        self.modify(scope, owner, |records| {
+            let index = records
+                .iter()
+                .position(|record| record.id() == id)
+                .ok_or_else(|| StoreError::NotFound(id.to_string()))?;
+            records.remove(index);
+            Ok(())
+        })
+    }
+
+    /// Изменяет записи владельца и сохраняет их на диск под блокировкой
+    fn modify<R>(
+        &self,
+        scope: Scope,
+        owner: &str,
+        change: impl FnOnce(&mut Vec<T>) -> Result<R, StoreError>,
+    ) -> Result<R, StoreError> {
+        let key = key(scope, owner)?;
+        let mut cache = self.lock();
+        let mut records = self.records(&mut cache, &key)?.as_ref().clone();
+        let result = change(&mut records)?;
+
+        write_json(&self.path(&key), &records)?;
+        info!("{} {} {}: {} записей", self.label, scope.as_str(), owner, records.len());
+        cache.insert(key, Arc::new(records));
+        Ok(result)
+    }
+
+    fn records(&self, cache: &mut HashMap<Key, Arc<Vec<T>>>, key: &Key) -> Result<Arc<Vec<T>>, StoreError> {
+        if let Some(records) = cache.get(key) {
+            return Ok(records.clone());
+        }
+
+        let records: Arc<Vec<T>> = Arc::new(read_json(&self.path(key))?.unwrap_or_default());
+        cache.insert(key.clone(), records.clone());
+        Ok(records)
+    }
+
+    fn path(&self, (scope, owner): &Key) -> PathBuf {
+        self.dir.join(scope.as_str()).join(format!("{}.json", owner))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Key, Arc<Vec<T>>>> {
+        self.records.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Читает JSON файл; `None`, если файла нет
+pub fn read_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, StoreError> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => serde_json::from_str(&text).map(Some).map_err(|source| StoreError::Corrupted {
+            path: path.to_path_buf(),
+            source,
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(StoreError::Io {
+            path: path.to_path_buf(),
+            source,
+        }),
+    }
+}
+
+/// Атомарно перезаписывает JSON файл через временный файл
+pub fn write_json<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<(), StoreError> {
+    let tmp = path.with_extension("json.tmp");
+    let json = serde_json::to_vec_pretty(value).map_err(|source| StoreError::Corrupted {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    std::fs::write(&tmp, json)
+        .and_then(|_| std::fs::rename(&tmp, path))
+        .map_err(|source| StoreError::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+/// Проверяет id владельца: он становится именем файла
+fn key(scope: Scope, owner: &str) -> Result<Key, StoreError> {
+    let valid = !owner.is_empty()
+        && owner.len() <= 128
+        && !owner.starts_with('.')
+        && owner
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '@'));
+    if !valid {
+        return Err(StoreError::InvalidOwner(owner.to_string()));
+    }
+    Ok((scope, owner.to_string()))
+}
@@ -0,0 +1,498 @@
+//! Стили переписывания текста: деловой, разговорный, письмо, комментарий к коду, список
+//!
+//! Профиль стиля задаёт инструкцию LLM (`prompt`), температуру, максимальную
+//! длину и ограничения вывода. Этап постобработки `style` переписывает текст
+//! LLM по инструкции профиля, а если LLM отключена или недоступна - применяет
+//! ограничения детерминированно: убирает слова-паразиты, раскладывает текст
+//! списком, добавляет префикс строк и обрезает по длине.
+//!
+//! Профили хранятся в одном JSON файле (`styles.path`), при первом запуске
+//! он создаётся со встроенными профилями. Стиль выбирается клиентом
+//! (`style` в запросе или `start`), правилом `formatting.rules`, по
+//! приложению (`styles.apps`) или берётся `styles.default`.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::config::StylesConfig;
+use crate::phrases::Phrases;
+use crate::store::{self, StoreError};
+
+/// Профиль стиля
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StyleProfile {
+    /// Идентификатор: `formal`, `email`
+    pub id: String,
+    #[serde(flatten)]
+    pub settings: StyleSettings,
+}
+
+/// Настройки профиля (тело `PUT /v1/styles/{id}`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StyleSettings {
+    /// Название для интерфейса
+    pub name: String,
+    /// Системный промпт LLM; без него стиль применяется без LLM
+    #[serde(default)]
+    pub prompt: String,
+    /// Температура вместо `llm.temperature`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    /// Максимальная длина результата (символов)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_chars: Option<usize>,
+    #[serde(default)]
+    pub constraints: Constraints,
+}
+
+/// Ограничения вывода
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Constraints {
+    /// Раскладка текста без LLM
+    pub layout: Layout,
+    /// Префикс каждой строки: `// ` для комментария к коду
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_prefix: Option<String>,
+    /// Слова и фразы, удаляемые без LLM: `ну`, `короче`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub remove_words: Vec<String>,
+}
+
+/// Раскладка текста
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layout {
+    #[default]
+    Paragraph,
+    /// Каждое предложение - пункт `- ...`
+    BulletList,
+}
+
+impl StyleSettings {
+    fn validate(&self) -> Result<(), StoreError> {
+        let invalid = |message: &str| Err(StoreError::Invalid(message.to_string()));
+        if self.name.trim().is_empty() || self.name.chars().count() > 100 {
+            return invalid("name должно быть от 1 до 100 символов");
+        }
+        if self.prompt.chars().count() > 4000 {
+            return invalid("prompt длиннее 4000 символов");
+        }
+        if self.temperature.is_some_and(|t| !(0.0..=2.0).contains(&t)) {
+            return invalid("temperature должна быть от 0 до 2");
+        }
+        if self.max_chars.is_some_and(|max| !(1..=100_000).contains(&max)) {
+            return invalid("max_chars должно быть от 1 до 100000");
+        }
+        if self.constraints.line_prefix.as_ref().is_some_and(|prefix| prefix.chars().count() > 16) {
+            return invalid("line_prefix длиннее 16 символов");
+        }
+        if self.constraints.remove_words.len() > 100 {
+            return invalid("Не больше 100 слов в remove_words");
+        }
+        Ok(())
+    }
+}
+
+impl StyleProfile {
+    /// Стиль без LLM: все ограничения профиля
+    pub fn apply(&self, text: &str) -> String {
+        let constraints = &self.settings.constraints;
+        let mut text = remove_words(text, &constraints.remove_words);
+        if constraints.layout == Layout::BulletList {
+            text = sentences(&text)
+                .into_iter()
+                .map(|sentence| format!("- {}", sentence.trim_end_matches('.')))
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+        self.finish(&text)
+    }
+
+    /// Ограничения, обязательные и для ответа LLM: длина и префикс строк
+    pub fn finish(&self, text: &str) -> String {
+        let text = match self.settings.max_chars {
+            Some(max_chars) => truncate(text, max_chars),
+            None => text.to_string(),
+        };
+        match &self.settings.constraints.line_prefix {
+            Some(prefix) => text
+                .lines()
+                .map(|line| format!("{}{}", prefix, line).trim_end().to_string())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            None => text,
+        }
+    }
+}
+
+/// Предложения текста вместе с завершающими знаками
+fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let next_is_space = chars.peek().is_none_or(|(_, next)| next.is_whitespace());
+        if c == '\n' || (matches!(c, '.' | '!' | '?' | '…') && next_is_space) {
+            let end = index + c.len_utf8();
+            if !text[start..end].trim().is_empty() {
+                sentences.push(text[start..end].trim());
+            }
+            start = end;
+        }
+    }
+    if !text[start..].trim().is_empty() {
+        sentences.push(text[start..].trim());
+    }
+    sentences
+}
+
+/// Обрезает текст до `max_chars` по концу предложения или слова
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(max_chars).collect();
+    let sentence_end = cut.rfind(['.', '!', '?', '…']).filter(|&end| end >= cut.len() / 2);
+    let end = match sentence_end {
+        Some(end) => end + cut[end..].chars().next().map_or(0, char::len_utf8),
+        None => cut.rfind(char::is_whitespace).unwrap_or(cut.len()),
+    };
+    cut[..end].trim_end().trim_end_matches([',', ';', ':', '-', '—']).to_string()
+}
+
+/// Удаляет фразы целыми словами вместе с запятой после них
+fn remove_words(text: &str, words: &[String]) -> String {
+    let mut phrases = Phrases::default();
+    for word in words {
+        phrases.insert(word, ());
+    }
+    if phrases.is_empty() {
+        return text.to_string();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut cursor = 0;
+    for (range, _) in phrases.find(text) {
+        out.push_str(&text[cursor..range.start]);
+        // Перевод строки после фразы остаётся: он разделяет пункты и абзацы
+        cursor = range.end
+            + text[range.end..]
+                .find(|c: char| !(c.is_whitespace() && c != '\n') && c != ',')
+                .unwrap_or(text.len() - range.end);
+    }
+    out.push_str(&text[cursor..]);
+
+    // Пробелы схлопываются по строкам, как в этапе normalize; первое слово
+    // строки и предложения после удаления - с заглавной буквы
+    let lines: Vec<String> = out
+        .trim()
+        .lines()
+        .map(|line| {
+            let mut capitalize = true;
+            let mut result = String::with_capacity(line.len());
+            for word in line.split_whitespace() {
+                if !result.is_empty() {
+                    result.push(' ');
+                }
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) if capitalize => result.extend(first.to_uppercase().chain(chars)),
+                    _ => result.push_str(word),
+                }
+                capitalize = word.ends_with(['.', '!', '?', '…']);
+            }
+            result
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// Хранилище профилей стилей
+pub struct StyleStore {
+    path: PathBuf,
+    default: Option<String>,
+    apps: BTreeMap<String, String>,
+    styles: Mutex<Vec<Arc<StyleProfile>>>,
+}
+
+impl StyleStore {
+    /// Читает профили; если файла нет, создаёт его со встроенными профилями
+    pub fn open(config: &StylesConfig) -> Result<Arc<Self>, StoreError> {
+        let styles = match store::read_json::<Vec<StyleProfile>>(&config.path)? {
+            Some(styles) => styles,
+            None => {
+                if let Some(dir) = config.path.parent() {
+                    std::fs::create_dir_all(dir).map_err(|source| StoreError::Io {
+                        path: dir.to_path_buf(),
+                        source,
+                    })?;
+                }
+                let styles = builtin();
+                store::write_json(&config.path, &styles)?;
+                info!("Созданы встроенные стили: {}", config.path.display());
+                styles
+            }
+        };
+
+        let store = Self {
+            path: config.path.clone(),
+            default: config.default.clone(),
+            apps: config.apps.clone(),
+            styles: Mutex::new(styles.into_iter().map(Arc::new).collect()),
+        };
+        for id in store.default.iter().chain(store.apps.values()) {
+            if store.get(id).is_none() {
+                warn!("Стиль {} из настроек styles не найден", id);
+            }
+        }
+        Ok(Arc::new(store))
+    }
+
+    pub fn list(&self) -> Vec<StyleProfile> {
+        self.lock().iter().map(|style| style.as_ref().clone()).collect()
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<StyleProfile>> {
+        self.lock().iter().find(|style| style.id == id).cloned()
+    }
+
+    /// Создаёт или заменяет профиль; `true`, если профиль создан
+    pub fn put(&self, id: &str, settings: StyleSettings) -> Result<(StyleProfile, bool), StoreError> {
+        let valid_id = (1..=64).contains(&id.len())
+            && id.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-');
+        if !valid_id {
+            return Err(StoreError::Invalid(format!(
+                "Некорректный id стиля {:?}: строчные латинские буквы, цифры, _ и -",
+                id
+            )));
+        }
+        settings.validate()?;
+
+        let profile = StyleProfile {
+            id: id.to_string(),
+            settings,
+        };
+        let mut styles = self.lock();
+        let mut updated = styles.clone();
+        let created = match updated.iter_mut().find(|style| style.id == id) {
+            Some(style) => {
+                *style = Arc::new(profile.clone());
+                false
+            }
+            None => {
+                updated.push(Arc::new(profile.clone()));
+                true
+            }
+        };
+        self.save(&updated)?;
+        *styles = updated;
+        info!("Стиль {} {}", id, if created { "создан" } else { "изменён" });
+        Ok((profile, created))
+    }
+
+    pub fn remove(&self, id: &str) -> Result<(), StoreError> {
+        let mut styles = self.lock();
+        let mut updated = styles.clone();
+        let index = updated
+            .iter()
+            .position(|style| style.id == id)
+            .ok_or_else(|| StoreError::NotFound(id.to_string()))?;
+        updated.remove(index);
+        self.save(&updated)?;
+        *styles = updated;
+        info!("Стиль {} удалён", id);
+        Ok(())
+    }
+
+    /// Стиль запроса: выбранный клиентом, правилом форматирования
+    /// (`formatting.rules`), по приложению или по умолчанию
+    ///
+    /// Ошибка - только если клиент выбрал несуществующий стиль.
+    pub fn select(
+        &self,
+        requested: Option<&str>,
+        rule: Option<&str>,
+        app: Option<&str>,
+    ) -> Result<Option<Arc<StyleProfile>>, StoreError> {
+        if let Some(id) = requested {
+            return self.get(id).map(Some).ok_or_else(|| StoreError::NotFound(format!("Стиль {}", id)));
+        }
+        let configured = rule
+            .or_else(|| app.and_then(|app| self.apps.get(app)).map(String::as_str))
+            .or(self.default.as_deref());
+        let style = configured.and_then(|id| self.get(id));
+        if let (Some(id), None) = (configured, &style) {
+            warn!("Стиль {} из настроек не найден", id);
+        }
+        Ok(style)
+    }
+
+    fn save(&self, styles: &[Arc<StyleProfile>]) -> Result<(), StoreError> {
+        let styles: Vec<&StyleProfile> = styles.iter().map(Arc::as_ref).collect();
+        store::write_json(&self.path, &styles)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Arc<StyleProfile>>> {
+        self.styles.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Встроенные профили
+fn builtin() -> Vec<StyleProfile> {
+    let fillers = || ["ну", "короче", "типа", "как бы", "в общем", "блин"].map(String::from).to_vec();
+    let profile = |id: &str, name: &str, prompt: &str, temperature: f64, max_chars, constraints| StyleProfile {
+        id: id.to_string(),
+        settings: StyleSettings {
+            name: name.to_string(),
+            prompt: prompt.to_string(),
+            temperature: Some(temperature),
+            max_chars,
+            constraints,
+        },
+    };
+
+    vec![
+        profile(
+            "formal",
+            "Деловой",
+            "Перепиши продиктованный текст в деловом стиле: полные предложения, нейтральная лексика, \
+             без разговорных слов и междометий. Сохрани смысл и язык оригинала, ничего не добавляй. \
+             Ответь только текстом.",
+            0.3,
+            None,
+            Constraints {
+                remove_words: fillers(),
+                ..Constraints::default()
+            },
+        ),
+        profile(
+            "casual",
+            "Разговорный",
+            "Перепиши продиктованный текст в дружелюбном разговорном стиле, коротко и просто. \
+             Сохрани смысл и язык оригинала. Ответь только текстом.",
+            0.7,
+            None,
+            Constraints::default(),
+        ),
+        profile(
+            "email",
+            "Письмо",
+            "Оформи продиктованный текст как письмо: приветствие, основной текст короткими абзацами, \
+             вежливое завершение. Не придумывай имён и фактов. Сохрани язык оригинала. \
+             Ответь только текстом письма.",
+            0.4,
+            None,
+            Constraints {
+                remove_words: fillers(),
+                ..Constraints::default()
+            },
+        ),
+        profile(
+            "code_comment",
+            "Комментарий к коду",
+            "Перепиши продиктованный текст как краткий комментарий к коду: технически точно, без вводных \
+             слов. Сохрани язык оригинала. Ответь только текстом комментария без символов комментария.",
+            0.2,
+            Some(300),
+            Constraints {
+                line_prefix: Some("// ".to_string()),
+                remove_words: fillers(),
+                ..Constraints::default()
+            },
+        ),
+        profile(
+            "bullet_list",
+            "Список",
+            "Оформи продиктованный текст маркированным списком: каждый пункт с новой строки и начинается \
+             с «- », коротко, без вступления. Сохрани язык оригинала. Ответь только списком.",
+            0.3,
+            None,
+            Constraints {
+                layout: Layout::BulletList,
+                remove_words: fillers(),
+                ..Constraints::default()
+            },
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builtin_style(id: &str) -> StyleProfile {
+        builtin().into_iter().find(|style| style.id == id).unwrap()
+    }
+
+    #[test]
+    fn test_fallback_without_llm() {
+        assert_eq!(
+            builtin_style("formal").apply("Ну, короче, встреча переносится. В общем завтра в 10."),
+            "Встреча переносится. Завтра в 10."
+        );
+        assert_eq!(
+            builtin_style("bullet_list").apply("Купить молоко. Позвонить маме! Сдать отчёт"),
+            "- Купить молоко\n- Позвонить маме!\n- Сдать отчёт"
+        );
+        // Строки без знака в конце остаются отдельными пунктами
+        assert_eq!(
+            builtin_style("bullet_list").apply("Ну купить молоко\nкороче позвонить маме\n\nсдать отчёт"),
+            "- Купить молоко\n- Позвонить маме\n- Сдать отчёт"
+        );
+
+        let comment = builtin_style("code_comment").apply(&"Проверяет токен перед запросом. ".repeat(20));
+        assert!(comment.starts_with("// Проверяет токен"));
+        assert!(comment.chars().count() <= 300 + 3 && comment.ends_with('.'), "{}", comment);
+    }
+
+    #[test]
+    fn test_llm_output_constraints() {
+        let style = builtin_style("code_comment");
+        assert_eq!(style.finish("Retry the request.\nKeep the token."), "// Retry the request.\n// Keep the token.");
+        assert_eq!(truncate("одно два три четыре", 12), "одно два");
+    }
+
+    #[test]
+    fn test_store_and_selection() {
+        let dir = std::env::temp_dir().join(format!("alfavoice-styles-{}", uuid::Uuid::new_v4()));
+        let config = StylesConfig {
+            path: dir.join("styles.json"),
+            default: Some("formal".to_string()),
+            apps: BTreeMap::from([("com.microsoft.Outlook".to_string(), "email".to_string())]),
+        };
+        let store = StyleStore::open(&config).unwrap();
+        assert_eq!(store.list().len(), 5);
+
+        let settings = StyleSettings {
+            name: "Твит".to_string(),
+            prompt: String::new(),
+            temperature: None,
+            max_chars: Some(280),
+            constraints: Constraints::default(),
+        };
+        assert!(store.put("tweet", settings.clone()).unwrap().1);
+        assert!(!store.put("tweet", settings.clone()).unwrap().1);
+        assert!(matches!(store.put("Tweet!", settings), Err(StoreError::Invalid(_))));
+        store.remove("casual").unwrap();
+
+        // Изменения сохраняются в файл
+        let store = StyleStore::open(&config).unwrap();
+        assert!(store.get("tweet").is_some() && store.get("casual").is_none());
+
+        let id = |style: Option<Arc<StyleProfile>>| style.map(|style| style.id.clone());
+        let outlook = Some("com.microsoft.Outlook");
+        assert_eq!(id(store.select(Some("tweet"), Some("casual"), outlook).unwrap()).as_deref(), Some("tweet"));
+        assert_eq!(id(store.select(None, Some("tweet"), outlook).unwrap()).as_deref(), Some("tweet"));
+        assert_eq!(id(store.select(None, None, outlook).unwrap()).as_deref(), Some("email"));
+        assert_eq!(id(store.select(None, None, None).unwrap()).as_deref(), Some("formal"));
+        assert_eq!(id(store.select(None, Some("casual"), None).unwrap()), None);
+        assert!(store.select(Some("missing"), None, None).is_err());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
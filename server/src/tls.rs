@@ -0,0 +1,324 @@
+//! Встроенный TLS: HTTPS и WSS без внешнего прокси
+//!
+//! Сертификат и ключ читаются из PEM файлов `server.tls`; с `client_ca`
+//! сервер проверяет сертификаты клиентов (mTLS). Файлы проверяются каждые
+//! `reload_secs` секунд и при изменении перечитываются: новые соединения
+//! получают новую конфигурацию, а при ошибке чтения остаётся прежняя.
+//! Шифрование выполняет tokio-rustls, поверх TLS работает HTTP/1.1 с upgrade
+//! для WebSocket.
+
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use axum::Router;
+use hyper::server::conn::http1;
+use hyper_util::rt::TokioIo;
+use hyper_util::service::TowerToHyperService;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::server::TlsStream;
+use tracing::{debug, error, info, warn};
+
+use crate::config::TlsConfig;
+
+/// Максимальное время TLS рукопожатия
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Ошибки конфигурации TLS
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("Не удалось прочитать {path}: {message}")]
+    Pem { path: PathBuf, message: String },
+
+    #[error("В {0} нет сертификатов")]
+    NoCertificates(PathBuf),
+
+    #[error("Некорректная конфигурация TLS: {0}")]
+    Config(String),
+}
+
+/// Конфигурация rustls из файлов `server.tls`
+pub fn load(config: &TlsConfig) -> Result<Arc<ServerConfig>, TlsError> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let certs = certificates(&config.cert)?;
+    let key = PrivateKeyDer::from_pem_file(&config.key).map_err(|e| TlsError::Pem {
+        path: config.key.clone(),
+        message: e.to_string(),
+    })?;
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| TlsError::Config(e.to_string()))?;
+    let builder = match &config.client_ca {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in certificates(path)? {
+                roots
+                    .add(cert)
+                    .map_err(|e| TlsError::Config(format!("{}: {}", path.display(), e)))?;
+            }
+            let mut verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            if !config.require_client_cert {
+                verifier = verifier.allow_unauthenticated();
+            }
+            builder.with_client_cert_verifier(verifier.build().map_err(|e| TlsError::Config(e.to_string()))?)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut server = builder
+        .with_single_cert(certs, key)
+        .map_err(|e| TlsError::Config(e.to_string()))?;
+    server.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Arc::new(server))
+}
+
+fn certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let pem_error = |e: rustls::pki_types::pem::Error| TlsError::Pem {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    };
+    let certs = CertificateDer::pem_file_iter(path)
+        .map_err(pem_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(pem_error)?;
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificates(path.to_path_buf()));
+    }
+    Ok(certs)
+}
+
+/// Время изменения файлов TLS
+fn modified(config: &TlsConfig) -> Vec<Option<SystemTime>> {
+    [Some(&config.cert), Some(&config.key), config.client_ca.as_ref()]
+        .into_iter()
+        .flatten()
+        .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// Текущая конфигурация TLS для новых соединений
+pub struct TlsAcceptor {
+    config: TlsConfig,
+    server: RwLock<Arc<ServerConfig>>,
+    modified: RwLock<Vec<Option<SystemTime>>>,
+}
+
+impl TlsAcceptor {
+    pub fn new(config: &TlsConfig) -> Result<Arc<Self>, TlsError> {
+        Ok(Arc::new(Self {
+            config: config.clone(),
+            modified: RwLock::new(modified(config)),
+            server: RwLock::new(load(config)?),
+        }))
+    }
+
+    /// Перечитывает файлы, если они изменились; `true`, если конфигурация заменена
+    pub fn reload_if_changed(&self) -> bool {
+        let current = modified(&self.config);
+        {
+            let mut known = self.modified.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if *known == current {
+                return false;
+            }
+            *known = current;
+        }
+
+        match load(&self.config) {
+            Ok(server) => {
+                *self.server.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = server;
+                info!("Сертификаты TLS перечитаны: {}", self.config.cert.display());
+                true
+            }
+            Err(e) => {
+                error!("Сертификаты TLS не перечитаны, используются прежние: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Проверяет изменение файлов каждые `reload_secs` секунд
+    pub fn spawn_reload(self: &Arc<Self>) {
+        let acceptor = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(acceptor.config.reload_secs));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                acceptor.reload_if_changed();
+            }
+        });
+    }
+
+    fn current(&self) -> Arc<ServerConfig> {
+        self.server.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    /// TLS рукопожатие с клиентом
+    pub async fn accept<IO: AsyncRead + AsyncWrite + Unpin>(&self, io: IO) -> io::Result<TlsStream<IO>> {
+        tokio_rustls::TlsAcceptor::from(self.current()).accept(io).await
+    }
+}
+
+/// Принимает TLS соединения и обслуживает их приложением
+pub async fn serve(listener: TcpListener, app: Router, acceptor: Arc<TlsAcceptor>) {
+    loop {
+        let (tcp, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // Например, исчерпаны файловые дескрипторы: не крутим цикл впустую
+                warn!("Ошибка приёма соединения: {}", e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        tokio::spawn(serve_connection(tcp, peer, app.clone(), acceptor.clone()));
+    }
+}
+
+async fn serve_connection(tcp: TcpStream, peer: SocketAddr, app: Router, acceptor: Arc<TlsAcceptor>) {
+    let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(tcp)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => {
+            debug!("TLS рукопожатие с {}: {}", peer, e);
+            return;
+        }
+        Err(_) => {
+            debug!("TLS рукопожатие с {}: превышено время", peer);
+            return;
+        }
+    };
+
+    let service = TowerToHyperService::new(app);
+    let connection = http1::Builder::new()
+        .serve_connection(TokioIo::new(stream), service)
+        .with_upgrades();
+    if let Err(e) = connection.await {
+        debug!("Соединение {}: {}", peer, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::pki_types::ServerName;
+    use rustls::ClientConfig;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::TlsConnector;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tls").join(name)
+    }
+
+    fn tls_config(client_ca: bool) -> TlsConfig {
+        TlsConfig {
+            cert: fixture("server.pem"),
+            key: fixture("server.key"),
+            client_ca: client_ca.then(|| fixture("ca.pem")),
+            ..TlsConfig::default()
+        }
+    }
+
+    fn client(with_cert: bool) -> TlsConnector {
+        let mut roots = RootCertStore::empty();
+        roots.add(certificates(&fixture("ca.pem")).unwrap().remove(0)).unwrap();
+        let builder = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        let config = if with_cert {
+            let key = PrivateKeyDer::from_pem_file(fixture("client.key")).unwrap();
+            builder.with_client_auth_cert(certificates(&fixture("client.pem")).unwrap(), key).unwrap()
+        } else {
+            builder.with_no_client_auth()
+        };
+        TlsConnector::from(Arc::new(config))
+    }
+
+    /// Рукопожатие клиента с сервером через канал в памяти и обмен данными
+    async fn exchange(acceptor: &TlsAcceptor, connector: TlsConnector) -> io::Result<String> {
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        let server = async {
+            let mut stream = acceptor.accept(server_io).await?;
+            let mut request = [0u8; 4];
+            stream.read_exact(&mut request).await?;
+            stream.write_all(b"pong").await?;
+            // close_notify: клиент дочитывает ответ до конца потока
+            stream.shutdown().await?;
+            io::Result::Ok(request)
+        };
+        let client = async {
+            let mut client = connector
+                .connect(ServerName::try_from("localhost").unwrap(), client_io)
+                .await?;
+            client.write_all(b"ping").await?;
+            client.flush().await?;
+            let mut response = String::new();
+            client.read_to_string(&mut response).await?;
+            io::Result::Ok(response)
+        };
+
+        let (request, response) = tokio::join!(server, client);
+        assert_eq!(&request?, b"ping");
+        response
+    }
+
+    #[tokio::test]
+    async fn test_tls_and_mutual_tls() {
+        let acceptor = TlsAcceptor::new(&tls_config(false)).unwrap();
+        assert_eq!(exchange(&acceptor, client(false)).await.unwrap(), "pong");
+
+        let acceptor = TlsAcceptor::new(&tls_config(true)).unwrap();
+        assert_eq!(exchange(&acceptor, client(true)).await.unwrap(), "pong");
+        assert!(exchange(&acceptor, client(false)).await.is_err());
+
+        let optional = TlsConfig {
+            require_client_cert: false,
+            ..tls_config(true)
+        };
+        let acceptor = TlsAcceptor::new(&optional).unwrap();
+        assert_eq!(exchange(&acceptor, client(false)).await.unwrap(), "pong");
+    }
+
+    #[test]
+    fn test_reload_on_change() {
+        let dir = std::env::temp_dir().join(format!("alfavoice-tls-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = TlsConfig {
+            cert: dir.join("server.pem"),
+            key: dir.join("server.key"),
+            ..TlsConfig::default()
+        };
+        std::fs::copy(fixture("server.pem"), &config.cert).unwrap();
+        std::fs::copy(fixture("server.key"), &config.key).unwrap();
+
+        let acceptor = TlsAcceptor::new(&config).unwrap();
+        let initial = acceptor.current();
+        assert!(!acceptor.reload_if_changed());
+
+        let touch = |path: &Path, contents: &[u8], shift: u64| {
+            std::fs::write(path, contents).unwrap();
+            let file = std::fs::File::options().write(true).open(path).unwrap();
+            file.set_modified(SystemTime::now() + Duration::from_secs(shift)).unwrap();
+        };
+
+        // Некорректный файл: остаётся прежняя конфигурация
+        touch(&config.cert, b"not a certificate", 10);
+        assert!(!acceptor.reload_if_changed());
+        assert!(Arc::ptr_eq(&initial, &acceptor.current()));
+
+        touch(&config.cert, &std::fs::read(fixture("server.pem")).unwrap(), 20);
+        assert!(acceptor.reload_if_changed());
+        assert!(!Arc::ptr_eq(&initial, &acceptor.current()));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,236 @@
+//! Структурированный результат распознавания
+//!
+//! Whisper возвращает сегменты с таймкодами и вероятностями токенов.
+//! `Transcript` сохраняет их для заметок и экспорта субтитров, а плоский
+//! текст получается из сегментов.
+
+use serde::{Deserialize, Serialize};
+
+/// Результат распознавания: упорядоченные сегменты
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Transcript {
+    pub segments: Vec<Segment>,
+    /// Язык распознавания (код ISO 639-1)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Вероятность языка, если он определён автоматически
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language_probability: Option<f32>,
+}
+
+/// Сегмент распознанного текста
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Segment {
+    /// Начало сегмента (сек)
+    pub start: f64,
+    /// Конец сегмента (сек)
+    pub end: f64,
+    /// Текст сегмента без пробелов по краям
+    pub text: String,
+    /// Средний логарифм вероятности текстовых токенов
+    pub avg_logprob: f32,
+    /// Вероятность отсутствия речи (токен `<|nospeech|>` в начале окна)
+    ///
+    /// Whisper заполняет её всегда; `None` бывает у сегментов, собранных
+    /// не из результата распознавания.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_speech_prob: Option<f32>,
+    /// Слова с таймкодами
+    #[serde(default)]
+    pub words: Vec<Word>,
+}
+
+/// Слово с таймкодами и уверенностью
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Word {
+    pub word: String,
+    /// Начало слова (сек)
+    pub start: f64,
+    /// Конец слова (сек)
+    pub end: f64,
+    /// Вероятность слова (минимальная вероятность его токенов)
+    pub probability: f32,
+}
+
+impl Transcript {
+    /// Плоский текст: сегменты через пробел
+    pub fn text(&self) -> String {
+        self.segments
+            .iter()
+            .map(|s| s.text.as_str())
+            .filter(|t| !t.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Нет ни одного непустого сегмента
+    pub fn is_empty(&self) -> bool {
+        self.segments.iter().all(|s| s.text.is_empty())
+    }
+
+    /// Добавляет сегменты другого результата, сдвинув их таймкоды на `offset` секунд
+    ///
+    /// Язык берётся из первого результата, в котором он указан.
+    pub fn append(&mut self, other: Transcript, offset: f64) {
+        if self.language.is_none() {
+            self.language = other.language;
+            self.language_probability = other.language_probability;
+        }
+        self.segments.extend(other.segments.into_iter().map(|mut segment| {
+            segment.shift(offset);
+            segment
+        }));
+    }
+}
+
+impl Segment {
+    /// Сдвигает таймкоды сегмента и его слов
+    pub fn shift(&mut self, offset: f64) {
+        self.start += offset;
+        self.end += offset;
+        for word in &mut self.words {
+            word.start += offset;
+            word.end += offset;
+        }
+    }
+}
+
+/// Токен Whisper с таймкодами, из которых собираются слова
+#[derive(Debug, Clone)]
+pub struct TokenInfo {
+    /// Байты токена: кириллический символ может быть разрезан между токенами
+    pub bytes: Vec<u8>,
+    /// Начало токена (сек)
+    pub start: f64,
+    /// Конец токена (сек)
+    pub end: f64,
+    pub probability: f32,
+}
+
+/// Собирает слова из текстовых токенов сегмента
+///
+/// Токены Whisper - части слов; новое слово начинается с токена,
+/// байты которого начинаются с пробела. Текст слова декодируется из
+/// склеенных байтов, поэтому разрезанные UTF-8 символы собираются целыми.
+pub fn words_from_tokens(tokens: &[TokenInfo]) -> Vec<Word> {
+    let mut words: Vec<(Vec<u8>, Word)> = Vec::new();
+
+    for token in tokens {
+        if token.bytes.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+
+        match words.last_mut() {
+            Some((bytes, word)) if token.bytes[0] != b' ' => {
+                bytes.extend_from_slice(&token.bytes);
+                word.end = token.end;
+                word.probability = word.probability.min(token.probability);
+            }
+            _ => words.push((
+                token.bytes.clone(),
+                Word {
+                    word: String::new(),
+                    start: token.start,
+                    end: token.end,
+                    probability: token.probability,
+                },
+            )),
+        }
+    }
+
+    words
+        .into_iter()
+        .map(|(bytes, mut word)| {
+            word.word = String::from_utf8_lossy(&bytes).trim().to_string();
+            word
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start: f64, end: f64, text: &str) -> Segment {
+        Segment {
+            start,
+            end,
+            text: text.to_string(),
+            avg_logprob: -0.1,
+            no_speech_prob: None,
+            words: Vec::new(),
+        }
+    }
+
+    fn token(text: &str, start: f64, end: f64, probability: f32) -> TokenInfo {
+        TokenInfo {
+            bytes: text.as_bytes().to_vec(),
+            start,
+            end,
+            probability,
+        }
+    }
+
+    #[test]
+    fn test_text_joins_segments() {
+        let transcript = Transcript {
+            segments: vec![segment(0.0, 1.0, "Привет."), segment(1.0, 2.0, ""), segment(2.0, 3.0, "Как дела?")],
+            ..Transcript::default()
+        };
+        assert_eq!(transcript.text(), "Привет. Как дела?");
+        assert!(!transcript.is_empty());
+        assert!(Transcript::default().is_empty());
+    }
+
+    #[test]
+    fn test_append_shifts_timestamps() {
+        let mut transcript = Transcript {
+            segments: vec![segment(0.0, 1.0, "раз")],
+            ..Transcript::default()
+        };
+        let mut second = segment(0.5, 1.5, "два");
+        second.words.push(Word {
+            word: "два".to_string(),
+            start: 0.5,
+            end: 1.5,
+            probability: 0.9,
+        });
+        let detected = Transcript {
+            segments: vec![second],
+            language: Some("en".to_string()),
+            language_probability: Some(0.9),
+        };
+        transcript.append(detected, 10.0);
+
+        assert_eq!(transcript.segments[1].start, 10.5);
+        assert_eq!(transcript.segments[1].words[0].end, 11.5);
+        assert_eq!(transcript.segments[1].end, 11.5);
+        assert_eq!(transcript.language.as_deref(), Some("en"));
+    }
+
+    #[test]
+    fn test_words_from_tokens() {
+        let tokens = vec![
+            token(" При", 0.0, 0.2, 0.9),
+            token("вет", 0.2, 0.4, 0.7),
+            token(" мир", 0.5, 0.8, 0.95),
+        ];
+        let mut words = words_from_tokens(&tokens);
+
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[0].word, "Привет");
+        assert_eq!(words[0].end, 0.4);
+        assert_eq!(words[0].probability, 0.7);
+        assert_eq!(words[1].word, "мир");
+
+        // Символ «ж», разрезанный между двумя токенами
+        let split = "ж".as_bytes();
+        let tokens = vec![
+            TokenInfo { bytes: vec![b' ', split[0]], start: 0.0, end: 0.1, probability: 0.8 },
+            TokenInfo { bytes: vec![split[1]], start: 0.1, end: 0.2, probability: 0.9 },
+        ];
+        words = words_from_tokens(&tokens);
+        assert_eq!(words.len(), 1);
+        assert_eq!(words[0].word, "ж");
+    }
+}
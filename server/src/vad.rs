@@ -0,0 +1,338 @@
+//! Детектор голосовой активности (VAD)
+//!
+//! Классифицирует кадры 20 мс по энергии и спектральной плоскостности:
+//! речь громче шумового фона и имеет выраженную гармоническую структуру,
+//! а шум и тишина - плоский спектр. Используется, чтобы не отдавать Whisper
+//! тишину (экономия CPU и меньше «галлюцинаций»), резать длинное аудио на
+//! паузах и определять конец высказывания в потоковой сессии.
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use rayon::prelude::*;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+use crate::config::VadConfig;
+use crate::whisper::WHISPER_SAMPLE_RATE;
+
+/// Размер FFT для кадра 20 мс при 16kHz (320 сэмплов с дополнением нулями)
+const FFT_SIZE: usize = 512;
+
+/// Полоса частот речи, по которой считается плоскостность спектра
+const SPEECH_BAND_HZ: Range<f32> = 100.0..4000.0;
+
+/// Окно поиска тихого кадра при разрезании слишком длинного сегмента (мс)
+const SPLIT_SEARCH_MS: u64 = 5000;
+
+/// Минимум кадров на одну задачу rayon (10 с при кадре 20 мс)
+const PARALLEL_FRAMES: usize = 500;
+
+/// Признаки одного кадра
+#[derive(Debug, Clone, Copy)]
+struct FrameFeatures {
+    /// Энергия кадра в dBFS
+    energy_db: f32,
+    /// Спектральная плоскостность (0 - тон, ~0.56 - белый шум)
+    flatness: f32,
+}
+
+/// Детектор голосовой активности для 16kHz mono
+pub struct Vad {
+    config: VadConfig,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+}
+
+impl Vad {
+    /// Создаёт детектор с указанной конфигурацией
+    pub fn new(config: VadConfig) -> Self {
+        let frame_len = config.frame_samples();
+        let fft = FftPlanner::new().plan_fft_forward(FFT_SIZE);
+
+        // Окно Ханна по длине кадра
+        let window = (0..frame_len)
+            .map(|i| {
+                let phase = 2.0 * std::f32::consts::PI * i as f32 / frame_len as f32;
+                0.5 - 0.5 * phase.cos()
+            })
+            .collect();
+
+        Self { config, fft, window }
+    }
+
+    /// Находит участки речи (в сэмплах), разделённые паузами
+    ///
+    /// Соседние участки с паузой короче `min_silence_ms` сливаются,
+    /// участки короче `min_speech_ms` отбрасываются, каждый участок
+    /// расширяется на `padding_ms`, а слишком длинные режутся по самому
+    /// тихому кадру, чтобы уложиться в `max_segment_secs`.
+    pub fn segments(&self, samples: &[f32]) -> Vec<Range<usize>> {
+        let features = self.features(samples);
+        let active = self.classify(&features);
+        let frame_len = self.config.frame_samples();
+
+        let min_silence = self.ms_to_frames(self.config.min_silence_ms);
+        let min_speech = self.ms_to_frames(self.config.min_speech_ms);
+        let padding = self.ms_to_frames(self.config.padding_ms);
+
+        // Непрерывные участки активных кадров
+        let mut runs: Vec<Range<usize>> = Vec::new();
+        let mut start = None;
+        for (i, &is_speech) in active.iter().enumerate() {
+            match (is_speech, start) {
+                (true, None) => start = Some(i),
+                (false, Some(s)) => {
+                    runs.push(s..i);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(s) = start {
+            runs.push(s..active.len());
+        }
+
+        // Сливаем участки, разделённые короткими паузами
+        let mut merged: Vec<Range<usize>> = Vec::new();
+        for run in runs {
+            match merged.last_mut() {
+                Some(last) if run.start - last.end < min_silence => last.end = run.end,
+                _ => merged.push(run),
+            }
+        }
+
+        let max_frames = self.ms_to_frames(self.config.max_segment_secs * 1000);
+        let search_frames = self.ms_to_frames(SPLIT_SEARCH_MS).min(max_frames / 2);
+
+        let mut segments = Vec::new();
+        for run in merged.into_iter().filter(|r| r.len() >= min_speech) {
+            let padded = run.start.saturating_sub(padding)..(run.end + padding).min(features.len());
+            for frames in split_long(padded, max_frames, search_frames, &features) {
+                let start = frames.start * frame_len;
+                let end = (frames.end * frame_len).min(samples.len());
+                if start < end {
+                    segments.push(start..end);
+                }
+            }
+        }
+
+        segments
+    }
+
+    /// Есть ли в буфере речь
+    pub fn has_speech(&self, samples: &[f32]) -> bool {
+        !self.segments(samples).is_empty()
+    }
+
+    /// Длительность тишины в конце буфера после последнего участка речи (мс)
+    ///
+    /// Возвращает `None`, если речи в буфере ещё не было.
+    pub fn trailing_silence_ms(&self, samples: &[f32]) -> Option<u64> {
+        let features = self.features(samples);
+        let active = self.classify(&features);
+        let last_speech = active.iter().rposition(|&a| a)?;
+        let silent_frames = (active.len() - last_speech - 1) as u64;
+        Some(silent_frames * self.config.frame_ms)
+    }
+
+    /// Считает признаки для каждого полного кадра
+    ///
+    /// Кадры независимы: длинный буфер (задания, загрузки) обрабатывается
+    /// частями параллельно в пуле rayon.
+    fn features(&self, samples: &[f32]) -> Vec<FrameFeatures> {
+        let frame_len = self.config.frame_samples();
+        let bin_hz = WHISPER_SAMPLE_RATE as f32 / FFT_SIZE as f32;
+        let band = (SPEECH_BAND_HZ.start / bin_hz) as usize..(SPEECH_BAND_HZ.end / bin_hz) as usize;
+        let scratch_len = self.fft.get_inplace_scratch_len();
+
+        samples
+            .par_chunks_exact(frame_len)
+            .with_min_len(PARALLEL_FRAMES)
+            .map_init(
+                || {
+                    let buffer = vec![Complex::new(0.0f32, 0.0); FFT_SIZE];
+                    (buffer, vec![Complex::new(0.0f32, 0.0); scratch_len])
+                },
+                |(buffer, scratch), frame| {
+                    let mean_square = frame.iter().map(|s| s * s).sum::<f32>() / frame_len as f32;
+                    let energy_db = 10.0 * (mean_square + 1e-10).log10();
+
+                    for (dst, (s, w)) in buffer.iter_mut().zip(frame.iter().zip(&self.window)) {
+                        *dst = Complex::new(s * w, 0.0);
+                    }
+                    for dst in buffer.iter_mut().skip(frame_len) {
+                        *dst = Complex::new(0.0, 0.0);
+                    }
+                    self.fft.process_with_scratch(buffer, scratch);
+
+                    let powers: Vec<f32> = buffer[band.clone()]
+                        .iter()
+                        .map(|c| c.norm_sqr() + 1e-12)
+                        .collect();
+                    let log_mean = powers.iter().map(|p| p.ln()).sum::<f32>() / powers.len() as f32;
+                    let mean = powers.iter().sum::<f32>() / powers.len() as f32;
+
+                    FrameFeatures {
+                        energy_db,
+                        flatness: log_mean.exp() / mean,
+                    }
+                },
+            )
+            .collect()
+    }
+
+    /// Помечает кадры с речью
+    ///
+    /// Порог энергии адаптивный: шумовой фон оценивается нижним перцентилем
+    /// энергий буфера. Очень громкие кадры считаются речью независимо от
+    /// спектра (шипящие звуки имеют плоский спектр), остальные - только при
+    /// низкой плоскостности.
+    fn classify(&self, features: &[FrameFeatures]) -> Vec<bool> {
+        if features.is_empty() {
+            return Vec::new();
+        }
+
+        let mut energies: Vec<f32> = features.iter().map(|f| f.energy_db).collect();
+        energies.sort_by(|a, b| a.total_cmp(b));
+        let noise_floor = energies[energies.len() / 10];
+
+        let threshold = (noise_floor + self.config.energy_margin_db)
+            .clamp(self.config.min_energy_db, self.config.max_threshold_db);
+        let strong_threshold = threshold + self.config.energy_margin_db;
+
+        features
+            .iter()
+            .map(|f| {
+                f.energy_db >= strong_threshold
+                    || (f.energy_db >= threshold && f.flatness <= self.config.max_flatness)
+            })
+            .collect()
+    }
+
+    fn ms_to_frames(&self, ms: u64) -> usize {
+        (ms / self.config.frame_ms) as usize
+    }
+}
+
+/// Режет участок кадров на части не длиннее `max_frames` по самым тихим кадрам
+fn split_long(
+    mut frames: Range<usize>,
+    max_frames: usize,
+    search_frames: usize,
+    features: &[FrameFeatures],
+) -> Vec<Range<usize>> {
+    let mut parts = Vec::new();
+
+    while max_frames > 0 && frames.len() > max_frames {
+        let search = frames.start + max_frames - search_frames..frames.start + max_frames;
+        let cut = search
+            .clone()
+            .min_by(|&a, &b| features[a].energy_db.total_cmp(&features[b].energy_db))
+            .unwrap_or(search.end);
+        parts.push(frames.start..cut);
+        frames.start = cut;
+    }
+
+    parts.push(frames);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: usize = WHISPER_SAMPLE_RATE as usize;
+
+    /// Гармонический сигнал, похожий на гласный звук (основной тон + обертоны)
+    fn voiced(seconds: f32) -> Vec<f32> {
+        let n = (RATE as f32 * seconds) as usize;
+        (0..n)
+            .map(|i| {
+                let t = i as f32 / RATE as f32;
+                (1..=5)
+                    .map(|h| (2.0 * std::f32::consts::PI * 150.0 * h as f32 * t).sin() * 0.1 / h as f32)
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Псевдослучайный белый шум с заданной амплитудой
+    fn noise(seconds: f32, amplitude: f32) -> Vec<f32> {
+        let n = (RATE as f32 * seconds) as usize;
+        let mut state = 0x1234_5678u32;
+        (0..n)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state as f32 / u32::MAX as f32 * 2.0 - 1.0) * amplitude
+            })
+            .collect()
+    }
+
+    fn vad() -> Vad {
+        Vad::new(VadConfig::default())
+    }
+
+    #[test]
+    fn test_silence_has_no_speech() {
+        let vad = vad();
+        assert!(vad.segments(&vec![0.0; RATE * 2]).is_empty());
+        assert!(!vad.has_speech(&noise(2.0, 0.003)));
+        assert_eq!(vad.trailing_silence_ms(&vec![0.0; RATE]), None);
+    }
+
+    #[test]
+    fn test_trims_leading_and_trailing_silence() {
+        let mut audio = noise(1.0, 0.001);
+        audio.extend(voiced(1.0));
+        audio.extend(noise(1.0, 0.001));
+
+        let segments = vad().segments(&audio);
+        assert_eq!(segments.len(), 1);
+        let range = &segments[0];
+        let padding = VadConfig::default().padding_ms as usize * RATE / 1000;
+        assert!(range.start >= RATE - padding - 320 && range.start <= RATE);
+        assert!(range.end >= 2 * RATE && range.end <= 2 * RATE + padding + 320);
+    }
+
+    #[test]
+    fn test_splits_on_long_pause() {
+        let mut audio = voiced(1.0);
+        audio.extend(noise(1.5, 0.001));
+        audio.extend(voiced(1.0));
+
+        assert_eq!(vad().segments(&audio).len(), 2);
+    }
+
+    #[test]
+    fn test_short_pause_is_merged() {
+        let mut audio = voiced(1.0);
+        audio.extend(noise(0.1, 0.001));
+        audio.extend(voiced(1.0));
+
+        assert_eq!(vad().segments(&audio).len(), 1);
+    }
+
+    #[test]
+    fn test_long_speech_is_split() {
+        let config = VadConfig {
+            max_segment_secs: 2,
+            ..VadConfig::default()
+        };
+        let segments = Vad::new(config).segments(&voiced(5.0));
+
+        assert!(segments.len() >= 3);
+        assert!(segments.iter().all(|s| s.len() <= 2 * RATE));
+    }
+
+    #[test]
+    fn test_trailing_silence() {
+        let mut audio = voiced(1.0);
+        audio.extend(noise(0.5, 0.001));
+
+        let silence = vad().trailing_silence_ms(&audio).unwrap();
+        assert!((400..=500).contains(&silence), "silence {} ms", silence);
+    }
+}
@@ -0,0 +1,181 @@
+//! Голосовые команды редактирования: «новая строка», «удали последнее предложение»
+//!
+//! Команды ищутся в тексте Whisper до постобработки, целыми фразами без
+//! учёта регистра (грамматика - `voice_commands.commands`). Текст между
+//! командами постобрабатывается отдельно, а клиент получает в `final`
+//! последовательность операций `edits`: вставки текста и команды
+//! (удаление, выделение, регистр, отмена), которые он применяет к полю ввода.
+
+use crate::config::{EditOp, VoiceCommandsConfig};
+use crate::phrases::Phrases;
+
+/// Знаки, которые Whisper ставит вокруг произнесённой команды
+const COMMAND_PUNCTUATION: &[char] = &['.', ',', '!', '?', ';', ':', '…'];
+
+/// Часть высказывания
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Piece {
+    /// Диктуемый текст, ещё не прошедший постобработку
+    Text(String),
+    Command(EditOp),
+}
+
+/// Грамматика команд
+#[derive(Debug)]
+pub struct Grammar {
+    commands: Phrases<EditOp>,
+}
+
+impl Grammar {
+    pub fn new(config: &VoiceCommandsConfig) -> Self {
+        let mut commands = Phrases::default();
+        for command in &config.commands {
+            for phrase in &command.phrases {
+                commands.insert(phrase, command.op.clone());
+            }
+        }
+        Self { commands }
+    }
+
+    /// Делит текст на диктовку и команды; `None`, если команд нет
+    pub fn interpret(&self, text: &str) -> Option<Vec<Piece>> {
+        let found = self.commands.find(text);
+        if found.is_empty() {
+            return None;
+        }
+
+        let mut pieces = Vec::new();
+        let mut cursor = 0;
+        for (range, op) in found {
+            let mut before = text[cursor..range.start].trim_end();
+            // «Привет. Запятая.» - знак Whisper перед вставляемым знаком лишний
+            if matches!(op, EditOp::Insert { text } if text.starts_with(COMMAND_PUNCTUATION)) {
+                before = before.trim_end_matches(COMMAND_PUNCTUATION);
+            }
+            if !before.trim().is_empty() {
+                pieces.push(Piece::Text(before.trim().to_string()));
+            }
+            pieces.push(Piece::Command(op.clone()));
+
+            // Знаки и пробелы сразу после команды относятся к самой команде
+            cursor = range.end
+                + text[range.end..]
+                    .find(|c: char| !c.is_whitespace() && !COMMAND_PUNCTUATION.contains(&c))
+                    .unwrap_or(text.len() - range.end);
+        }
+        if !text[cursor..].trim().is_empty() {
+            pieces.push(Piece::Text(text[cursor..].trim().to_string()));
+        }
+
+        Some(pieces)
+    }
+}
+
+/// Операции высказывания для клиента
+#[derive(Debug, Default)]
+pub struct Edits {
+    ops: Vec<EditOp>,
+    /// Текст всех вставок: результат для клиентов без поддержки `edits`
+    text: String,
+}
+
+impl Edits {
+    /// Вставка диктуемого текста, отделённого пробелом от предыдущего
+    pub fn push_text(&mut self, text: &str) {
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+        let separated = self.text.is_empty() || self.text.ends_with(char::is_whitespace);
+        let text = if separated { text.to_string() } else { format!(" {}", text) };
+        self.insert(text);
+    }
+
+    pub fn push_command(&mut self, op: EditOp) {
+        match op {
+            EditOp::Insert { text } => self.insert(text),
+            op => self.ops.push(op),
+        }
+    }
+
+    fn insert(&mut self, text: String) {
+        self.text.push_str(&text);
+        // Соседние вставки объединяются в одну
+        if let Some(EditOp::Insert { text: last }) = self.ops.last_mut() {
+            last.push_str(&text);
+        } else {
+            self.ops.push(EditOp::Insert { text });
+        }
+    }
+
+    pub fn finish(self) -> (String, Vec<EditOp>) {
+        (self.text, self.ops)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TextUnit;
+
+    fn insert(text: &str) -> EditOp {
+        EditOp::Insert { text: text.to_string() }
+    }
+
+    #[test]
+    fn test_interpret_commands() {
+        let grammar = Grammar::new(&VoiceCommandsConfig::default());
+        assert_eq!(grammar.interpret("Просто текст, без команд."), None);
+
+        let pieces = grammar.interpret("Привет. Новая строка. Как дела? Удали последнее предложение.").unwrap();
+        assert_eq!(
+            pieces,
+            [
+                Piece::Text("Привет.".to_string()),
+                Piece::Command(insert("\n")),
+                Piece::Text("Как дела?".to_string()),
+                Piece::Command(EditOp::Delete { unit: TextUnit::Sentence }),
+            ]
+        );
+
+        // «поставь точку с запятой» длиннее, чем «поставь точку», и не путается с ней
+        let pieces = grammar.interpret("первое, поставь запятую второе поставь точку с запятой третье").unwrap();
+        assert_eq!(pieces[1], Piece::Command(insert(",")));
+        assert_eq!(pieces[3], Piece::Command(insert(";")));
+    }
+
+    #[test]
+    fn test_dictation_is_not_a_command() {
+        let grammar = Grammar::new(&VoiceCommandsConfig::default());
+        for text in [
+            "Отмена рейса перенесена на завтра.",
+            "Запятая здесь не нужна, а двоеточие нужно.",
+            "The trial period ends soon: use a comma or a colon.",
+            "Сделай новый отчёт, точка входа в меню.",
+        ] {
+            assert_eq!(grammar.interpret(text), None, "{}", text);
+        }
+
+        for command in &VoiceCommandsConfig::default().commands {
+            for phrase in &command.phrases {
+                assert!(phrase.split_whitespace().count() > 1, "{}", phrase);
+            }
+        }
+    }
+
+    #[test]
+    fn test_edits_spacing() {
+        let grammar = Grammar::new(&VoiceCommandsConfig::default());
+        let mut edits = Edits::default();
+        for piece in grammar.interpret("привет, поставь запятую, как дела новый абзац пока scratch that").unwrap() {
+            match piece {
+                Piece::Text(text) => edits.push_text(&text),
+                Piece::Command(op) => edits.push_command(op),
+            }
+        }
+
+        let (text, ops) = edits.finish();
+        assert_eq!(text, "привет, как дела\n\nпока");
+        assert_eq!(ops, [insert("привет, как дела\n\nпока"), EditOp::Undo]);
+    }
+}
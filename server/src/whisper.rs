@@ -1,14 +1,87 @@
 use std::path::Path;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing::{info, error, warn};
-use whisper_rs::{WhisperContext, FullParams, SamplingStrategy, WhisperContextParameters};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{info, error, warn, debug};
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperError, WhisperState};
 
+use crate::audio::{self, AudioError, PcmSpec};
+use crate::pool::{Pool, PoolError, PoolStats};
+use crate::resample;
+use crate::transcript::{words_from_tokens, Segment, TokenInfo, Transcript};
+use crate::vad::Vad;
 use crate::config::WhisperConfig;
 
+/// Язык распознавания
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    Ru,
+    En,
+    /// Определить автоматически (среди поддерживаемых языков)
+    Auto,
+}
+
+impl Language {
+    /// Языки, из которых выбирает автоопределение
+    const DETECTABLE: [Language; 2] = [Language::Ru, Language::En];
+
+    /// Код языка для Whisper; `None` для `Auto`
+    pub fn code(self) -> Option<&'static str> {
+        match self {
+            Language::Ru => Some("ru"),
+            Language::En => Some("en"),
+            Language::Auto => None,
+        }
+    }
+
+    /// Язык по коду Whisper
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::DETECTABLE.into_iter().find(|language| language.code() == Some(code))
+    }
+}
+
+/// Задача Whisper
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Task {
+    /// Распознавание на языке речи
+    #[default]
+    Transcribe,
+    /// Перевод на английский встроенным переводчиком Whisper
+    Translate,
+}
+
+/// Ошибки распознавания
+#[derive(Debug, Error)]
+pub enum TranscribeError {
+    /// Все состояния Whisper заняты дольше допустимого ожидания
+    #[error(transparent)]
+    Busy(#[from] PoolError),
+
+    #[error("{0}")]
+    Failed(String),
+}
+
+/// Параметры одного запроса на распознавание
+#[derive(Debug, Clone, Default)]
+pub struct TranscribeOptions {
+    pub language: Language,
+    pub task: Task,
+    /// Подсказка декодеру: термины и стиль предыдущего текста
+    pub prompt: Option<String>,
+    /// Температура сэмплинга; `None` - значение whisper.cpp по умолчанию
+    pub temperature: Option<f32>,
+}
+
 /// Обёртка для Whisper модели
 pub struct WhisperModel {
-    context: Arc<Mutex<WhisperContext>>,
+    /// Контекст модели (веса и словарь токенов), общий для всех состояний
+    context: Arc<WhisperContext>,
+    /// Состояния инференса: каждое обслуживает одно распознавание за раз
+    states: Arc<Pool<WhisperState>>,
     config: WhisperConfig,
 }
 
@@ -17,14 +90,11 @@ impl WhisperModel {
     ///
     /// # Аргументы
     /// * `model_path` - путь к файлу модели Whisper (например, "models/ggml-large-v3.bin")
-    /// * `config` - конфигурация параметров инференса (опционально, используется Default если None)
+    /// * `config` - конфигурация параметров инференса и устройства (опционально, используется Default если None)
     ///
     /// # Возвращает
     /// * `Ok(WhisperModel)` - если модель успешно загружена
     /// * `Err(String)` - с описанием ошибки и инструкцией по скачиванию модели
-    ///
-    /// # Переменные окружения
-    /// * `WHISPER_DEVICE` - устройство для инференса: "cpu" или "gpu" (по умолчанию: "cpu")
     pub fn load(model_path: &str, config: Option<WhisperConfig>) -> Result<Self, String> {
         let config = config.unwrap_or_default();
         info!("Загрузка Whisper модели из: {}", model_path);
@@ -47,25 +117,12 @@ impl WhisperModel {
             return Err(error_msg);
         }
 
-        // Читаем переменную окружения для выбора устройства
-        let device = std::env::var("WHISPER_DEVICE")
-            .unwrap_or_else(|_| "cpu".to_string())
-            .to_lowercase();
-        
-        let use_gpu = match device.as_str() {
-            "gpu" | "cuda" => {
-                info!("Используем GPU (CUDA) для Whisper");
-                true
-            },
-            "cpu" => {
-                info!("Используем CPU для Whisper");
-                false
-            },
-            _ => {
-                warn!("Неизвестное значение WHISPER_DEVICE: '{}', используем CPU", device);
-                false
-            }
-        };
+        let use_gpu = config.use_gpu;
+        if use_gpu {
+            info!("Используем GPU (CUDA) для Whisper");
+        } else {
+            info!("Используем CPU для Whisper");
+        }
 
         // Создаём контекст Whisper с выбранным устройством
         let context = WhisperContext::new_with_params(
@@ -88,113 +145,268 @@ impl WhisperModel {
         info!("Whisper модель успешно загружена (устройство: {})", if use_gpu { "GPU" } else { "CPU" });
         info!("Параметры инференса: threads={}, beam_search={}", config.n_threads, config.use_beam_search);
         
+        let pool_size = config.pool_size.max(1);
+        let states = (0..pool_size)
+            .map(|_| context.create_state())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Не удалось создать состояние Whisper: {}", e))?;
+        info!(
+            "Пул Whisper: {} состояний, максимальное ожидание {} мс",
+            pool_size, config.max_queue_wait_ms
+        );
+
         Ok(Self {
-            context: Arc::new(Mutex::new(context)),
+            context: Arc::new(context),
+            states: Arc::new(Pool::new(states, Duration::from_millis(config.max_queue_wait_ms))),
             config,
         })
     }
 
     /// Выполняет транскрипцию аудио данных
-    /// 
+    ///
     /// # Аргументы
-    /// * `audio_data` - сырые аудио данные в формате PCM 16-bit, 16kHz, mono
-    /// 
+    /// * `audio_data` - сэмплы PCM 16kHz mono в диапазоне [-1.0, 1.0]
+    /// * `options` - язык и задача; для `Language::Auto` язык определяется по аудио
+    ///
     /// # Возвращает
-    /// * `Ok(String)` - распознанный текст
-    /// * `Err(String)` - описание ошибки
-    pub async fn transcribe(&self, audio_data: &[f32]) -> Result<String, String> {
+    /// * `Ok(Transcript)` - сегменты с таймкодами слов, вероятностями и языком речи
+    ///   (при `Task::Translate` текст сегментов на английском)
+    /// * `Err(TranscribeError)` - сервер занят или ошибка Whisper
+    pub async fn transcribe(&self, audio_data: &[f32], options: &TranscribeOptions) -> Result<Transcript, TranscribeError> {
+        // Ждём свободное состояние в очереди; при таймауте - «сервер занят»
+        let mut state = self.states.acquire().await.inspect_err(|e| warn!("{}", e))?;
+
         let context = self.context.clone();
         let config = self.config.clone();
-        
+        let samples = audio_data.to_vec();
+        let language = options.language;
+        let task = options.task;
+        let prompt = options.prompt.clone();
+        let temperature = options.temperature;
+
         tokio::task::spawn_blocking(move || {
-            let mut ctx = context.blocking_lock();
-            
-            // Конвертируем f32 в i32 для Whisper
-            let samples: Vec<i32> = audio_data
-                .iter()
-                .map(|&sample| (sample * 32768.0) as i32)
-                .collect();
-            
+            let (language, probability) = match language.code() {
+                Some(code) => (code, None),
+                None => {
+                    let (detected, probability) = detect_language(&mut state, &samples, config.n_threads)
+                        .map_err(|e| format!("Не удалось определить язык: {}", e))?;
+                    (detected.code().unwrap_or("ru"), Some(probability))
+                }
+            };
+
             // Определяем стратегию сэмплинга на основе конфигурации
             let strategy = if config.use_beam_search {
-                SamplingStrategy::BeamSearch
+                SamplingStrategy::BeamSearch {
+                    beam_size: config.beam_size,
+                    patience: -1.0,
+                }
             } else {
-                SamplingStrategy::Greedy
+                SamplingStrategy::Greedy { best_of: 1 }
             };
-            
-            // Получаем параметры контекста
-            let mut params = ctx.full_default_params(strategy);
-            
+
+            let mut params = FullParams::new(strategy);
+
             // Применяем настройки из конфигурации
             params.set_n_threads(config.n_threads);
-            params.set_beam_size(config.beam_size);
-            params.set_translate(false); // Не переводим, только транскрибируем
-            params.set_language(Some("ru")); // Русский язык по умолчанию
+            params.set_translate(task == Task::Translate);
+            params.set_language(Some(language));
+            params.set_token_timestamps(true); // Таймкоды токенов для слов
+            if let Some(prompt) = prompt.as_deref().filter(|p| !p.trim().is_empty()) {
+                params.set_initial_prompt(prompt);
+            }
+            if let Some(temperature) = temperature {
+                params.set_temperature(temperature);
+            }
             params.set_print_special(false);
             params.set_print_progress(false);
             params.set_print_realtime(false);
             params.set_print_timestamps(false);
-            
+
             // Выполняем транскрипцию
-            ctx.full(params, &samples).map_err(|e| {
+            state.full(params, &samples).map_err(|e| {
                 error!("Ошибка транскрипции Whisper: {}", e);
                 format!("Не удалось выполнить транскрипцию: {}", e)
             })?;
-            
-            // Получаем количество сегментов
-            let num_segments = ctx.full_n_segments();
-            
-            // Собираем все сегменты в один текст
-            let mut transcription = String::new();
-            for i in 0..num_segments {
-                let segment_text = ctx
-                    .full_get_segment_text(i)
-                    .map_err(|e| format!("Не удалось получить сегмент {}: {}", i, e))?;
-                
-                transcription.push_str(segment_text.trim());
-                transcription.push(' ');
-            }
-            
-            // Убираем лишние пробелы
-            let transcription = transcription.trim().to_string();
-            
-            if transcription.is_empty() {
+
+            let mut transcript = collect_transcript(&context, &state)
+                .map_err(|e| format!("Не удалось получить результат транскрипции: {}", e))?;
+            transcript.language = Some(language.to_string());
+            transcript.language_probability = probability;
+
+            if transcript.is_empty() {
                 warn!("Транскрипция вернула пустой результат");
             } else {
-                info!("Транскрипция выполнена успешно, длина: {} символов", transcription.len());
+                info!("Транскрипция выполнена успешно, сегментов: {}", transcript.segments.len());
             }
-            
-            Ok(transcription)
+
+            Ok(transcript)
         })
         .await
-        .map_err(|e| format!("Ошибка выполнения задачи транскрипции: {}", e))?
+        .map_err(|e| TranscribeError::Failed(format!("Ошибка выполнения задачи транскрипции: {}", e)))?
+        .map_err(TranscribeError::Failed)
+    }
+
+    /// Метрики пула состояний (очередь, занятость, отказы)
+    pub fn pool_stats(&self) -> PoolStats {
+        self.states.stats()
+    }
+
+    /// Распознаёт только участки речи, найденные VAD
+    ///
+    /// Тишина в начале и конце не передаётся Whisper, длинное аудио
+    /// распознаётся по сегментам между паузами. Таймкоды сегментов
+    /// считаются от начала `audio_data`. Если речи нет, Whisper
+    /// не вызывается и возвращается пустой результат.
+    ///
+    /// При `Language::Auto` язык определяется по первому сегменту речи
+    /// и используется для остальных, чтобы запись не «прыгала» между языками.
+    pub async fn transcribe_speech(
+        &self,
+        audio_data: &[f32],
+        vad: &Vad,
+        options: &TranscribeOptions,
+    ) -> Result<Transcript, TranscribeError> {
+        let segments = vad.segments(audio_data);
+        if segments.is_empty() {
+            debug!("VAD: речь не обнаружена в {} сэмплах, транскрипция пропущена", audio_data.len());
+            return Ok(Transcript::default());
+        }
+
+        debug!("VAD: найдено сегментов речи: {}", segments.len());
+
+        let mut transcript = Transcript::default();
+        let mut options = options.clone();
+        for segment in segments {
+            let offset = segment.start as f64 / WHISPER_SAMPLE_RATE as f64;
+            let part = self.transcribe(&audio_data[segment], &options).await?;
+
+            if options.language == Language::Auto {
+                options.language = part.language.as_deref().and_then(Language::from_code).unwrap_or_default();
+                debug!("Определён язык: {:?} (вероятность {:?})", part.language, part.language_probability);
+            }
+            transcript.append(part, offset);
+        }
+
+        Ok(transcript)
     }
 }
 
-/// Конвертирует бинарные аудио данные из формата WebM/Opus в PCM 16kHz mono
-/// 
-/// # Внимание
-/// Это упрощённая реализация. Для production рекомендуется использовать
-/// специализированные библиотеки для декодирования аудио (symphonia, rodio, etc.)
-pub fn convert_audio_to_pcm(audio_data: &[u8]) -> Result<Vec<f32>, String> {
-    // Для упрощения предполагаем, что входные данные уже в нужном формате
-    // В реальной реализации здесь должна быть конвертация из WebM/Opus в PCM
-    
-    // Если данные пустые - возвращаем ошибку
-    if audio_data.is_empty() {
-        return Err("Пустые аудио данные".to_string());
+/// Определяет язык аудио среди поддерживаемых (`Language::DETECTABLE`)
+///
+/// Whisper оценивает вероятности всех своих языков по первым 30 секундам;
+/// выбирается наиболее вероятный из поддерживаемых, его вероятность
+/// возвращается как есть.
+fn detect_language(state: &mut WhisperState, samples: &[f32], n_threads: i32) -> Result<(Language, f32), WhisperError> {
+    let threads = n_threads.max(1) as usize;
+    state.pcm_to_mel(samples, threads)?;
+    let (_, probabilities) = state.lang_detect(0, threads)?;
+
+    let probability_of = |language: Language| {
+        language
+            .code()
+            .and_then(whisper_rs::get_lang_id)
+            .and_then(|id| probabilities.get(id as usize).copied())
+            .unwrap_or(0.0)
+    };
+
+    Ok(Language::DETECTABLE
+        .into_iter()
+        .map(|language| (language, probability_of(language)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or((Language::Ru, 0.0)))
+}
+
+/// Собирает сегменты и слова из состояния после `full`
+///
+/// Служебные токены (таймкоды, `[_BEG_]` и т.п.) имеют id не меньше EOT
+/// и в текст слов и среднюю вероятность не входят. Время whisper.cpp
+/// отдаёт в сотых долях секунды.
+fn collect_transcript(context: &WhisperContext, state: &WhisperState) -> Result<Transcript, WhisperError> {
+    let eot = context.token_eot();
+    let mut transcript = Transcript::default();
+
+    for segment in state.as_iter() {
+        let text = segment.to_str_lossy()?.trim().to_string();
+        let start = centis_to_secs(segment.start_timestamp());
+        let end = centis_to_secs(segment.end_timestamp());
+
+        let mut tokens = Vec::new();
+        let mut logprob_sum = 0.0f32;
+        for j in 0..segment.n_tokens() {
+            let Some(token) = segment.get_token(j) else {
+                continue;
+            };
+            let data = token.token_data();
+            if data.id >= eot {
+                continue;
+            }
+            logprob_sum += data.plog;
+            tokens.push(TokenInfo {
+                bytes: token.to_bytes()?.to_vec(),
+                start: centis_to_secs(data.t0),
+                end: centis_to_secs(data.t1),
+                probability: data.p,
+            });
+        }
+
+        let avg_logprob = if tokens.is_empty() { 0.0 } else { logprob_sum / tokens.len() as f32 };
+
+        transcript.segments.push(Segment {
+            start,
+            end,
+            text,
+            avg_logprob,
+            no_speech_prob: Some(segment.no_speech_probability()),
+            words: words_from_tokens(&tokens),
+        });
     }
-    
-    // Предполагаем, что данные уже в формате PCM 16-bit little-endian
-    // Конвертируем в f32 для Whisper
-    let mut pcm_data = Vec::with_capacity(audio_data.len() / 2);
-    
-    for chunk in audio_data.chunks_exact(2) {
-        let sample = i16::from_le_bytes([chunk[0], chunk[1]]);
-        pcm_data.push(sample as f32 / 32768.0);
+
+    Ok(transcript)
+}
+
+fn centis_to_secs(t: i64) -> f64 {
+    t as f64 / 100.0
+}
+
+/// Частота дискретизации, которую ожидает Whisper
+pub const WHISPER_SAMPLE_RATE: u32 = 16000;
+
+/// Конвертирует бинарные аудио данные в PCM 16kHz mono f32 для Whisper
+///
+/// Данные без заголовка считаются сырым PCM 16-bit 16kHz mono.
+/// Для других параметров сырого PCM используйте `convert_audio_to_pcm_with_spec`.
+pub fn convert_audio_to_pcm(audio_data: &[u8]) -> Result<Vec<f32>, AudioError> {
+    convert_audio_to_pcm_with_spec(audio_data, PcmSpec::default())
+}
+
+/// Конвертирует бинарные аудио данные в PCM 16kHz mono f32 для Whisper
+///
+/// Формат определяется по сигнатуре: WAV, WebM/Opus, Ogg (Vorbis/Opus), FLAC, MP3.
+/// Многоканальное аудио сводится в mono, затем ресемплируется в 16kHz.
+/// Частота берётся из заголовка контейнера, а для сырого PCM - из `raw_spec`.
+///
+/// # Возвращает
+/// * `Ok(Vec<f32>)` - сэмплы 16kHz mono в диапазоне [-1.0, 1.0]
+/// * `Err(AudioError)` - пустые данные, неподдерживаемый формат или кодек
+pub fn convert_audio_to_pcm_with_spec(audio_data: &[u8], raw_spec: PcmSpec) -> Result<Vec<f32>, AudioError> {
+    let decoded = audio::decode(audio_data, raw_spec)?;
+
+    debug!(
+        "Аудио декодировано: формат {:?}, {} сэмплов, {} Гц, каналов в источнике: {}",
+        decoded.format, decoded.samples.len(), decoded.sample_rate, decoded.source_channels
+    );
+
+    if decoded.sample_rate == WHISPER_SAMPLE_RATE {
+        return Ok(decoded.samples);
     }
-    
-    Ok(pcm_data)
+
+    let samples = resample::resample(&decoded.samples, decoded.sample_rate, WHISPER_SAMPLE_RATE);
+    debug!(
+        "Ресемплинг {} Гц -> {} Гц: {} -> {} сэмплов",
+        decoded.sample_rate, WHISPER_SAMPLE_RATE, decoded.samples.len(), samples.len()
+    );
+
+    Ok(samples)
 }
 
 #[cfg(test)]
@@ -220,6 +432,26 @@ mod tests {
         assert!((pcm[2] - (-1.0)).abs() < 0.01);
     }
 
+    #[test]
+    fn test_convert_audio_resamples_declared_rate() {
+        // 0.1 секунды тишины в 48kHz должны превратиться в 0.1 секунды 16kHz
+        let test_data = vec![0u8; 4800 * 2];
+        let spec = PcmSpec::from_client(Some(48000), None).unwrap();
+
+        let pcm = convert_audio_to_pcm_with_spec(&test_data, spec).unwrap();
+        assert_eq!(pcm.len(), 1600);
+    }
+
+    #[test]
+    fn test_language_codes() {
+        let auto: Language = serde_json::from_str("\"auto\"").unwrap();
+        assert_eq!(auto, Language::Auto);
+        assert_eq!(auto.code(), None);
+        assert_eq!(Language::default().code(), Some("ru"));
+        assert_eq!(Language::from_code("en"), Some(Language::En));
+        assert_eq!(Language::from_code("de"), None);
+    }
+
     #[test]
     fn test_convert_empty_audio() {
         let result = convert_audio_to_pcm(&[]);
@@ -3,26 +3,102 @@ use axum::{
         State,
         ws::{Message, WebSocket, WebSocketUpgrade},
     },
-    response::Response,
+    http::StatusCode,
+    response::{IntoResponse, Response},
 };
-use futures::{sink::SinkExt, stream::StreamExt};
+use futures::{sink::SinkExt, stream::{SplitSink, StreamExt}};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::sync::Arc;
-use tracing::{info, error, debug};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::{JoinError, JoinHandle};
+use tracing::{info, error, debug, warn};
 
 use crate::state::AppState;
-use crate::whisper;
-use crate::llm;
-
-#[cfg(feature = "nlp")]
-use crate::nlp::BertModel;
+use crate::store::Owners;
+use crate::jobs::{JobEvent, JobStatus};
+use crate::audio::{self, PcmSpec};
+use crate::format::{self, Format};
+use crate::config::EditOp;
+use crate::session::{SessionOptions, StreamSession};
+use crate::transcript::{Segment, Transcript};
+use crate::voice_commands::{Edits, Piece};
+use crate::formatting::AppContext;
+use crate::auth::Caller;
+use crate::whisper::{self, Language, Task, TranscribeOptions};
 
 /// Сообщение от клиента
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type")]
 enum ClientMessage {
     #[serde(rename = "audio")]
-    AudioData { data: String },
+    AudioData {
+        data: String,
+        /// Частота дискретизации сырого PCM (для контейнеров берётся из заголовка)
+        #[serde(default)]
+        sample_rate: Option<u32>,
+        /// Количество каналов сырого PCM
+        #[serde(default)]
+        channels: Option<u16>,
+        /// Вернуть сегменты с таймкодами слов и вероятностями
+        #[serde(default)]
+        details: bool,
+        /// Язык: "ru", "en" или "auto"
+        #[serde(default)]
+        language: Language,
+        /// Задача: "transcribe" или "translate" (перевод на английский)
+        #[serde(default)]
+        task: Task,
+        /// Пользователь: его словарь и сниппеты применяются к результату
+        #[serde(default)]
+        user: Option<String>,
+        /// Рабочее пространство со своим словарём и сниппетами
+        #[serde(default)]
+        workspace: Option<String>,
+    },
+    /// Начало потоковой сессии: дальнейшие аудио фрагменты копятся в буфере
+    #[serde(rename = "start")]
+    Start {
+        #[serde(default)]
+        sample_rate: Option<u32>,
+        #[serde(default)]
+        channels: Option<u16>,
+        /// Вернуть сегменты с таймкодами слов в `final`
+        #[serde(default)]
+        details: bool,
+        #[serde(default)]
+        language: Language,
+        #[serde(default)]
+        task: Task,
+        /// Пользователь: его словарь подсказывает Whisper термины
+        #[serde(default)]
+        user: Option<String>,
+        /// Рабочее пространство со своим словарём
+        #[serde(default)]
+        workspace: Option<String>,
+        /// Распознавать голосовые команды и присылать `edits` в `final`
+        #[serde(default)]
+        commands: bool,
+        /// Стиль переписывания финального текста (`formal`, `email`)
+        #[serde(default)]
+        style: Option<String>,
+        /// Куда вставляется текст: приложение, заголовок окна, тип поля
+        #[serde(default)]
+        context: AppContext,
+        /// Краткая форма `context.app`
+        #[serde(default)]
+        app: Option<String>,
+    },
+    /// Конец потоковой сессии: сервер отвечает финальным результатом
+    #[serde(rename = "stop")]
+    Stop {
+        /// Прислать всю сессию в формате экспорта после `final`
+        #[serde(default)]
+        format: Option<Format>,
+    },
+    /// Подписка на прогресс задания из `POST /v1/jobs`
+    #[serde(rename = "subscribe")]
+    Subscribe { job_id: String },
     #[serde(rename = "ping")]
     Ping,
 }
@@ -32,265 +108,350 @@ enum ClientMessage {
 #[serde(tag = "type")]
 enum ServerMessage {
     #[serde(rename = "transcription")]
-    Transcription { text: String },
+    Transcription {
+        text: String,
+        /// Язык распознавания
+        #[serde(skip_serializing_if = "Option::is_none")]
+        language: Option<String>,
+        /// Вероятность языка при `language: "auto"`
+        #[serde(skip_serializing_if = "Option::is_none")]
+        language_probability: Option<f32>,
+        /// Сегменты с таймкодами, если клиент запросил `details`
+        #[serde(skip_serializing_if = "Option::is_none")]
+        segments: Option<Vec<Segment>>,
+    },
+    #[serde(rename = "session_started")]
+    SessionStarted { session_id: String },
+    /// Промежуточный результат, может измениться с приходом нового аудио
+    #[serde(rename = "partial")]
+    Partial { session_id: String, text: String },
+    /// Стабильный результат завершённого высказывания
+    #[serde(rename = "final")]
+    Final {
+        session_id: String,
+        text: String,
+        audio_ms: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        language: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        language_probability: Option<f32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        segments: Option<Vec<Segment>>,
+        /// Вставки текста и команды редактирования по порядку, если в
+        /// высказывании были голосовые команды
+        #[serde(skip_serializing_if = "Option::is_none")]
+        edits: Option<Vec<EditOp>>,
+    },
+    /// Вся сессия в формате, запрошенном в `stop`
+    #[serde(rename = "export")]
+    Export {
+        session_id: String,
+        format: Format,
+        content: String,
+    },
+    /// Состояние задания, на которое подписан клиент
+    #[serde(rename = "job")]
+    Job {
+        job_id: String,
+        status: JobStatus,
+        progress: f32,
+        /// Итоговый текст, когда задание выполнено
+        #[serde(skip_serializing_if = "Option::is_none")]
+        text: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
     #[serde(rename = "pong")]
     Pong,
     #[serde(rename = "error")]
     Error { message: String },
 }
 
+type WsSender = SplitSink<WebSocket, Message>;
+
+impl From<JobEvent> for ServerMessage {
+    fn from(event: JobEvent) -> Self {
+        ServerMessage::Job {
+            job_id: event.id,
+            status: event.status,
+            progress: event.progress,
+            text: event.text,
+            error: event.error,
+        }
+    }
+}
+
+/// Подписки соединения на прогресс заданий
+#[derive(Default)]
+struct JobSubscriptions {
+    ids: HashSet<String>,
+    events: Option<broadcast::Receiver<JobEvent>>,
+}
+
+impl JobSubscriptions {
+    /// Подписывает на задание и возвращает его текущее состояние
+    fn subscribe(&mut self, state: &AppState, caller: &Caller, job_id: String) -> ServerMessage {
+        let Some(jobs) = state.jobs.as_ref() else {
+            return ServerMessage::Error {
+                message: "Очередь заданий недоступна".to_string(),
+            };
+        };
+
+        // Подписываемся до чтения состояния, чтобы не пропустить изменения
+        if self.events.is_none() {
+            self.events = Some(jobs.subscribe());
+        }
+        let Some(job) = jobs.get(&job_id).filter(|job| caller.can_access(job.user.as_deref())) else {
+            self.release_if_idle();
+            return ServerMessage::Error {
+                message: format!("Задание {} не найдено", job_id),
+            };
+        };
+
+        if job.status.is_finished() {
+            self.release_if_idle();
+        } else {
+            self.ids.insert(job_id);
+        }
+        JobEvent::from(&job).into()
+    }
+
+    /// Отписывается от событий, если не осталось заданий
+    fn release_if_idle(&mut self) {
+        if self.ids.is_empty() {
+            self.events = None;
+        }
+    }
+
+    /// Ждёт изменения подписанных заданий; без подписок не завершается
+    async fn next_message(&mut self) -> Option<ServerMessage> {
+        let Some(events) = self.events.as_mut() else {
+            return std::future::pending().await;
+        };
+
+        loop {
+            match events.recv().await {
+                Ok(event) if self.ids.contains(&event.id) => {
+                    if event.status.is_finished() {
+                        self.ids.remove(&event.id);
+                        self.release_if_idle();
+                    }
+                    return Some(event.into());
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(skipped)) => {
+                    debug!("Пропущено событий заданий: {}", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => {
+                    self.events = None;
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// Промежуточное распознавание, выполняющееся параллельно с приёмом аудио
+///
+/// Привязано к высказыванию сессии: после финального результата или `stop`
+/// результат устаревает и не отправляется, а удаление задачи её отменяет.
+struct PartialTask {
+    session_id: String,
+    utterance: u64,
+    handle: JoinHandle<Result<String, String>>,
+}
+
+impl PartialTask {
+    /// Запускает распознавание конца текущего высказывания
+    fn spawn(state: &Arc<AppState>, session: &mut StreamSession) -> Self {
+        let pcm = session.start_partial();
+        let options = session.options().transcribe.clone();
+        let state = state.clone();
+        let handle = tokio::spawn(async move {
+            transcribe_pcm(&state, &pcm, &options)
+                .await
+                .map(|transcript| transcript.text())
+        });
+
+        Self {
+            session_id: session.id().to_string(),
+            utterance: session.utterance(),
+            handle,
+        }
+    }
+
+    /// Относится ли задача к текущему высказыванию сессии
+    fn is_current(&self, session: Option<&StreamSession>) -> bool {
+        session.is_some_and(|s| s.id() == self.session_id && s.utterance() == self.utterance)
+    }
+}
+
+impl Drop for PartialTask {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Ждёт результат partial; без активной задачи не завершается
+async fn wait_partial(partial: &mut Option<PartialTask>) -> Result<Result<String, String>, JoinError> {
+    match partial.as_mut() {
+        Some(task) => (&mut task.handle).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Сообщение с результатом partial, если он актуален и текст изменился
+fn partial_message(
+    task: &PartialTask,
+    result: Result<Result<String, String>, JoinError>,
+    session: Option<&mut StreamSession>,
+) -> Option<ServerMessage> {
+    let session = session.filter(|s| task.is_current(Some(&**s)))?;
+    match result {
+        Ok(Ok(text)) if session.record_partial(&text) => Some(ServerMessage::Partial {
+            session_id: session.id().to_string(),
+            text,
+        }),
+        Ok(Ok(_)) => None,
+        Ok(Err(message)) => Some(ServerMessage::Error { message }),
+        Err(e) => {
+            error!("Задача partial сессии {} завершилась с ошибкой: {}", session.id(), e);
+            None
+        }
+    }
+}
+
 /// Обработчик WebSocket соединения
 pub async fn websocket_handler(
     ws: WebSocketUpgrade,
     State(state): State<Arc<AppState>>,
+    caller: Caller,
 ) -> Response {
-    ws.on_upgrade(move |socket| handle_socket(socket, state))
+    if state.client_count().await >= state.limits.max_connections {
+        warn!("Отклонено подключение: достигнут лимит {} соединений", state.limits.max_connections);
+        return (StatusCode::SERVICE_UNAVAILABLE, "Сервер занят: достигнут лимит соединений").into_response();
+    }
+    if let (Some(user), Some(limit)) = (caller.user(), state.limits.max_connections_per_user) {
+        if state.user_client_count(user).await >= limit {
+            warn!("Отклонено подключение {}: достигнут лимит {} соединений пользователя", user, limit);
+            return (StatusCode::TOO_MANY_REQUESTS, "Достигнут лимит соединений пользователя").into_response();
+        }
+    }
+
+    ws.max_message_size(state.limits.max_message_bytes)
+        .max_frame_size(state.limits.max_message_bytes)
+        .on_upgrade(move |socket| handle_socket(socket, state, caller))
 }
 
 /// Обработка WebSocket соединения
-async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
+async fn handle_socket(socket: WebSocket, state: Arc<AppState>, caller: Caller) {
     let (mut sender, mut receiver) = socket.split();
     let client_id = uuid::Uuid::new_v4();
 
     // Регистрируем клиента
-    state.add_client(client_id.to_string()).await;
-    
-    info!("New WebSocket client connected: {}", client_id);
+    state.add_client(client_id.to_string(), caller.user().map(str::to_string)).await;
+
+    match caller.user() {
+        Some(user) => info!("New WebSocket client connected: {} (user {})", client_id, user),
+        None => info!("New WebSocket client connected: {}", client_id),
+    }
 
     // Отправляем приветственное сообщение
-    let welcome_msg = ServerMessage::Transcription {
-        text: "Подключено к AlfaVoice Server".to_string(),
-    };
-    
-    if let Ok(msg_json) = serde_json::to_string(&welcome_msg) {
-        if let Err(e) = sender.send(Message::Text(msg_json)).await {
-            error!("Failed to send welcome message to client {}: {}", client_id, e);
-            return;
-        }
+    let welcome_msg = transcription("Подключено к AlfaVoice Server".to_string());
+
+    if let Err(e) = send_message(&mut sender, &welcome_msg).await {
+        error!("Failed to send welcome message to client {}: {}", client_id, e);
+        return;
     }
 
-    // Обрабатываем сообщения от клиента
-    while let Some(result) = receiver.next().await {
-        match result {
-            Ok(msg) => {
-                match msg {
-                    Message::Text(text) => {
-                        debug!("Received text message from {}: {}", client_id, text);
-                        
-                        // Парсим сообщение
-                        if let Ok(client_msg) = serde_json::from_str::<ClientMessage>(&text) {
-                            match client_msg {
-                                ClientMessage::Ping => {
-                                    // Отвечаем на ping
-                                    let pong_msg = ServerMessage::Pong;
-                                    if let Ok(msg_json) = serde_json::to_string(&pong_msg) {
-                                        let _ = sender.send(Message::Text(msg_json)).await;
-                                    }
-                                }
-                                ClientMessage::AudioData { data } => {
-                                    // Обрабатываем аудио данные
-                                    debug!("Received audio data from {}: {} bytes", client_id, data.len());
-                                    
-                                    // Пытаемся выполнить транскрипцию через Whisper
-                                    let transcription = match &state.whisper_model {
-                                        Some(model) => {
-                                            // Декодируем base64 аудио данные
-                                            match base64_decode(&data) {
-                                                Ok(audio_bytes) => {
-                                                    // Конвертируем в PCM формат
-                                                    match whisper::convert_audio_to_pcm(&audio_bytes) {
-                                                        Ok(pcm_data) => {
-                                                            // Выполняем транскрипцию
-                                                            match model.transcribe(&pcm_data).await {
-                                                                Ok(text) => {
-                                                                    // Постобработка через BERT (если доступен) или LLM
-                                                                    #[cfg(feature = "nlp")]
-                                                                    let processed_text = if let Some(bert_model) = &state.bert_model {
-                                                                        if bert_model.is_ready() {
-                                                                            match bert_model.process_text(&text) {
-                                                                                Ok(result) => {
-                                                    debug!("BERT постобработка за {}ms (GPU: {})",
-                                                        result.processing_time_ms, result.used_gpu);
-                                                    result.text
-                                                }
-                                                Err(e) => {
-                                                    debug!("Ошибка BERT постобработки: {}, используем LLM", e);
-                                                    // Fallback на LLM
-                                                    match state.llm_model.post_process(&text).await {
-                                                        Ok(llm_text) => llm_text,
-                                                        Err(_) => text
-                                                    }
-                                                }
-                                            }
-                                        } else {
-                                            // BERT не готов, используем LLM
-                                            match state.llm_model.post_process(&text).await {
-                                                Ok(llm_text) => llm_text,
-                                                Err(_) => text
-                                            }
-                                        }
-                                    } else {
-                                        // BERT недоступен, используем LLM
-                                        match state.llm_model.post_process(&text).await {
-                                            Ok(llm_text) => llm_text,
-                                            Err(_) => text
-                                        }
-                                    };
-
-                                                                    #[cfg(not(feature = "nlp"))]
-                                                                    let processed_text = match state.llm_model.post_process(&text).await {
-                                                                        Ok(llm_text) => llm_text,
-                                                                        Err(_) => text
-                                                                    };
-
-                                                                    processed_text
-                                                                }
-                                                                Err(e) => {
-                                                                    error!("Ошибка транскрипции: {}", e);
-                                                                    format!("Ошибка транскрипции: {}", e)
-                                                                }
-                                                            }
-                                                        }
-                                                        Err(e) => {
-                                                            error!("Ошибка конвертации аудио: {}", e);
-                                                            format!("Ошибка конвертации аудио: {}", e)
-                                                        }
-                                                    }
-                                                }
-                                                Err(e) => {
-                                                    error!("Ошибка декодирования base64: {}", e);
-                                                    format!("Ошибка декодирования аудио: {}", e)
-                                                }
-                                            }
-                                        }
-                                        None => {
-                                            // Whisper модель не загружена - используем заглушку
-                                            let stub_text = process_audio_stub(&data).await;
-                                            // Постобработка заглушки через LLM
-                                            match state.llm_model.post_process(&stub_text).await {
-                                                Ok(processed_text) => processed_text,
-                                                Err(e) => {
-                                                    debug!("Ошибка постобработки LLM: {}", e);
-                                                    stub_text
-                                                }
-                                            }
-                                        }
-                                    };
-                                    
-                                    let response = ServerMessage::Transcription { text: transcription };
-                                    
-                                    if let Ok(msg_json) = serde_json::to_string(&response) {
-                                        if let Err(e) = sender.send(Message::Text(msg_json)).await {
-                                            error!("Failed to send transcription to client {}: {}", client_id, e);
-                                            break;
-                                        }
-                                    }
-                                }
-                            }
-                        } else {
-                            error!("Failed to parse message from client {}", client_id);
-                        }
+    // Активная потоковая сессия (если клиент прислал `start`)
+    let mut session: Option<StreamSession> = None;
+
+    // Подписки на прогресс заданий
+    let mut subscriptions = JobSubscriptions::default();
+
+    // Промежуточное распознавание текущего высказывания (не больше одного)
+    let mut partial: Option<PartialTask> = None;
+
+    // Обрабатываем сообщения от клиента и события подписанных заданий
+    loop {
+        let result = tokio::select! {
+            message = receiver.next() => match message {
+                Some(result) => result,
+                None => break,
+            },
+            Some(message) = subscriptions.next_message() => {
+                if let Err(e) = send_message(&mut sender, &message).await {
+                    error!("Failed to send message to client {}: {}", client_id, e);
+                    break;
+                }
+                continue;
+            }
+            result = wait_partial(&mut partial) => {
+                let message = partial.take().and_then(|task| partial_message(&task, result, session.as_mut()));
+                if let Some(message) = message {
+                    if let Err(e) = send_message(&mut sender, &message).await {
+                        error!("Failed to send message to client {}: {}", client_id, e);
+                        break;
                     }
-                    Message::Binary(data) => {
-                        debug!("Received binary data from {}: {} bytes", client_id, data.len());
-                        
-                        // Пытаемся выполнить транскрипцию через Whisper
-                        let transcription = match &state.whisper_model {
-                            Some(model) => {
-                                // Конвертируем бинарные данные в PCM формат
-                                match whisper::convert_audio_to_pcm(&data) {
-                                    Ok(pcm_data) => {
-                                        // Выполняем транскрипцию
-                                        match model.transcribe(&pcm_data).await {
-                                            Ok(text) => {
-                                                // Постобработка через BERT (если доступен) или LLM
-                                                #[cfg(feature = "nlp")]
-                                                let processed_text = if let Some(bert_model) = &state.bert_model {
-                                                    if bert_model.is_ready() {
-                                                        match bert_model.process_text(&text) {
-                                                            Ok(result) => {
-                                                                debug!("BERT постобработка за {}ms (GPU: {})",
-                                                                    result.processing_time_ms, result.used_gpu);
-                                                                result.text
-                                                            }
-                                                            Err(e) => {
-                                                                debug!("Ошибка BERT постобработки: {}, используем LLM", e);
-                                                                // Fallback на LLM
-                                                                match state.llm_model.post_process(&text).await {
-                                                                    Ok(llm_text) => llm_text,
-                                                                    Err(_) => text
-                                                                }
-                                                            }
-                                                        }
-                                                    } else {
-                                                        // BERT не готов, используем LLM
-                                                        match state.llm_model.post_process(&text).await {
-                                                            Ok(llm_text) => llm_text,
-                                                            Err(_) => text
-                                                        }
-                                                    }
-                                                } else {
-                                                    // BERT недоступен, используем LLM
-                                                    match state.llm_model.post_process(&text).await {
-                                                        Ok(llm_text) => llm_text,
-                                                        Err(_) => text
-                                                    }
-                                                };
-
-                                                #[cfg(not(feature = "nlp"))]
-                                                let processed_text = match state.llm_model.post_process(&text).await {
-                                                    Ok(llm_text) => llm_text,
-                                                    Err(_) => text
-                                                };
-
-                                                processed_text
-                                            }
-                                            Err(e) => {
-                                                error!("Ошибка транскрипции: {}", e);
-                                                format!("Ошибка транскрипции: {}", e)
-                                            }
-                                        }
-                                    }
-                                    Err(e) => {
-                                        error!("Ошибка конвертации аудио: {}", e);
-                                        format!("Ошибка конвертации аудио: {}", e)
-                                    }
-                                }
-                            }
-                            None => {
-                                // Whisper модель не загружена - используем заглушку
-                                let stub_text = process_audio_binary_stub(&data).await;
-                                // Постобработка заглушки через LLM
-                                match state.llm_model.post_process(&stub_text).await {
-                                    Ok(processed_text) => processed_text,
-                                    Err(e) => {
-                                        debug!("Ошибка постобработки LLM: {}", e);
-                                        stub_text
-                                    }
-                                }
-                            }
-                        };
-                        
-                        let response = ServerMessage::Transcription { text: transcription };
-                        
-                        if let Ok(msg_json) = serde_json::to_string(&response) {
-                            if let Err(e) = sender.send(Message::Text(msg_json)).await {
-                                error!("Failed to send transcription to client {}: {}", client_id, e);
-                                break;
-                            }
-                        }
+                }
+                continue;
+            }
+        };
+
+        let responses = match result {
+            Ok(Message::Text(text)) => {
+                debug!("Received text message from {}: {}", client_id, text);
+
+                match serde_json::from_str::<ClientMessage>(&text) {
+                    Ok(client_msg) => {
+                        handle_client_message(&state, &caller, &mut session, &mut subscriptions, client_msg).await
                     }
-                    Message::Close(_) => {
-                        info!("Client {} disconnected", client_id);
-                        break;
+                    Err(e) => {
+                        error!("Failed to parse message from client {}: {}", client_id, e);
+                        continue;
                     }
-                    _ => {}
                 }
             }
+            Ok(Message::Binary(data)) => {
+                debug!("Received binary data from {}: {} bytes", client_id, data.len());
+
+                match session.as_mut() {
+                    Some(active) => handle_session_audio(&state, active, &data).await,
+                    None => vec![transcribe_binary(&state, &caller, &data).await],
+                }
+            }
+            Ok(Message::Close(_)) => {
+                info!("Client {} disconnected", client_id);
+                break;
+            }
+            Ok(_) => continue,
             Err(e) => {
                 error!("WebSocket error for client {}: {}", client_id, e);
                 break;
             }
+        };
+
+        // Partial завершённого высказывания отменяем, новый запускаем,
+        // только когда предыдущий готов
+        if partial.as_ref().is_some_and(|task| !task.is_current(session.as_ref())) {
+            partial = None;
+        }
+        if let Some(active) = session.as_mut().filter(|s| partial.is_none() && s.partial_due()) {
+            partial = Some(PartialTask::spawn(&state, active));
+        }
+
+        let mut send_failed = false;
+        for response in &responses {
+            if let Err(e) = send_message(&mut sender, response).await {
+                error!("Failed to send message to client {}: {}", client_id, e);
+                send_failed = true;
+                break;
+            }
+        }
+        if send_failed {
+            break;
         }
     }
 
@@ -299,10 +460,379 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
     info!("WebSocket connection closed for client {}", client_id);
 }
 
+/// Обрабатывает JSON сообщение клиента и возвращает ответы
+async fn handle_client_message(
+    state: &AppState,
+    caller: &Caller,
+    session: &mut Option<StreamSession>,
+    subscriptions: &mut JobSubscriptions,
+    client_msg: ClientMessage,
+) -> Vec<ServerMessage> {
+    match client_msg {
+        ClientMessage::Ping => vec![ServerMessage::Pong],
+        ClientMessage::Subscribe { job_id } => vec![subscriptions.subscribe(state, caller, job_id)],
+        ClientMessage::Start {
+            sample_rate,
+            channels,
+            details,
+            language,
+            task,
+            user,
+            workspace,
+            commands,
+            style,
+            mut context,
+            app,
+        } => {
+            let spec = match PcmSpec::from_client(sample_rate, channels) {
+                Ok(spec) => spec,
+                Err(e) => return vec![ServerMessage::Error { message: e.to_string() }],
+            };
+            let mut postprocess = state.postprocess_context(&caller.owners(Owners { user, workspace }));
+            context.app = context.app.or(app);
+            if let Err(e) = state.apply_target(&mut postprocess, style.as_deref(), &context) {
+                return vec![ServerMessage::Error { message: e.to_string() }];
+            }
+            debug!("Контекст приложения {:?}: {:?}", context, postprocess.formatting);
+
+            let mut responses = Vec::new();
+
+            // Повторный `start` без `stop` завершает предыдущую сессию
+            if let Some(mut previous) = session.take() {
+                responses.extend(finish_session(state, &mut previous).await);
+            }
+
+            let options = SessionOptions {
+                details,
+                transcribe: TranscribeOptions {
+                    language,
+                    task,
+                    prompt: postprocess.prompt(None),
+                    ..TranscribeOptions::default()
+                },
+                postprocess,
+                commands,
+            };
+            let new_session = StreamSession::new(spec, options, state.streaming_config.clone());
+            info!("Начата потоковая сессия {}", new_session.id());
+            responses.push(ServerMessage::SessionStarted {
+                session_id: new_session.id().to_string(),
+            });
+            *session = Some(new_session);
+            responses
+        }
+        ClientMessage::Stop { format } => match session.take() {
+            Some(mut active) => {
+                info!("Завершена потоковая сессия {} ({} мс)", active.id(), active.elapsed_ms());
+                let mut responses = finish_session(state, &mut active).await;
+                if let Some(format) = format {
+                    let (transcript, text) = active.transcript();
+                    responses.push(ServerMessage::Export {
+                        session_id: active.id().to_string(),
+                        format,
+                        content: format::render(format, transcript, &text),
+                    });
+                }
+                responses
+            }
+            None => vec![ServerMessage::Error {
+                message: "Потоковая сессия не начата: отправьте сообщение start".to_string(),
+            }],
+        },
+        ClientMessage::AudioData {
+            data,
+            sample_rate,
+            channels,
+            details,
+            language,
+            task,
+            user,
+            workspace,
+        } => {
+            debug!("Received audio data: {} bytes", data.len());
+
+            if let Some(active) = session.as_mut() {
+                return match base64_decode(&data) {
+                    Ok(audio_bytes) => handle_session_audio(state, active, &audio_bytes).await,
+                    Err(e) => vec![ServerMessage::Error { message: e }],
+                };
+            }
+
+            let owners = caller.owners(Owners { user, workspace });
+            let options = single_shot_options(state, &owners, details, language, task);
+            vec![transcribe_base64(state, &data, sample_rate, channels, &options).await]
+        }
+    }
+}
+
+/// Добавляет фрагмент в сессию и при необходимости считает final
+async fn handle_session_audio(
+    state: &AppState,
+    session: &mut StreamSession,
+    chunk: &[u8],
+) -> Vec<ServerMessage> {
+    if let Err(e) = session.push_chunk(chunk).await {
+        error!("Ошибка декодирования фрагмента сессии {}: {}", session.id(), e);
+        return vec![ServerMessage::Error { message: e.to_string() }];
+    }
+
+    // Буфер переполнен - фиксируем накопленное как финальный результат
+    if session.buffer_full() {
+        return finish_or_discard(state, session).await;
+    }
+
+    // После речи наступила пауза - высказывание завершено
+    if session.utterance_complete(&state.vad) {
+        return vec![finish_utterance(state, session).await];
+    }
+
+    Vec::new()
+}
+
+/// Завершает переполненный буфер: с речью - финальным результатом,
+/// без речи - молча сбрасывает, чтобы не слать клиенту пустые `final`
+async fn finish_or_discard(state: &AppState, session: &mut StreamSession) -> Vec<ServerMessage> {
+    if state.vad.has_speech(session.pcm()) {
+        vec![finish_utterance(state, session).await]
+    } else {
+        debug!("Буфер сессии {} заполнен тишиной, сброшен", session.id());
+        session.reset();
+        Vec::new()
+    }
+}
+
+/// Завершает сессию: декодирует остаток потока и распознаёт последнее высказывание
+async fn finish_session(state: &AppState, session: &mut StreamSession) -> Vec<ServerMessage> {
+    let mut responses = Vec::new();
+    if let Err(e) = session.finish().await {
+        error!("Ошибка декодирования конца сессии {}: {}", session.id(), e);
+        responses.push(ServerMessage::Error { message: e.to_string() });
+    }
+    responses.push(finish_utterance(state, session).await);
+    responses
+}
+
+/// Распознаёт накопленный буфер сессии, выполняет постобработку и сбрасывает буфер
+async fn finish_utterance(state: &AppState, session: &mut StreamSession) -> ServerMessage {
+    let pcm = session.pcm().to_vec();
+    let audio_ms = pcm.len() as u64 * 1000 / whisper::WHISPER_SAMPLE_RATE as u64;
+    session.reset();
+
+    let transcript = if pcm.is_empty() {
+        Transcript::default()
+    } else {
+        match transcribe_pcm(state, &pcm, &session.options().transcribe).await {
+            Ok(transcript) => transcript,
+            Err(e) => {
+                // Аудио потеряно, но таймкоды следующих высказываний должны остаться верными
+                session.record_final(Transcript::default(), "", audio_ms as f64 / 1000.0);
+                return ServerMessage::Error { message: e };
+            }
+        }
+    };
+    let (text, edits) = if transcript.is_empty() {
+        (String::new(), None)
+    } else {
+        post_process_final(state, session.options(), &transcript).await
+    };
+    session.record_final(transcript.clone(), &text, audio_ms as f64 / 1000.0);
+
+    ServerMessage::Final {
+        session_id: session.id().to_string(),
+        text,
+        audio_ms,
+        language: transcript.language,
+        language_probability: transcript.language_probability,
+        segments: session.options().details.then_some(transcript.segments),
+        edits,
+    }
+}
+
+/// Постобработка финального результата сессии
+///
+/// С голосовыми командами постобрабатывается только диктуемый текст между
+/// ними, а команды передаются клиенту операциями `edits`.
+async fn post_process_final(
+    state: &AppState,
+    options: &SessionOptions,
+    transcript: &Transcript,
+) -> (String, Option<Vec<EditOp>>) {
+    let context = options.postprocess_context(transcript.language.clone());
+    let text = transcript.text();
+    let pieces = match &state.voice_commands {
+        Some(grammar) if options.commands => grammar.interpret(&text),
+        _ => None,
+    };
+    let Some(pieces) = pieces else {
+        return (state.post_process_detailed(text, &context).await.text, None);
+    };
+
+    let mut edits = Edits::default();
+    for piece in pieces {
+        match piece {
+            Piece::Text(text) => edits.push_text(&state.post_process_detailed(text, &context).await.text),
+            Piece::Command(op) => {
+                debug!("Голосовая команда: {:?}", op);
+                edits.push_command(op);
+            }
+        }
+    }
+    let (text, ops) = edits.finish();
+    (text, Some(ops))
+}
+
+/// Распознаёт участки речи в PCM 16kHz через Whisper
+async fn transcribe_pcm(
+    state: &AppState,
+    pcm: &[f32],
+    options: &TranscribeOptions,
+) -> Result<Transcript, String> {
+    match &state.whisper_model {
+        Some(model) => model.transcribe_speech(pcm, &state.vad, options).await.map_err(|e| {
+            error!("Ошибка транскрипции: {}", e);
+            format!("Ошибка транскрипции: {}", e)
+        }),
+        None => Err("Whisper модель не загружена".to_string()),
+    }
+}
+
+/// Параметры одиночной транскрипции вне сессии
+///
+/// Словарь владельцев подсказывает Whisper термины и исправляет текст,
+/// а сниппеты раскрываются, как в сессии и HTTP API.
+fn single_shot_options(
+    state: &AppState,
+    owners: &Owners,
+    details: bool,
+    language: Language,
+    task: Task,
+) -> SessionOptions {
+    let postprocess = state.postprocess_context(owners);
+    SessionOptions {
+        details,
+        transcribe: TranscribeOptions {
+            language,
+            task,
+            prompt: postprocess.prompt(None),
+            ..TranscribeOptions::default()
+        },
+        postprocess,
+        commands: false,
+    }
+}
+
+/// Одиночная транскрипция аудио из JSON сообщения (base64)
+async fn transcribe_base64(
+    state: &AppState,
+    data: &str,
+    sample_rate: Option<u32>,
+    channels: Option<u16>,
+    options: &SessionOptions,
+) -> ServerMessage {
+    if state.whisper_model.is_none() {
+        // Whisper модель не загружена - используем заглушку
+        let stub_text = process_audio_stub(data).await;
+        return transcription(state.post_process_detailed(stub_text, &options.postprocess).await.text);
+    }
+
+    // Декодируем base64 аудио данные
+    let audio_bytes = match base64_decode(data) {
+        Ok(audio_bytes) => audio_bytes,
+        Err(e) => {
+            error!("Ошибка декодирования base64: {}", e);
+            return transcription(format!("Ошибка декодирования аудио: {}", e));
+        }
+    };
+
+    // Конвертируем в PCM 16kHz mono с учётом параметров клиента
+    let pcm_result = audio::PcmSpec::from_client(sample_rate, channels)
+        .and_then(|spec| whisper::convert_audio_to_pcm_with_spec(&audio_bytes, spec));
+
+    transcribe_decoded(state, pcm_result, options).await
+}
+
+/// Одиночная транскрипция бинарного сообщения
+async fn transcribe_binary(state: &AppState, caller: &Caller, data: &[u8]) -> ServerMessage {
+    let options = single_shot_options(
+        state,
+        &caller.owners(Owners::default()),
+        false,
+        Language::default(),
+        Task::default(),
+    );
+    if state.whisper_model.is_none() {
+        // Whisper модель не загружена - используем заглушку
+        let stub_text = process_audio_binary_stub(data).await;
+        return transcription(state.post_process_detailed(stub_text, &options.postprocess).await.text);
+    }
+
+    transcribe_decoded(state, whisper::convert_audio_to_pcm(data), &options).await
+}
+
+/// Распознаёт декодированное аудио и выполняет постобработку
+///
+/// Ошибки возвращаются текстом транскрипции, как и раньше в одиночном режиме.
+async fn transcribe_decoded(
+    state: &AppState,
+    pcm_result: Result<Vec<f32>, audio::AudioError>,
+    options: &SessionOptions,
+) -> ServerMessage {
+    let pcm_data = match pcm_result {
+        Ok(pcm_data) => pcm_data,
+        Err(e) => {
+            error!("Ошибка конвертации аудио: {}", e);
+            return transcription(format!("Ошибка конвертации аудио: {}", e));
+        }
+    };
+
+    let max_samples = state.limits.max_audio_secs as usize * whisper::WHISPER_SAMPLE_RATE as usize;
+    if pcm_data.len() > max_samples {
+        return transcription(format!(
+            "Аудио слишком длинное: {} сек, максимум {} сек",
+            pcm_data.len() / whisper::WHISPER_SAMPLE_RATE as usize,
+            state.limits.max_audio_secs
+        ));
+    }
+
+    match transcribe_pcm(state, &pcm_data, &options.transcribe).await {
+        Ok(transcript) => ServerMessage::Transcription {
+            text: state
+                .post_process_detailed(transcript.text(), &options.postprocess_context(transcript.language.clone()))
+                .await
+                .text,
+            language: transcript.language,
+            language_probability: transcript.language_probability,
+            segments: options.details.then_some(transcript.segments),
+        },
+        Err(e) => transcription(e),
+    }
+}
+
+/// Сообщение `transcription` без деталей
+fn transcription(text: String) -> ServerMessage {
+    ServerMessage::Transcription {
+        text,
+        language: None,
+        language_probability: None,
+        segments: None,
+    }
+}
+
+/// Сериализует и отправляет сообщение клиенту
+async fn send_message(sender: &mut WsSender, message: &ServerMessage) -> Result<(), axum::Error> {
+    match serde_json::to_string(message) {
+        Ok(msg_json) => sender.send(Message::Text(msg_json)).await,
+        Err(e) => {
+            error!("Failed to serialize server message: {}", e);
+            Ok(())
+        }
+    }
+}
+
 /// Декодирует base64 строку в байты
 fn base64_decode(data: &str) -> Result<Vec<u8>, String> {
     use base64::{Engine as _, engine::general_purpose};
-    
+
     general_purpose::STANDARD
         .decode(data)
         .map_err(|e| format!("Ошибка декодирования base64: {}", e))
@@ -312,7 +842,7 @@ fn base64_decode(data: &str) -> Result<Vec<u8>, String> {
 async fn process_audio_stub(data: &str) -> String {
     // В реальной реализации здесь будет вызов модели транскрипции
     // Для демонстрации возвращаем mock-ответ
-    
+
     let data_len = data.len();
     match data_len {
         0..=100 => "Короткий аудио фрагмент...".to_string(),
@@ -324,7 +854,7 @@ async fn process_audio_stub(data: &str) -> String {
 /// Заглушка для обработки бинарных аудио данных
 async fn process_audio_binary_stub(data: &[u8]) -> String {
     // В реальной реализации здесь будет декодирование аудио и вызов модели транскрипции
-    
+
     let data_len = data.len();
     match data_len {
         0..=1000 => "Короткий аудио фрагмент (бинарный)...".to_string(),